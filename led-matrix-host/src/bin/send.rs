@@ -0,0 +1,140 @@
+//! Send images to the LED matrix through its serial port, or to the simulator
+//! through a pseudo-terminal. Every source is a PNG or PPM picture, scaled down
+//! to 8x8 pixels, or a solid colour.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use led_matrix_host::encoder::{encode, encode_image};
+use led_matrix_host::{images, serial};
+use tp_led_matrix::calibration::MAX_DOT_CORRECTION;
+use tp_led_matrix::command::{Command, Mode};
+use tp_led_matrix::gamma::{Curve, Gamma};
+use tp_led_matrix::{Color, Image};
+
+#[derive(Parser)]
+#[clap(version, about)]
+struct Args {
+    /// Serial device of the board, or pseudo-terminal of the simulator
+    #[clap(short, long)]
+    device: PathBuf,
+    /// Send raw images after 0xff bytes instead of commands
+    #[clap(long)]
+    legacy: bool,
+    /// Number of images sent per second, as fast as possible by default (at 38400
+    /// bauds, about 19 images per second)
+    #[clap(long)]
+    fps: Option<f64>,
+    /// Number of times the images are sent
+    #[clap(long, default_value = "1", conflicts_with = "forever")]
+    repeat: usize,
+    /// Send the images until interrupted
+    #[clap(long = "loop")]
+    forever: bool,
+    /// Set the brightness of the matrix, from 0 to 255, before sending the images
+    #[clap(long)]
+    brightness: Option<u8>,
+    /// Set the coefficients of the red, green and blue components, given as a colour
+    /// (such as #ffc0a0), before sending the images
+    #[clap(long, parse(try_from_str = parse_color))]
+    white_balance: Option<Color>,
+    /// Set the dot correction of the red, green and blue LEDs, from 0 to 63 each
+    /// (such as 63,40,50), before sending the images
+    #[clap(long, parse(try_from_str = parse_dot_correction))]
+    dot_correction: Option<Color>,
+    /// Set the gamma correction curve (linear, 1.8, 2.2, 2.8 or srgb), or the curves of
+    /// the red, green and blue components (such as 2.2,2.2,2.8), before sending the images
+    #[clap(long, parse(try_from_str = parse_gamma))]
+    gamma: Option<Gamma>,
+    /// Pictures (PNG or PPM) or colours (such as red or #ff8000)
+    #[clap(required = true)]
+    sources: Vec<String>,
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    images::parse_color(s).ok_or_else(|| format!("invalid colour {}", s))
+}
+
+fn parse_dot_correction(s: &str) -> Result<Color, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse().ok().filter(|&v| v <= MAX_DOT_CORRECTION))
+        .collect::<Option<Vec<u8>>>();
+    match values.as_deref() {
+        Some(&[r, g, b]) => Ok(Color { r, g, b }),
+        _ => Err(format!("expected three values from 0 to 63, got {}", s)),
+    }
+}
+
+fn parse_gamma(s: &str) -> Result<Gamma, String> {
+    let curve = |name: &str| match name.trim() {
+        "linear" => Some(Curve::Linear),
+        "1.8" => Some(Curve::Gamma18),
+        "2.2" => Some(Curve::Gamma22),
+        "2.8" => Some(Curve::Gamma28),
+        "srgb" => Some(Curve::Srgb),
+        _ => None,
+    };
+    let curves = s.split(',').map(curve).collect::<Option<Vec<_>>>();
+    match curves.as_deref() {
+        Some(&[curve]) => Ok(Gamma::uniform(curve)),
+        Some(&[r, g, b]) => Ok(Gamma { r, g, b }),
+        _ => Err(format!("expected one or three curves among linear, 1.8, 2.2, 2.8 and srgb, got {}", s)),
+    }
+}
+
+/// Load a source, either a colour or a picture.
+fn load(source: &str) -> io::Result<Image> {
+    match images::parse_color(source) {
+        Some(color) => Ok(Image::new_solid(color)),
+        None => images::load(Path::new(source)).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", source, e))),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let mode = if args.legacy { Mode::Legacy } else { Mode::Commands };
+    if let Some(fps) = args.fps.filter(|&fps| !(fps > 0.0 && fps.is_finite())) {
+        eprintln!("error: invalid frame rate {}", fps);
+        std::process::exit(2);
+    }
+    let settings = [
+        args.brightness.map(Command::SetBrightness),
+        args.white_balance.map(Command::SetWhiteBalance),
+        args.dot_correction.map(Command::SetDotCorrection),
+        args.gamma.map(Command::SetGamma),
+    ];
+    if args.legacy && settings.iter().any(Option::is_some) {
+        eprintln!("error: the legacy protocol only sends images");
+        std::process::exit(2);
+    }
+    let frames = args
+        .sources
+        .iter()
+        .map(|source| load(source).map(|image| encode_image(&image, mode)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut device = serial::open(&args.device)?;
+    for command in settings.iter().flatten() {
+        device.write_all(&encode(command))?;
+    }
+    let period = args.fps.map(|fps| Duration::from_secs_f64(1.0 / fps));
+    let mut next = Instant::now();
+    let mut round = 0;
+    while args.forever || round < args.repeat {
+        for frame in &frames {
+            if let Some(period) = period {
+                if let Some(delay) = next.checked_duration_since(Instant::now()) {
+                    thread::sleep(delay);
+                }
+                next += period;
+            }
+            device.write_all(frame)?;
+            device.flush()?;
+        }
+        round += 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! Display in the terminal the images sent to the LED matrix, using the same
+//! serial protocol as the board (see the command module of the library). The
+//! images go through the matrix driver and a simulated DM163, so the colours
+//! shown are those latched by the board, gamma correction included.
+
+use std::cell::RefCell;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpListener;
+
+use clap::Parser;
+use led_matrix_host::encoder::encode;
+use led_matrix_host::{pty::Pty, render};
+use tp_led_matrix::command::{Command, CommandDecoder, Mode, Status};
+use tp_led_matrix::sim::{self, Dm163};
+use tp_led_matrix::framebuffer::FrameBuffers;
+use tp_led_matrix::Calibration;
+
+#[derive(Parser)]
+#[clap(version, about)]
+struct Args {
+    /// Listen for connections on this address (such as 127.0.0.1:7878) instead of reading stdin
+    #[clap(long, conflicts_with = "pty")]
+    tcp: Option<String>,
+    /// Read from a new pseudo-terminal, whose path is printed on startup, instead of stdin
+    #[clap(long)]
+    pty: bool,
+    /// Receive raw images after 0xff bytes instead of commands
+    #[clap(long)]
+    legacy: bool,
+}
+
+/// Execute the commands received from `input` until its end, writing the
+/// replies to `output`.
+fn serve(input: impl Read, mut output: impl Write, mode: Mode) -> io::Result<()> {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    let mut decoder = CommandDecoder::new(mode);
+    let mut frames = FrameBuffers::default();
+    let mut calibration = Calibration::default();
+    let mut status = Status::default();
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[2J")?;
+    for b in BufReader::new(input).bytes() {
+        let command = match decoder.push(b?) {
+            None => continue,
+            Some(Ok(command)) => command,
+            Some(Err(e)) => {
+                eprintln!("invalid command: {}", e);
+                status.errors = status.errors.wrapping_add(1);
+                continue;
+            }
+        };
+        if let Some(reply) = command.execute(&mut frames, &mut calibration, &mut status) {
+            output.write_all(&encode(&Command::Status(reply)))?;
+            output.flush()?;
+            continue;
+        }
+        // Animations are not simulated, they only show up in the status: the image
+        // published last is displayed with the current calibration
+        matrix.set_calibration(calibration);
+        for row in 0..8 {
+            matrix.send_row(row, frames.row(row));
+        }
+        write!(stdout, "\x1b[H{}", render(&dm163.borrow().image()))?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let mode = if args.legacy { Mode::Legacy } else { Mode::Commands };
+    if let Some(addr) = args.tcp {
+        let listener = TcpListener::bind(addr)?;
+        eprintln!("listening on {}", listener.local_addr()?);
+        // A failing connection is only reported, the next ones are still accepted
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| serve(stream.try_clone()?, stream, mode));
+            if let Err(e) = result {
+                eprintln!("connection error: {}", e);
+            }
+        }
+        Ok(())
+    } else if args.pty {
+        let pty = Pty::open()?;
+        eprintln!("reading from {}", pty.path.display());
+        serve(pty.master.try_clone()?, pty.master, mode)
+    } else {
+        serve(io::stdin().lock(), io::sink(), mode)
+    }
+}
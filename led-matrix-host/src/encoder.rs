@@ -0,0 +1,37 @@
+//! Encoding of the commands sent to the board, in the command protocol or in the
+//! legacy one.
+
+use tp_led_matrix::command::{Command, CommandDecoder, Mode, Status};
+use tp_led_matrix::Image;
+
+/// Encode a command.
+pub fn encode(command: &Command) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    command.encode(|b| bytes.push(b));
+    bytes
+}
+
+/// Encode an image in the legacy protocol. Since 0xff starts an image, the
+/// components equal to 0xff are sent as 0xfe.
+pub fn encode_legacy(image: &Image) -> Vec<u8> {
+    let mut bytes = vec![0xff];
+    bytes.extend(image.as_ref().iter().map(|&b| b.min(0xfe)));
+    bytes
+}
+
+/// Encode an image for the given protocol.
+pub fn encode_image(image: &Image, mode: Mode) -> Vec<u8> {
+    match mode {
+        Mode::Commands => encode(&Command::SetImage(image)),
+        Mode::Legacy => encode_legacy(image),
+    }
+}
+
+/// Find the first status reply in the bytes received from the board.
+pub fn decode_status(bytes: &[u8]) -> Option<Status> {
+    let mut decoder = CommandDecoder::new(Mode::Commands);
+    bytes.iter().find_map(|&b| match decoder.push(b) {
+        Some(Ok(Command::Status(status))) => Some(status),
+        _ => None,
+    })
+}
@@ -0,0 +1,150 @@
+//! Conversion of picture files and colours into images of the matrix.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use png::{ColorType, Decoder, Transformations};
+use tp_led_matrix::{Color, Image};
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Parse a colour given as `#rrggbb` or by its name (`black`, `white`, `red`,
+/// `green`, `blue`, `yellow`, `cyan` or `magenta`).
+pub fn parse_color(s: &str) -> Option<Color> {
+    let rgb = |r, g, b| Some(Color { r, g, b });
+    match s {
+        "black" => rgb(0, 0, 0),
+        "white" => rgb(255, 255, 255),
+        "red" => Some(Color::RED),
+        "green" => Some(Color::GREEN),
+        "blue" => Some(Color::BLUE),
+        "yellow" => rgb(255, 255, 0),
+        "cyan" => rgb(0, 255, 255),
+        "magenta" => rgb(255, 0, 255),
+        _ => {
+            let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())?;
+            let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            rgb(component(0)?, component(2)?, component(4)?)
+        }
+    }
+}
+
+/// Scale down a picture given as RGB bytes, row by row, to 8x8 pixels, each pixel
+/// of the image being the average of the corresponding area of the picture.
+/// Pictures smaller than 8x8 are scaled up.
+pub fn from_rgb(width: usize, height: usize, rgb: &[u8]) -> Image {
+    assert_eq!(width * height * 3, rgb.len());
+    let mut image = Image::default();
+    for row in 0..8 {
+        for col in 0..8 {
+            let (y0, y1) = (row * height / 8, ((row + 1) * height / 8).max(row * height / 8 + 1));
+            let (x0, x1) = (col * width / 8, ((col + 1) * width / 8).max(col * width / 8 + 1));
+            let mut sum = [0usize; 3];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    for (c, sum) in sum.iter_mut().enumerate() {
+                        *sum += rgb[3 * (y * width + x) + c] as usize;
+                    }
+                }
+            }
+            let count = (y1 - y0) * (x1 - x0);
+            let [r, g, b] = sum.map(|sum| ((sum + count / 2) / count) as u8);
+            image[(row, col)] = Color { r, g, b };
+        }
+    }
+    image
+}
+
+/// Decode a PNG picture into RGB bytes, returning its width and height as well.
+pub fn decode_png(reader: impl io::Read) -> io::Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = Decoder::new(reader);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let samples = info.color_type.samples();
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for pixel in buffer[..info.buffer_size()].chunks(samples) {
+        match info.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => rgb.extend_from_slice(&[pixel[0]; 3]),
+            _ => rgb.extend_from_slice(&pixel[..3]),
+        }
+    }
+    Ok((width, height, rgb))
+}
+
+/// The whitespace-separated tokens of a PPM picture, comments excluded.
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> io::Result<&'a [u8]> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid("truncated PPM picture")),
+            }
+        }
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        let token = self.next()?;
+        std::str::from_utf8(token).ok().and_then(|n| n.parse().ok()).ok_or_else(|| invalid("bad number in PPM picture"))
+    }
+}
+
+/// Decode a binary (P6) or ASCII (P3) PPM picture into RGB bytes, returning its
+/// width and height as well.
+pub fn decode_ppm(bytes: &[u8]) -> io::Result<(usize, usize, Vec<u8>)> {
+    let mut tokens = Tokens { bytes, pos: 0 };
+    let magic = tokens.next()?;
+    let (width, height, max) = (tokens.number()?, tokens.number()?, tokens.number()?);
+    if max == 0 || max > 255 {
+        return Err(invalid(format!("unsupported PPM maximum value {}", max)));
+    }
+    let scale = |v: usize| ((v * 255 + max / 2) / max) as u8;
+    let too_large = || invalid("PPM picture too large");
+    let len = width.checked_mul(height).and_then(|n| n.checked_mul(3)).ok_or_else(too_large)?;
+    let rgb = match magic {
+        b"P6" => {
+            // A single whitespace separates the header from the pixels
+            let start = tokens.pos + 1;
+            let end = start.checked_add(len).ok_or_else(too_large)?;
+            let data = bytes.get(start..end).ok_or_else(|| invalid("truncated PPM picture"))?;
+            data.iter().map(|&v| scale(v as usize)).collect()
+        }
+        b"P3" => (0..len).map(|_| tokens.number().map(scale)).collect::<io::Result<_>>()?,
+        _ => return Err(invalid("not a PPM picture")),
+    };
+    Ok((width, height, rgb))
+}
+
+/// Load a PNG or PPM picture, depending on the extension of its name, as an image.
+pub fn load(path: &Path) -> io::Result<Image> {
+    let (width, height, rgb) = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => decode_png(BufReader::new(File::open(path)?))?,
+        Some("ppm") => decode_ppm(&std::fs::read(path)?)?,
+        _ => return Err(invalid("unknown picture format")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("empty picture"));
+    }
+    Ok(from_rgb(width, height, &rgb))
+}
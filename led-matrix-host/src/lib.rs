@@ -0,0 +1,23 @@
+//! Host-side tools for the LED matrix: a simulator displaying the images in a
+//! terminal, a tool sending images to the board, the encoder of the commands,
+//! and the helpers they share.
+
+pub mod encoder;
+pub mod images;
+pub mod pty;
+pub mod serial;
+
+use std::fmt::Write;
+use tp_led_matrix::Image;
+
+/// Render an image with two truecolor blocks per pixel, one line per row.
+pub fn render(image: &Image) -> String {
+    let mut out = String::new();
+    for row in 0..8 {
+        for color in image.row(row) {
+            write!(out, "\x1b[38;2;{};{};{}m\u{2588}\u{2588}", color.r, color.g, color.b).unwrap();
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
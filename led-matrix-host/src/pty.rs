@@ -0,0 +1,34 @@
+//! Pseudo-terminals standing for the serial link of the board.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::path::PathBuf;
+
+use nix::pty::openpty;
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+use nix::unistd::ttyname;
+
+/// A pseudo-terminal in raw mode, so that every byte goes through unchanged.
+pub struct Pty {
+    /// The side used by the simulator.
+    pub master: File,
+    /// The side opened by the programs talking to the simulator, kept open so
+    /// that reading the master does not fail once they close it.
+    pub slave: File,
+    /// Path of the slave side, such as `/dev/pts/3`.
+    pub path: PathBuf,
+}
+
+impl Pty {
+    pub fn open() -> io::Result<Self> {
+        let pty = openpty(None, None)?;
+        let mut termios = tcgetattr(pty.slave)?;
+        cfmakeraw(&mut termios);
+        tcsetattr(pty.slave, SetArg::TCSANOW, &termios)?;
+        let path = ttyname(pty.slave)?;
+        // Safety: the descriptors have just been opened and are owned by nobody else
+        let (master, slave) = unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+        Ok(Pty { master, slave, path })
+    }
+}
@@ -0,0 +1,23 @@
+//! Access to the serial port of the board.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use nix::sys::termios::{cfmakeraw, cfsetspeed, tcgetattr, tcsetattr, BaudRate, SetArg};
+
+/// Speed of the serial port of the board.
+pub const BAUD_RATE: BaudRate = BaudRate::B38400;
+
+/// Open a serial device, such as `/dev/ttyACM0`, or a pseudo-terminal, and
+/// configure it in raw mode at the speed of the board.
+pub fn open(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let fd = file.as_raw_fd();
+    let mut termios = tcgetattr(fd)?;
+    cfmakeraw(&mut termios);
+    cfsetspeed(&mut termios, BAUD_RATE)?;
+    tcsetattr(fd, SetArg::TCSANOW, &termios)?;
+    Ok(file)
+}
@@ -0,0 +1,38 @@
+use led_matrix_host::encoder::{decode_status, encode, encode_image, encode_legacy};
+use tp_led_matrix::command::{Command, CommandDecoder, Mode, Status};
+use tp_led_matrix::{Color, Image};
+
+#[test]
+fn legacy_images() {
+    let image = Image::new_solid(Color { r: 0xff, g: 0xfe, b: 1 });
+    let bytes = encode_legacy(&image);
+    assert_eq!(193, bytes.len());
+    assert_eq!([0xff, 0xfe, 0xfe, 1], bytes[..4]);
+    assert_eq!(bytes, encode_image(&image, Mode::Legacy));
+}
+
+#[test]
+fn images_decoded_by_the_board() {
+    let image = Image::gradient(Color::GREEN);
+    for mode in [Mode::Commands, Mode::Legacy] {
+        let mut decoder = CommandDecoder::new(mode);
+        let decoded = encode_image(&image, mode).iter().find_map(|&b| match decoder.push(b) {
+            Some(Ok(Command::SetImage(decoded))) => Some(decoded.clone()),
+            _ => None,
+        });
+        // The legacy protocol cannot send 0xff
+        let mut expected = image.clone();
+        if mode == Mode::Legacy {
+            expected.as_mut().iter_mut().for_each(|b| *b = (*b).min(0xfe));
+        }
+        assert_eq!(Some(expected), decoded);
+    }
+}
+
+#[test]
+fn status_replies() {
+    let status = Status { brightness: 0xff, animation: 1, commands: 3, errors: 0xffff };
+    let bytes = [&[1, 2][..], &encode(&Command::QueryStatus), &encode(&Command::Status(status))].concat();
+    assert_eq!(Some(status), decode_status(&bytes));
+    assert_eq!(None, decode_status(&bytes[..bytes.len() - 1]));
+}
@@ -0,0 +1,37 @@
+use led_matrix_host::images::{decode_ppm, from_rgb, parse_color};
+use tp_led_matrix::{Color, Image};
+
+#[test]
+fn colors() {
+    assert_eq!(Some(Color::RED), parse_color("red"));
+    assert_eq!(Some(Color { r: 0x12, g: 0xab, b: 0xff }), parse_color("#12abFF"));
+    assert_eq!(None, parse_color("#12abf"));
+    assert_eq!(None, parse_color("#12abfg"));
+    assert_eq!(None, parse_color("pictures/heart.png"));
+}
+
+#[test]
+fn scaling() {
+    // 16x8 picture, whose pixels are averaged by pairs
+    let rgb: Vec<u8> = (0..16 * 8).flat_map(|i| [(i % 16) as u8 * 10, 0, 255]).collect();
+    let image = from_rgb(16, 8, &rgb);
+    assert_eq!(Color { r: 5, g: 0, b: 255 }, image[(0, 0)]);
+    assert_eq!(Color { r: 145, g: 0, b: 255 }, image[(3, 7)]);
+    // A single pixel is scaled up
+    assert_eq!(Image::new_solid(Color { r: 1, g: 2, b: 3 }), from_rgb(1, 1, &[1, 2, 3]));
+}
+
+#[test]
+fn ppm() {
+    let ascii = b"P3\n# a comment\n2 1 15\n15 0 0  0 0 7\n";
+    assert_eq!((2, 1, vec![255, 0, 0, 0, 0, 119]), decode_ppm(ascii).unwrap());
+    let mut binary = b"P6 1 2\n255\n".to_vec();
+    binary.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+    assert_eq!((1, 2, vec![1, 2, 3, 4, 5, 6]), decode_ppm(&binary).unwrap());
+    assert!(decode_ppm(&binary[..binary.len() - 1]).is_err());
+    assert!(decode_ppm(b"P5 1 1 255 0").is_err());
+    // Sizes that overflow are rejected instead of panicking
+    assert!(decode_ppm(b"P6 4294967296 4294967296 255 ").is_err());
+    assert!(decode_ppm(b"P3 4294967296 4294967296 255 0").is_err());
+    assert!(decode_ppm(format!("P6 1 {} 255 ", usize::MAX / 3).as_bytes()).is_err());
+}
@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use led_matrix_host::images::from_rgb;
+use led_matrix_host::pty::Pty;
+use tp_led_matrix::command::{self, CommandDecoder, Mode};
+use tp_led_matrix::gamma::{Curve, Gamma};
+use tp_led_matrix::{Color, Image};
+
+fn send() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_send"))
+}
+
+fn picture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+/// Read the images sent to the pseudo-terminal until `count` of them are received.
+fn receive(master: &mut File, mode: Mode, count: usize) -> Vec<Image> {
+    let mut decoder = CommandDecoder::new(mode);
+    let mut images = Vec::new();
+    while images.len() < count {
+        let mut buffer = [0; 256];
+        let len = master.read(&mut buffer).unwrap();
+        for &b in &buffer[..len] {
+            if let Some(Ok(command::Command::SetImage(image))) = decoder.push(b) {
+                images.push(image.clone());
+            }
+        }
+    }
+    images
+}
+
+#[test]
+fn colors_and_ppm_pictures() {
+    let mut pty = Pty::open().unwrap();
+    // 16x16 picture, red on the left and blue on the right
+    let mut ppm = b"P6\n16 16\n255\n".to_vec();
+    for _ in 0..16 {
+        ppm.extend((0..16).flat_map(|x| if x < 8 { [200, 0, 0] } else { [0, 0, 200] }));
+    }
+    std::fs::write(picture("halves.ppm"), ppm).unwrap();
+    let status = send().arg("-d").arg(&pty.path).arg("#102030").arg(picture("halves.ppm")).status().unwrap();
+    assert!(status.success());
+    let images = receive(&mut pty.master, Mode::Commands, 2);
+    assert_eq!(Image::new_solid(Color { r: 0x10, g: 0x20, b: 0x30 }), images[0]);
+    assert_eq!(Color { r: 200, g: 0, b: 0 }, images[1][(5, 3)]);
+    assert_eq!(Color { r: 0, g: 0, b: 200 }, images[1][(5, 4)]);
+}
+
+#[test]
+fn legacy_png_animation() {
+    let mut pty = Pty::open().unwrap();
+    let rgb: Vec<u8> = (0..64).flat_map(|i| [i as u8, 255, 0]).collect();
+    let file = File::create(picture("ramp.png")).unwrap();
+    let mut encoder = png::Encoder::new(file, 8, 8);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&rgb).unwrap();
+    let start = Instant::now();
+    let status = send()
+        .args(["--legacy", "--fps", "20", "--repeat", "2", "-d"])
+        .arg(&pty.path)
+        .arg(picture("ramp.png"))
+        .arg("black")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    // 4 images at 20 images per second
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    let images = receive(&mut pty.master, Mode::Legacy, 4);
+    // 255 cannot be sent in the legacy protocol
+    let mut expected = from_rgb(8, 8, &rgb);
+    expected.as_mut().iter_mut().for_each(|b| *b = (*b).min(0xfe));
+    assert_eq!(expected, images[0]);
+    assert_eq!(Image::default(), images[1]);
+    assert_eq!(images[..2], images[2..]);
+}
+
+#[test]
+fn calibration_before_images() {
+    let mut pty = Pty::open().unwrap();
+    let status = send()
+        .args(["--brightness", "40", "--white-balance", "#ff8000", "--dot-correction", "63,10,0"])
+        .args(["--gamma", "srgb,2.2,linear", "-d"])
+        .arg(&pty.path)
+        .arg("red")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut decoder = CommandDecoder::new(Mode::Commands);
+    let mut commands = Vec::new();
+    while !commands.last().is_some_and(|c: &String| c.starts_with("SetImage")) {
+        let mut buffer = [0; 256];
+        let len = pty.master.read(&mut buffer).unwrap();
+        for &b in &buffer[..len] {
+            if let Some(command) = decoder.push(b) {
+                commands.push(format!("{:?}", command.unwrap()));
+            }
+        }
+    }
+    let expected = [
+        command::Command::SetBrightness(40),
+        command::Command::SetWhiteBalance(Color { r: 0xff, g: 0x80, b: 0 }),
+        command::Command::SetDotCorrection(Color { r: 63, g: 10, b: 0 }),
+        command::Command::SetGamma(Gamma { r: Curve::Srgb, g: Curve::Gamma22, b: Curve::Linear }),
+        command::Command::SetImage(&Image::new_solid(Color::RED)),
+    ];
+    assert_eq!(expected.iter().map(|c| format!("{:?}", c)).collect::<Vec<_>>(), commands);
+    let output = send().arg("-d").arg(&pty.path).args(["--dot-correction", "64,0,0", "red"]).output().unwrap();
+    assert!(!output.status.success());
+    let output = send().arg("-d").arg(&pty.path).args(["--gamma", "2.2,srgb", "red"]).output().unwrap();
+    assert!(!output.status.success());
+    let output = send().arg("-d").arg(&pty.path).args(["--legacy", "--brightness", "10", "red"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn bad_sources() {
+    let pty = Pty::open().unwrap();
+    let output = send().arg("-d").arg(&pty.path).arg("missing.png").output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing.png"));
+    let output = send().arg("-d").arg(&pty.path).arg("--fps").arg("0").arg("red").output().unwrap();
+    assert!(!output.status.success());
+}
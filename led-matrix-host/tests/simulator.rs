@@ -0,0 +1,98 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+use led_matrix_host::encoder::{decode_status, encode};
+use led_matrix_host::render;
+use tp_led_matrix::command::{self, Status};
+use tp_led_matrix::gamma::gamma_correct;
+use tp_led_matrix::{Color, Image};
+
+/// The serial encoding of an image.
+fn frame(image: &Image) -> Vec<u8> {
+    let mut frame = vec![0xff];
+    frame.extend_from_slice(image.as_ref());
+    frame
+}
+
+#[test]
+fn render_rows() {
+    let mut image = Image::new_solid(Color::BLUE);
+    image[(1, 2)] = Color { r: 1, g: 2, b: 3 };
+    let rendered = render(&image);
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(8, lines.len());
+    assert_eq!(8, lines[0].matches("\x1b[38;2;0;0;255m\u{2588}\u{2588}").count());
+    assert!(lines[1].starts_with("\x1b[38;2;0;0;255m\u{2588}\u{2588}\x1b[38;2;0;0;255m\u{2588}\u{2588}\x1b[38;2;1;2;3m"));
+    assert!(lines[7].ends_with("\x1b[0m"));
+}
+
+#[test]
+fn gamma_corrected_images_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_simulator"))
+        .arg("--legacy")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    // A truncated image is ignored once a new one starts
+    input.write_all(&frame(&Image::new_solid(Color::GREEN))[..100]).unwrap();
+    input.write_all(&frame(&Image::new_solid(Color { r: 127, g: 0, b: 0 }))).unwrap();
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(1, stdout.matches("\x1b[H").count());
+    let red = format!("\x1b[38;2;{};0;0m", gamma_correct(127));
+    assert_eq!(64, stdout.matches(&red).count());
+}
+
+#[test]
+fn images_from_tcp() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_simulator"))
+        .args(["--tcp", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stderr.take().unwrap()).read_line(&mut line).unwrap();
+    let addr = line.trim().strip_prefix("listening on ").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&encode(&command::Command::SetImage(&Image::new_solid(Color::RED)))).unwrap();
+    // Read the screen until the 8 rows of the image have been drawn
+    let mut stdout = child.stdout.take().unwrap();
+    let mut screen = Vec::new();
+    while String::from_utf8_lossy(&screen).matches("\x1b[0m\n").count() < 8 {
+        let mut buffer = [0; 256];
+        let len = stdout.read(&mut buffer).unwrap();
+        assert!(len > 0, "unexpected end of output");
+        screen.extend_from_slice(&buffer[..len]);
+    }
+    assert_eq!(64, String::from_utf8_lossy(&screen).matches("\x1b[38;2;255;0;0m").count());
+    // Replies are sent on the connection
+    stream.write_all(&encode(&command::Command::SetBrightness(10))).unwrap();
+    stream.write_all(&encode(&command::Command::QueryStatus)).unwrap();
+    let mut reply = Vec::new();
+    let status = loop {
+        if let Some(status) = decode_status(&reply) {
+            break status;
+        }
+        let mut buffer = [0; 16];
+        let len = stream.read(&mut buffer).unwrap();
+        assert!(len > 0, "no status reply");
+        reply.extend_from_slice(&buffer[..len]);
+    };
+    assert_eq!(Status { brightness: 10, animation: 0, commands: 3, errors: 0 }, status);
+    // The brightness applies to the image displayed, before gamma correction
+    let dimmed = format!("\x1b[38;2;{};0;0m", gamma_correct(10));
+    while String::from_utf8_lossy(&screen).matches(&dimmed).count() < 64 {
+        let mut buffer = [0; 256];
+        let len = stdout.read(&mut buffer).unwrap();
+        assert!(len > 0, "unexpected end of output");
+        screen.extend_from_slice(&buffer[..len]);
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
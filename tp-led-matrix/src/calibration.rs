@@ -0,0 +1,73 @@
+//! This module gathers the settings correcting the colours displayed by the matrix.
+
+use super::Color;
+use super::dither::dither;
+use super::gamma::Gamma;
+
+/// This structure contains the brightness and colour corrections applied to the images.
+///
+/// The brightness and the white balance scale the components of the pixels before
+/// gamma correction, which uses the selected curves, while the dot correction is loaded in the 6-bit registers of
+/// bank0 of the DM163, which scale the current driving the LEDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// Global brightness, 255 leaving the pixels unchanged.
+    pub brightness: u8,
+    /// Coefficients of the red, green and blue components, 255 leaving them unchanged.
+    pub white_balance: Color,
+    /// Dot correction of the red, green and blue LEDs, from 0 to 63.
+    pub dot_correction: Color,
+    /// Gamma correction curves of the red, green and blue components.
+    pub gamma: Gamma,
+}
+
+/// Largest dot correction value (6 bits).
+pub const MAX_DOT_CORRECTION: u8 = 0x3f;
+
+impl Default for Calibration {
+    /// This function returns the calibration leaving the images unchanged.
+    fn default() -> Self {
+        Calibration {
+            brightness: 255,
+            white_balance: Color { r: 255, g: 255, b: 255 },
+            dot_correction: Color { r: MAX_DOT_CORRECTION, g: MAX_DOT_CORRECTION, b: MAX_DOT_CORRECTION },
+            gamma: Gamma::default(),
+        }
+    }
+}
+
+/// Utility function computing `value * a * b / (255 * 255)`, rounded to the nearest integer.
+fn scale(value: u8, a: u8, b: u8) -> u8 {
+    const MAX: u32 = 255 * 255;
+    ((value as u32 * a as u32 * b as u32 + MAX / 2) / MAX) as u8
+}
+
+impl Calibration {
+    /// Function that applies the brightness and the white balance to the given color.
+    pub fn apply(&self, color: Color) -> Color {
+        Color {
+            r: scale(color.r, self.brightness, self.white_balance.r),
+            g: scale(color.g, self.brightness, self.white_balance.g),
+            b: scale(color.b, self.brightness, self.white_balance.b),
+        }
+    }
+
+    /// Function that applies the brightness, the white balance, then the gamma correction
+    /// to the given color, giving the PWM values of the LEDs.
+    pub fn correct(&self, color: Color) -> Color {
+        self.gamma.correct(self.apply(color))
+    }
+
+    /// Same as `correct()`, but keeping the fractional part of the gamma corrected
+    /// values through temporal dithering, at the given phase of the cycle.
+    pub fn correct_dithered(&self, color: Color, phase: usize) -> Color {
+        let [r, g, b] = self.gamma.correct_fine(self.apply(color));
+        Color { r: dither(r, phase), g: dither(g, phase), b: dither(b, phase) }
+    }
+
+    /// Dot correction of the given channel of the DM163 (see `sim` for the channel layout).
+    pub fn channel_dot_correction(&self, channel: usize) -> u8 {
+        let dc = self.dot_correction;
+        [dc.r, dc.g, dc.b][channel % 3].min(MAX_DOT_CORRECTION)
+    }
+}
@@ -0,0 +1,357 @@
+//! This module implements the command protocol used on the serial port.
+//!
+//! A command is sent as a 0xff byte followed by the protocol version, the length of
+//! the payload, the command id, the payload and a CRC-8 of all of them. To make sure
+//! that 0xff only starts commands, the bytes following it are stuffed: 0xfe is sent
+//! as 0xfe 0x00 and 0xff as 0xfe 0x01.
+//!
+//! The legacy protocol, where 0xff is followed by the 192 bytes of an image (see the
+//! protocol module), can still be decoded as a sequence of set-image commands.
+
+use super::calibration::MAX_DOT_CORRECTION;
+use super::framebuffer::FrameBuffers;
+use super::gamma::{Curve, Gamma};
+use super::protocol::{FrameDecoder, FRAME_SIZE};
+use super::{Calibration, Color, Image};
+
+/// Version of the protocol, sent at the beginning of every command.
+pub const VERSION: u8 = 1;
+
+/// Byte starting every command.
+pub const START: u8 = 0xff;
+
+/// Byte announcing a stuffed byte.
+pub const ESCAPE: u8 = 0xfe;
+
+/// Identifiers of the commands. Replies of the board have the high bit set.
+pub const SET_IMAGE: u8 = 0x01;
+pub const SET_PIXEL: u8 = 0x02;
+pub const SET_BRIGHTNESS: u8 = 0x03;
+pub const PLAY_ANIMATION: u8 = 0x04;
+pub const QUERY_STATUS: u8 = 0x05;
+pub const SET_WHITE_BALANCE: u8 = 0x06;
+pub const SET_DOT_CORRECTION: u8 = 0x07;
+pub const SET_GAMMA: u8 = 0x08;
+pub const STATUS: u8 = 0x85;
+
+/// Maximum length of a payload, the one of an image.
+pub const MAX_PAYLOAD: usize = FRAME_SIZE;
+
+/// Animations played by the board.
+pub const NO_ANIMATION: u8 = 0;
+pub const ROTATING_GRADIENTS: u8 = 1;
+
+/// State of the board, sent in reply to a query-status command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    pub brightness: u8,
+    pub animation: u8,
+    /// Number of commands received, wrapping around.
+    pub commands: u16,
+    /// Number of invalid commands received, wrapping around.
+    pub errors: u16,
+}
+
+/// The commands and the replies exchanged with the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command<'a> {
+    SetImage(&'a Image),
+    SetPixel { row: u8, col: u8, color: Color },
+    SetBrightness(u8),
+    PlayAnimation(u8),
+    QueryStatus,
+    Status(Status),
+    /// Coefficients of the red, green and blue components (see `Calibration`).
+    SetWhiteBalance(Color),
+    /// Dot correction of the red, green and blue LEDs, from 0 to 63.
+    SetDotCorrection(Color),
+    /// Gamma correction curves of the red, green and blue components, sent as the ids
+    /// of the curves (see `Curve::id`).
+    SetGamma(Gamma),
+}
+
+/// The reasons why a command is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The protocol version is not supported.
+    Version(u8),
+    /// The payload length does not suit the command, or is too long.
+    Length(u8),
+    /// 0xfe is followed by another byte than 0x00 or 0x01.
+    Escape(u8),
+    Checksum,
+    /// The command id is unknown.
+    Command(u8),
+    /// The pixel is outside of the image.
+    Pixel,
+    /// A dot correction value does not fit in 6 bits.
+    DotCorrection,
+    /// The curve id is unknown.
+    Curve(u8),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Error::Version(version) => write!(f, "unsupported protocol version {}", version),
+            Error::Length(len) => write!(f, "bad payload length {}", len),
+            Error::Escape(b) => write!(f, "bad escaped byte {:#04x}", b),
+            Error::Checksum => write!(f, "bad checksum"),
+            Error::Command(id) => write!(f, "unknown command {:#04x}", id),
+            Error::Pixel => write!(f, "pixel outside of the image"),
+            Error::DotCorrection => write!(f, "dot correction above 63"),
+            Error::Curve(id) => write!(f, "unknown gamma curve {}", id),
+        }
+    }
+}
+
+/// Update a CRC-8 (polynomial 0x07, initial value 0) with the given byte.
+pub fn crc8(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+    }
+    crc
+}
+
+impl Command<'_> {
+    /// Id of the command.
+    pub fn id(&self) -> u8 {
+        match self {
+            Command::SetImage(_) => SET_IMAGE,
+            Command::SetPixel { .. } => SET_PIXEL,
+            Command::SetBrightness(_) => SET_BRIGHTNESS,
+            Command::PlayAnimation(_) => PLAY_ANIMATION,
+            Command::QueryStatus => QUERY_STATUS,
+            Command::Status(_) => STATUS,
+            Command::SetWhiteBalance(_) => SET_WHITE_BALANCE,
+            Command::SetDotCorrection(_) => SET_DOT_CORRECTION,
+            Command::SetGamma(_) => SET_GAMMA,
+        }
+    }
+
+    /// Call `f` with the payload of the command.
+    fn with_payload<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        match *self {
+            Command::SetImage(image) => f(image.as_ref()),
+            Command::SetPixel { row, col, color } => f(&[row, col, color.r, color.g, color.b]),
+            Command::SetBrightness(value) | Command::PlayAnimation(value) => f(&[value]),
+            Command::QueryStatus => f(&[]),
+            Command::SetWhiteBalance(color) | Command::SetDotCorrection(color) => f(&[color.r, color.g, color.b]),
+            Command::SetGamma(gamma) => f(&[gamma.r.id(), gamma.g.id(), gamma.b.id()]),
+            Command::Status(status) => {
+                let [c0, c1] = status.commands.to_le_bytes();
+                let [e0, e1] = status.errors.to_le_bytes();
+                f(&[status.brightness, status.animation, c0, c1, e0, e1])
+            }
+        }
+    }
+
+    /// Encode the command, passing the bytes to send one by one to `put`.
+    pub fn encode(&self, mut put: impl FnMut(u8)) {
+        put(START);
+        let mut stuffed = |b: u8| {
+            if b >= ESCAPE {
+                put(ESCAPE);
+                put(b - ESCAPE);
+            } else {
+                put(b);
+            }
+        };
+        self.with_payload(|payload| {
+            let mut crc = 0;
+            for b in [VERSION, payload.len() as u8, self.id()].iter().chain(payload) {
+                crc = crc8(crc, *b);
+                stuffed(*b);
+            }
+            stuffed(crc);
+        });
+    }
+
+    /// Execute a command received by the board: count it in the status, publish the
+    /// image or update the pixel, change the calibration or play the animation. Images
+    /// sent by the host stop the animation. Returns the reply to send, if any.
+    pub fn execute(&self, frames: &mut FrameBuffers, calibration: &mut Calibration, status: &mut Status) -> Option<Status> {
+        status.commands = status.commands.wrapping_add(1);
+        match *self {
+            Command::SetImage(image) => {
+                frames.back().clone_from(image);
+                frames.publish();
+                status.animation = NO_ANIMATION;
+            }
+            Command::SetPixel { row, col, color } => {
+                frames.back()[(row as usize, col as usize)] = color;
+                frames.publish();
+                status.animation = NO_ANIMATION;
+            }
+            Command::SetBrightness(brightness) => calibration.brightness = brightness,
+            Command::SetWhiteBalance(white_balance) => calibration.white_balance = white_balance,
+            Command::SetDotCorrection(dot_correction) => calibration.dot_correction = dot_correction,
+            Command::SetGamma(gamma) => calibration.gamma = gamma,
+            Command::PlayAnimation(animation) => status.animation = animation,
+            Command::QueryStatus => return Some(Status { brightness: calibration.brightness, ..*status }),
+            Command::Status(_) => (),
+        }
+        None
+    }
+
+    /// Decode a command from its id and payload.
+    fn decode(id: u8, payload: &Image, len: usize) -> Result<Command<'_>, Error> {
+        let bytes = &payload.as_ref()[..len];
+        let expected = match id {
+            SET_IMAGE => FRAME_SIZE,
+            SET_PIXEL => 5,
+            SET_BRIGHTNESS | PLAY_ANIMATION => 1,
+            QUERY_STATUS => 0,
+            STATUS => 6,
+            SET_WHITE_BALANCE | SET_DOT_CORRECTION | SET_GAMMA => 3,
+            _ => return Err(Error::Command(id)),
+        };
+        if len != expected {
+            return Err(Error::Length(len as u8));
+        }
+        Ok(match id {
+            SET_IMAGE => Command::SetImage(payload),
+            SET_PIXEL if bytes[0] >= 8 || bytes[1] >= 8 => return Err(Error::Pixel),
+            SET_PIXEL => Command::SetPixel {
+                row: bytes[0],
+                col: bytes[1],
+                color: Color { r: bytes[2], g: bytes[3], b: bytes[4] },
+            },
+            SET_BRIGHTNESS => Command::SetBrightness(bytes[0]),
+            PLAY_ANIMATION => Command::PlayAnimation(bytes[0]),
+            QUERY_STATUS => Command::QueryStatus,
+            SET_WHITE_BALANCE => Command::SetWhiteBalance(Color { r: bytes[0], g: bytes[1], b: bytes[2] }),
+            SET_DOT_CORRECTION if bytes[..3].iter().any(|&b| b > MAX_DOT_CORRECTION) => {
+                return Err(Error::DotCorrection)
+            }
+            SET_DOT_CORRECTION => Command::SetDotCorrection(Color { r: bytes[0], g: bytes[1], b: bytes[2] }),
+            SET_GAMMA => {
+                let curve = |id: u8| Curve::from_id(id).ok_or(Error::Curve(id));
+                Command::SetGamma(Gamma { r: curve(bytes[0])?, g: curve(bytes[1])?, b: curve(bytes[2])? })
+            }
+            _ => Command::Status(Status {
+                brightness: bytes[0],
+                animation: bytes[1],
+                commands: u16::from_le_bytes([bytes[2], bytes[3]]),
+                errors: u16::from_le_bytes([bytes[4], bytes[5]]),
+            }),
+        })
+    }
+}
+
+/// The format of the received bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Commands,
+    /// Raw images, decoded as set-image commands.
+    Legacy,
+}
+
+/// Position in the command being received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for 0xff.
+    Idle,
+    Version,
+    Length,
+    Id,
+    /// Receiving the payload, whose given number of bytes have been received.
+    Payload(usize),
+    Checksum,
+}
+
+/// This structure rebuilds the commands from the bytes received one by one.
+pub struct CommandDecoder {
+    mode: Mode,
+    frames: FrameDecoder,
+    state: State,
+    escaped: bool,
+    crc: u8,
+    len: usize,
+    id: u8,
+    /// The payload, stored in an image so that set-image commands need no copy.
+    payload: Image,
+}
+
+impl CommandDecoder {
+    pub fn new(mode: Mode) -> Self {
+        CommandDecoder {
+            mode,
+            frames: FrameDecoder::new(),
+            state: State::Idle,
+            escaped: false,
+            crc: 0,
+            len: 0,
+            id: 0,
+            payload: Image::default(),
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Handle the next received byte, and return the command once it is complete, or
+    /// the reason why it is rejected. Bytes are then ignored until the next 0xff.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command<'_>, Error>> {
+        if self.mode == Mode::Legacy {
+            return self.frames.push(byte).map(|image| Ok(Command::SetImage(image)));
+        }
+        if byte == START {
+            self.state = State::Version;
+            self.escaped = false;
+            self.crc = 0;
+            return None;
+        }
+        if self.state == State::Idle {
+            return None;
+        }
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                0 | 1 => byte + ESCAPE,
+                _ => return self.reject(Error::Escape(byte)),
+            }
+        } else if byte == ESCAPE {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+        if self.state != State::Checksum {
+            self.crc = crc8(self.crc, byte);
+        }
+        match self.state {
+            State::Version if byte != VERSION => return self.reject(Error::Version(byte)),
+            State::Version => self.state = State::Length,
+            State::Length if byte as usize > MAX_PAYLOAD => return self.reject(Error::Length(byte)),
+            State::Length => {
+                self.len = byte as usize;
+                self.state = State::Id;
+            }
+            State::Id => {
+                self.id = byte;
+                self.state = if self.len == 0 { State::Checksum } else { State::Payload(0) };
+            }
+            State::Payload(pos) => {
+                self.payload.as_mut()[pos] = byte;
+                self.state = if pos + 1 == self.len { State::Checksum } else { State::Payload(pos + 1) };
+            }
+            State::Checksum => {
+                self.state = State::Idle;
+                if byte != self.crc {
+                    return Some(Err(Error::Checksum));
+                }
+                return Some(Command::decode(self.id, &self.payload, self.len));
+            }
+            State::Idle => unreachable!(),
+        }
+        None
+    }
+
+    fn reject(&mut self, error: Error) -> Option<Result<Command<'_>, Error>> {
+        self.state = State::Idle;
+        Some(Err(error))
+    }
+}
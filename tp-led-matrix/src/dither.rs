@@ -0,0 +1,37 @@
+//! This module implements the temporal dithering of the matrix.
+//!
+//! The DM163 only receives 8-bit values, and gamma correction maps many low intensities
+//! to the same value (0 or 1). With dithering, the fractional part of the gamma corrected
+//! values is spread over a cycle of `FRAMES` frames: a value of 1.25 is displayed as 2
+//! in 2 frames out of 8 and as 1 in the other ones, which averages to 1.25.
+
+/// Number of fractional bits kept by the dithering.
+pub const BITS: u32 = 3;
+
+/// Number of frames of a dithering cycle.
+pub const FRAMES: usize = 1 << BITS;
+
+/// Utility function reversing the `BITS` low bits of a phase, so that the frames where
+/// a value is rounded up are spread over the cycle.
+fn threshold(phase: usize) -> usize {
+    let mut threshold = 0;
+    for bit in 0..BITS {
+        threshold |= ((phase >> bit) & 1) << (BITS - 1 - bit);
+    }
+    threshold
+}
+
+/// Value to display at the given phase of the cycle for a value with 8 fractional bits
+/// (as given by the fine gamma tables). Over the `FRAMES` consecutive phases, the average
+/// of the values displayed is the given value, rounded to `1 / FRAMES`.
+pub fn dither(value: u16, phase: usize) -> u8 {
+    let integer = (value >> 8) as u8;
+    // Fractional part, rounded to BITS bits (FRAMES meaning that the value is rounded up
+    // in every frame)
+    let fraction = ((value & 0xff) as usize + (1 << (7 - BITS))) >> (8 - BITS);
+    if fraction > threshold(phase % FRAMES) {
+        integer + 1
+    } else {
+        integer
+    }
+}
@@ -0,0 +1,87 @@
+//! This module manages the images displayed by the matrix, so that a frame never shows
+//! rows of two different images.
+//!
+//! The rows are displayed from the front buffer, and the images are drawn in the back
+//! buffer. Once drawn, an image is published: it waits in a third buffer until the
+//! display comes back to row 0, where it becomes the front buffer (as a vertical
+//! synchronization). The back buffer can thus be drawn at any time, and only the last
+//! image published before row 0 is displayed.
+
+use super::{Color, Image};
+
+/// This structure contains the three buffers and counts the frames displayed.
+pub struct FrameBuffers {
+    images: [Image; 3],
+    /// Index of the image being displayed.
+    front: usize,
+    /// Index of the image being drawn.
+    back: usize,
+    /// Index of the image published since the beginning of the frame, if any.
+    pending: Option<usize>,
+    frames: u32,
+}
+
+impl Default for FrameBuffers {
+    fn default() -> Self {
+        FrameBuffers::new(&Image::default())
+    }
+}
+
+impl FrameBuffers {
+    /// Create the buffers, the front and the back buffers containing the given image.
+    pub fn new(image: &Image) -> Self {
+        FrameBuffers {
+            images: [image.clone(), image.clone(), Image::default()],
+            front: 0,
+            back: 1,
+            pending: None,
+            frames: 0,
+        }
+    }
+
+    /// Image being displayed.
+    pub fn front(&self) -> &Image {
+        &self.images[self.front]
+    }
+
+    /// Image being drawn, which starts as a copy of the last image published.
+    pub fn back(&mut self) -> &mut Image {
+        &mut self.images[self.back]
+    }
+
+    /// Publish the back buffer, which will be displayed from the next row 0. An image
+    /// published before and still waiting is dropped. The back buffer then becomes a
+    /// copy of the published image, so that it can be partially modified.
+    pub fn publish(&mut self) {
+        let published = self.back;
+        // The new back buffer is the one which is neither displayed nor published
+        self.back = match self.pending.replace(published) {
+            Some(dropped) => dropped,
+            None => 3 - self.front - published,
+        };
+        self.images[self.back] = self.images[published].clone();
+    }
+
+    /// Whether an image has been published and waits for row 0.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Return the given row of the front buffer, to be displayed. Row 0 starts a new
+    /// frame: the frame counter is incremented, and the image published, if any,
+    /// becomes the front buffer.
+    pub fn row(&mut self, row: usize) -> &[Color] {
+        if row == 0 {
+            self.frames = self.frames.wrapping_add(1);
+            if let Some(pending) = self.pending.take() {
+                self.front = pending;
+            }
+        }
+        self.images[self.front].row(row)
+    }
+
+    /// Number of frames started, wrapping around.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
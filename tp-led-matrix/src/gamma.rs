@@ -1,24 +1,205 @@
-const GAMMA_TAB: [u8; 256] = [
-    0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x02, 0x02, 0x02, 0x02, 0x02, 0x03,
-    0x03, 0x03, 0x03, 0x04, 0x04, 0x04, 0x04, 0x05, 0x05, 0x05, 0x06, 0x06, 0x06, 0x07, 0x07, 0x08,
-    0x08, 0x08, 0x09, 0x09, 0x0a, 0x0a, 0x0b, 0x0b, 0x0b, 0x0c, 0x0c, 0x0d, 0x0d, 0x0e, 0x0e, 0x0f,
-    0x0f, 0x10, 0x11, 0x11, 0x12, 0x12, 0x13, 0x13, 0x14, 0x15, 0x15, 0x16, 0x16, 0x17, 0x18, 0x18,
-    0x19, 0x1a, 0x1a, 0x1b, 0x1c, 0x1c, 0x1d, 0x1e, 0x1e, 0x1f, 0x20, 0x20, 0x21, 0x22, 0x23, 0x23,
-    0x24, 0x25, 0x26, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2e, 0x2f, 0x30,
-    0x31, 0x32, 0x33, 0x34, 0x35, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
-    0x40, 0x41, 0x42, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4c, 0x4d, 0x4e, 0x4f,
-    0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5c, 0x5d, 0x5e, 0x5f, 0x60,
-    0x61, 0x62, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x71, 0x72, 0x73,
-    0x74, 0x75, 0x77, 0x78, 0x79, 0x7a, 0x7c, 0x7d, 0x7e, 0x7f, 0x81, 0x82, 0x83, 0x85, 0x86, 0x87,
-    0x89, 0x8a, 0x8b, 0x8c, 0x8e, 0x8f, 0x91, 0x92, 0x93, 0x95, 0x96, 0x97, 0x99, 0x9a, 0x9b, 0x9d,
-    0x9e, 0xa0, 0xa1, 0xa2, 0xa4, 0xa5, 0xa7, 0xa8, 0xaa, 0xab, 0xac, 0xae, 0xaf, 0xb1, 0xb2, 0xb4,
-    0xb5, 0xb7, 0xb8, 0xba, 0xbb, 0xbd, 0xbe, 0xc0, 0xc1, 0xc3, 0xc4, 0xc6, 0xc7, 0xc9, 0xca, 0xcc,
-    0xcd, 0xcf, 0xd1, 0xd2, 0xd4, 0xd5, 0xd7, 0xd8, 0xda, 0xdc, 0xdd, 0xdf, 0xe0, 0xe2, 0xe4, 0xe5,
-    0xe7, 0xe9, 0xea, 0xec, 0xee, 0xef, 0xf1, 0xf3, 0xf4, 0xf6, 0xf8, 0xf9, 0xfb, 0xfd, 0xfe, 0xff,
-];
-
-/// This function returns the appropriate gamma correction for the intensity given.
+//! This module generates at compile time the gamma correction tables, which map the
+//! intensities of the components to the PWM values sent to the DM163.
+//!
+//! Every curve has its table, and the curve used for every component can be chosen at
+//! runtime (see `Gamma`). Fine tables keep 8 more bits of the curves, which are not lost
+//! when the matrix spreads them over several frames (see the dither module).
+
+use core::f64::consts::LN_2;
+use super::Color;
+
+/// The curves mapping the intensities to the PWM values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Curve {
+    /// No correction.
+    Linear,
+    /// Power law with an exponent of 1.8.
+    Gamma18,
+    /// Power law with an exponent of 2.2, the closest to the hand-written table used before.
+    #[default]
+    Gamma22,
+    /// Power law with an exponent of 2.8.
+    Gamma28,
+    /// Transfer function of sRGB, close to an exponent of 2.2 with a linear part near 0.
+    Srgb,
+}
+
+static LINEAR: [u8; 256] = table(Curve::Linear);
+static GAMMA_18: [u8; 256] = table(Curve::Gamma18);
+static GAMMA_22: [u8; 256] = table(Curve::Gamma22);
+static GAMMA_28: [u8; 256] = table(Curve::Gamma28);
+static SRGB: [u8; 256] = table(Curve::Srgb);
+static FINE_LINEAR: [u16; 256] = fine_table(Curve::Linear);
+static FINE_GAMMA_18: [u16; 256] = fine_table(Curve::Gamma18);
+static FINE_GAMMA_22: [u16; 256] = fine_table(Curve::Gamma22);
+static FINE_GAMMA_28: [u16; 256] = fine_table(Curve::Gamma28);
+static FINE_SRGB: [u16; 256] = fine_table(Curve::Srgb);
+
+impl Curve {
+    /// All the curves, in the order of their ids.
+    pub const ALL: [Curve; 5] = [Curve::Linear, Curve::Gamma18, Curve::Gamma22, Curve::Gamma28, Curve::Srgb];
+
+    /// Id of the curve in the commands.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Curve with the given id, if any.
+    pub fn from_id(id: u8) -> Option<Curve> {
+        Curve::ALL.get(id as usize).copied()
+    }
+
+    /// Table of the curve, generated at compile time.
+    pub fn table(self) -> &'static [u8; 256] {
+        match self {
+            Curve::Linear => &LINEAR,
+            Curve::Gamma18 => &GAMMA_18,
+            Curve::Gamma22 => &GAMMA_22,
+            Curve::Gamma28 => &GAMMA_28,
+            Curve::Srgb => &SRGB,
+        }
+    }
+
+    /// Fine table of the curve, generated at compile time.
+    pub fn fine_table(self) -> &'static [u16; 256] {
+        match self {
+            Curve::Linear => &FINE_LINEAR,
+            Curve::Gamma18 => &FINE_GAMMA_18,
+            Curve::Gamma22 => &FINE_GAMMA_22,
+            Curve::Gamma28 => &FINE_GAMMA_28,
+            Curve::Srgb => &FINE_SRGB,
+        }
+    }
+
+    /// Value of the curve for an intensity between 0 and 1.
+    const fn eval(self, x: f64) -> f64 {
+        match self {
+            Curve::Linear => x,
+            Curve::Gamma18 => powf(x, 1.8),
+            Curve::Gamma22 => powf(x, 2.2),
+            Curve::Gamma28 => powf(x, 2.8),
+            Curve::Srgb if x <= 0.04045 => x / 12.92,
+            Curve::Srgb => powf((x + 0.055) / 1.055, 2.4),
+        }
+    }
+}
+
+/// Generate the table of a curve, rounding its values to the nearest integers.
+pub const fn table(curve: Curve) -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = (curve.eval(i as f64 / 255.0) * 255.0 + 0.5) as u8;
+        i += 1;
+    }
+    table
+}
+
+/// Generate the fine table of a curve, whose values are those of the table with 8
+/// fractional bits, from 0 to 0xff00.
+pub const fn fine_table(curve: Curve) -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = (curve.eval(i as f64 / 255.0) * 255.0 * 256.0 + 0.5) as u16;
+        i += 1;
+    }
+    table
+}
+
+/// Utility function computing `x` to the power `e`, for `x` between 0 and 1, as
+/// `f64::powf` is not available in `const fn` (nor without `std`).
+const fn powf(x: f64, e: f64) -> f64 {
+    if x <= 0.0 {
+        0.0
+    } else {
+        exp(e * ln(x))
+    }
+}
+
+/// Natural logarithm of a positive number.
+const fn ln(x: f64) -> f64 {
+    // x = m * 2^k with m in [1, 2)
+    let (mut m, mut k) = (x, 0);
+    while m >= 2.0 {
+        m /= 2.0;
+        k += 1;
+    }
+    while m < 1.0 {
+        m *= 2.0;
+        k -= 1;
+    }
+    // ln(m) = 2 atanh(t) with t = (m - 1) / (m + 1) below 1/3
+    let t = (m - 1.0) / (m + 1.0);
+    let (mut term, mut sum, mut n) = (t, 0.0, 1);
+    while n < 40 {
+        sum += term / n as f64;
+        term *= t * t;
+        n += 2;
+    }
+    2.0 * sum + k as f64 * LN_2
+}
+
+/// Exponential of a number.
+const fn exp(y: f64) -> f64 {
+    // y = n ln(2) + r with r in (-ln(2), ln(2))
+    let n = (y / LN_2) as i32;
+    let r = y - n as f64 * LN_2;
+    let (mut term, mut sum, mut i) = (1.0, 1.0, 1);
+    while i < 25 {
+        term *= r / i as f64;
+        sum += term;
+        i += 1;
+    }
+    let mut n = n;
+    while n > 0 {
+        sum *= 2.0;
+        n -= 1;
+    }
+    while n < 0 {
+        sum /= 2.0;
+        n += 1;
+    }
+    sum
+}
+
+/// This structure selects the curve used for every component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Gamma {
+    pub r: Curve,
+    pub g: Curve,
+    pub b: Curve,
+}
+
+impl Gamma {
+    /// The same curve for the three components.
+    pub const fn uniform(curve: Curve) -> Self {
+        Gamma { r: curve, g: curve, b: curve }
+    }
+
+    /// Apply the curve of every component to the given color.
+    pub fn correct(&self, color: Color) -> Color {
+        Color {
+            r: self.r.table()[color.r as usize],
+            g: self.g.table()[color.g as usize],
+            b: self.b.table()[color.b as usize],
+        }
+    }
+
+    /// Apply the fine curve of every component to the given color, giving the red,
+    /// green and blue values with 8 fractional bits.
+    pub fn correct_fine(&self, color: Color) -> [u16; 3] {
+        [
+            self.r.fine_table()[color.r as usize],
+            self.g.fine_table()[color.g as usize],
+            self.b.fine_table()[color.b as usize],
+        ]
+    }
+}
+
+/// This function returns the appropriate gamma correction for the intensity given,
+/// using the default curve.
 /// Gamma correction helps increase the quality of the picture by modifying the contrast.
 pub fn gamma_correct(x : u8) -> u8 {
-    GAMMA_TAB[x as usize]
-}
\ No newline at end of file
+    Curve::default().table()[x as usize]
+}
@@ -2,7 +2,6 @@
 //! The goal is to easily handle image creation in order to test matrix printing
 
 #[allow(unused_imports)]
-
 use micromath::F32;
 use super::gamma;
 
@@ -10,6 +9,8 @@ use super::gamma;
 #[derive(Clone)]
 #[derive(Copy)]
 #[derive(Default)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
 #[repr(C)]
 pub struct Color {
     pub r : u8,
@@ -20,6 +21,9 @@ pub struct Color {
 
 
 /// This structure is only a representation of an image (8x8 pixels)
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Image([Color; 64]);
 
@@ -38,7 +42,7 @@ impl Color {
     pub const GREEN : Color = Color{r : 0, g : 255, b : 0};
     pub const BLUE : Color = Color{r : 0, g : 0, b : 255};
 
-    /// Function that apply gamma correction to the given color. (Cf gamma module)
+    /// Function that apply gamma correction to the given color, with the default curve. (Cf gamma module)
     pub fn gamma_correct(&self) -> Self {
         Color{
             r : gamma::gamma_correct(self.r),
@@ -1,8 +1,15 @@
 #![no_std]
 
+pub mod calibration;
+pub mod command;
+pub mod dither;
+pub mod framebuffer;
 pub mod gamma;
 pub mod image;
 pub mod matrix;
+pub mod protocol;
+pub mod sim;
 
+pub use calibration::Calibration;
 pub use image::Color;
 pub use image::Image;
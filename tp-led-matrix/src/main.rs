@@ -3,14 +3,23 @@
 #![allow(clippy::empty_loop)]
 
 use stm32l4xx_hal::{pac, prelude::*};
-use stm32l4xx_hal::serial::{Config, Event, Rx, Serial};
+use stm32l4xx_hal::serial::{Config, Event, Rx, Serial, Tx};
 use panic_probe as _;
 use defmt_rtt as _;
-use tp_led_matrix::{Image, matrix::Matrix};
+use tp_led_matrix::{Calibration, Color, Image, framebuffer::FrameBuffers, matrix::BoardMatrix};
+use tp_led_matrix::command::{Command, CommandDecoder, Mode, Status, ROTATING_GRADIENTS};
 use dwt_systick_monotonic::DwtSystick;
 use dwt_systick_monotonic::ExtU32;
 use crate::pac::USART1;
 
+/// Protocol used on the serial port, `Mode::Legacy` being for the tools sending raw images.
+const PROTOCOL: Mode = Mode::Commands;
+
+/// Whether the matrix spreads the fractional part of the gamma corrected values over
+/// several frames, to smooth the dark gradients. It is disabled by default, as the
+/// 8-frame cycle runs at 7.5 Hz and can make the dark pixels flicker.
+const DITHERING: bool = false;
+
 #[rtic::app(device = pac, dispatchers = [USART2, USART3])]
 mod app {
 
@@ -44,7 +53,7 @@ mod app {
         let mut gpioc = dp.GPIOC.split(&mut rcc.ahb2);
 
         // Initialising led matrix
-        let matrix = Matrix::new(
+        let mut matrix = BoardMatrix::new_stm32(
             gpioa.pa2,
             gpioa.pa3,
             gpioa.pa4,
@@ -66,6 +75,7 @@ mod app {
             &mut gpioc.otyper,
             clocks
         );
+        matrix.set_dithering(DITHERING);
 
         // Getting representation of transmission and reception pins for the serial port.
         let tx = gpiob.pb6.into_alternate::<7>(&mut gpiob.moder, &mut gpiob.otyper, &mut gpiob.afrl);
@@ -75,31 +85,35 @@ mod app {
         let config = Config::default().baudrate(38_400.bps());
         let mut serial = Serial::usart1(dp.USART1, (tx, rx), config, clocks, &mut rcc.apb2);
         serial.listen(Event::Rxne);
-        let usart1_rx = serial.split().1;
+        let (usart1_tx, usart1_rx) = serial.split();
 
-        // Initialising local and shared image variables.
-        let image = Image::default();
-        let next_image = Image::default();
+        // Initialising the shared frame buffers, calibration and status, and the decoder of the received commands.
+        let frames = FrameBuffers::default();
+        let calibration = Calibration::default();
+        let status = Status::default();
+        let decoder = CommandDecoder::new(PROTOCOL);
 
-        // First launch of the display task.
+        // First launch of the display and animation tasks.
         display::spawn(mono.now()).unwrap();
-
-        //rotate_image::spawn(mono.now(), 0).unwrap();
+        rotate_image::spawn(mono.now(), 0).unwrap();
 
         // Return the resources and the monotonic timer
-        (Shared {image}, Local {matrix, usart1_rx, next_image}, init::Monotonics(mono))
+        (Shared {frames, calibration, status}, Local {matrix, usart1_rx, usart1_tx, decoder}, init::Monotonics(mono))
     }
 
     #[shared]
     struct Shared {
-        image : Image,
+        frames : FrameBuffers,
+        calibration : Calibration,
+        status : Status,
     }
 
     #[local]
     struct Local {
-        matrix: Matrix,
+        matrix: BoardMatrix,
         usart1_rx : Rx<USART1>,
-        next_image : Image,
+        usart1_tx : Tx<USART1>,
+        decoder : CommandDecoder,
     }
 
     #[idle(local = [])]
@@ -107,13 +121,16 @@ mod app {
         loop {} // infinite loop because this idle task isn't usefull for this program.
     }
 
-    #[task(local = [matrix, next_line: usize = 0], shared = [image], priority = 2)]
+    #[task(local = [matrix, next_line: usize = 0], shared = [frames, calibration], priority = 2)]
     fn display(mut cx: display::Context, at: Instant) {
+        // Take the changes of calibration into account
+        let calibration = cx.shared.calibration.lock(|calibration| *calibration);
+        cx.local.matrix.set_calibration(calibration);
         // Display line next_line (cx.local.next_line) of
-        // the image (cx.local.image) on the matrix (cx.local.matrix).
-        // All those are mutable references.
-        cx.shared.image.lock( |image| {
-            cx.local.matrix.send_row(*cx.local.next_line, image.row(*cx.local.next_line));
+        // the front buffer on the matrix (cx.local.matrix). The image published
+        // last is only displayed from line 0, so that frames do not tear.
+        cx.shared.frames.lock( |frames| {
+            cx.local.matrix.send_row(*cx.local.next_line, frames.row(*cx.local.next_line));
         } );
         // Increment next_line up to 7 and wraparound to 0
         *cx.local.next_line = (*cx.local.next_line + 1) % 8;
@@ -123,53 +140,55 @@ mod app {
         display::spawn_at(time, time).unwrap();
     }
 
-    #[task(binds = USART1, local = [usart1_rx, next_image, next_pos: usize = 0], shared = [image])]
+    // The reception preempts the sending of the replies, so that no byte is lost while
+    // the transmitter is busy.
+    #[task(binds = USART1, local = [usart1_rx, decoder], shared = [frames, calibration, status], priority = 2)]
     fn receive_byte(mut cx: receive_byte::Context) {
-        let next_image: &mut Image = cx.local.next_image;
-        let next_pos: &mut usize = cx.local.next_pos;
+        let decoder: &mut CommandDecoder = cx.local.decoder;
         if let Ok(b) = cx.local.usart1_rx.read() {
-            // Handle the incoming byte according to the SE203 protocol
-            // and update next_image
-            // Do not forget that next_image.as_mut() might be handy here!
-
-            if b == 0xff { // b==0xff anounces the beginning of a new image.
-                *next_pos = 0;
-            } else {
-                let mutimage = next_image.as_mut();
-                // The next image is changed pixel by pixel (and even component by component within a pixel).
-                // It is possible to use next_pos to change the right byte of the image since
-                // we chose to represent images as it is in the memory by adding #[repr(C)] attribute for Color
-                // and #[repr(transparent)] for Image.
-                mutimage[*next_pos as usize] = b;
-                *next_pos += 1;
-            }
-
-            // If the received image is complete, make it available to
-            // the display task.
-            if *next_pos == 8 * 8 * 3 {
-                cx.shared.image.lock(|image| {
-                    // Replace the image content by the new one, for example
-                    // by swapping them, and reset next_pos
-                    core::mem::swap(image, next_image);
-                    *next_pos = 0;
-                });
+            // Handle the incoming byte according to the command protocol, and
+            // execute the command once it is complete.
+            let command = match decoder.push(b) {
+                None => return,
+                Some(Ok(command)) => command,
+                Some(Err(e)) => {
+                    defmt::warn!("invalid command: {}", defmt::Display2Format(&e));
+                    cx.shared.status.lock(|status| status.errors = status.errors.wrapping_add(1));
+                    return;
+                }
+            };
+            let shared = (cx.shared.frames, cx.shared.calibration, cx.shared.status);
+            let reply = shared.lock(|frames, calibration, status| command.execute(frames, calibration, status));
+            if let Some(status) = reply {
+                if send_status::spawn(status).is_err() {
+                    defmt::warn!("too many status replies pending");
+                }
             }
         }
     }
- 
 
-    /*#[task(shared = [image], priority = 1)]
+    #[task(local = [usart1_tx], capacity = 4, priority = 1)]
+    fn send_status(cx: send_status::Context, status: Status) {
+        // Wait for the transmitter before sending every byte of the reply
+        let tx: &mut Tx<USART1> = cx.local.usart1_tx;
+        Command::Status(status).encode(|b| {
+            nb::block!(tx.write(b)).ok();
+        });
+    }
+
+    #[task(shared = [frames, status], priority = 1)]
     fn rotate_image(mut cx: rotate_image::Context, at: Instant, color_index: usize) {
-        cx.shared.image.lock( |image| {
-            match color_index {
-                0 => *image = Image::gradient(Color::RED),
-                1 => *image = Image::gradient(Color::GREEN),
-                2 => *image = Image::gradient(Color::BLUE),
-                _ => panic!("Wrong color value"),
-            }
-        } );
-        rotate_image::spawn_after(1.secs(), at, (color_index + 1) % 3).unwrap();
-    }*/
+        // Display a new gradient every second while the animation is played.
+        if cx.shared.status.lock(|status| status.animation) == ROTATING_GRADIENTS {
+            let color = [Color::RED, Color::GREEN, Color::BLUE][color_index];
+            cx.shared.frames.lock(|frames| {
+                *frames.back() = Image::gradient(color);
+                frames.publish();
+            });
+        }
+        let time : Instant = at + 1.secs();
+        rotate_image::spawn_at(time, time, (color_index + 1) % 3).unwrap();
+    }
 
 }
 
@@ -1,85 +1,60 @@
 //! This module gathers many functions whose aim is to help using the led matrix.
+//!
+//! The driver only relies on the embedded-hal `OutputPin` and `DelayMs` traits, so
+//! that it can be used with the pins of the board as well as with simulated pins on
+//! the host. Every control pin has its own type, so that the pins of the board used to
+//! send the bits to the DM163 are not erased, and the errors of the pins are ignored.
+//! The wiring of the STM32L475 board is provided by `BoardMatrix::new_stm32()`.
 
-use stm32l4xx_hal::{gpio::*, rcc::Clocks};
-use super::{Color, Image};
-use crate::matrix::PinState::*;
-use stm32l4xx_hal::prelude::_embedded_hal_blocking_delay_DelayMs;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::{OutputPin, PinState};
+use embedded_hal::digital::v2::PinState::*;
+use super::{Calibration, Color, Image};
+use super::dither::FRAMES;
 
 /// This structure contains the pins used to control the columns and row of the LED matrix
 /// in order to print images on it.
-pub struct Matrix {
-    sb: PC5<Output<PushPull>>,
-    lat: PC4<Output<PushPull>>,
-    rst: PC3<Output<PushPull>>,
-    sck: PB1<Output<PushPull>>,
-    sda: PA4<Output<PushPull>>,
-    c0: PB2<Output<PushPull>>,
-    c1: PA15<Output<PushPull>>,
-    c2: PA2<Output<PushPull>>,
-    c3: PA7<Output<PushPull>>,
-    c4: PA6<Output<PushPull>>,
-    c5: PA5<Output<PushPull>>,
-    c6: PB0<Output<PushPull>>,
-    c7: PA3<Output<PushPull>>,
+pub struct Matrix<SB, LAT, RST, SCK, SDA, ROW> {
+    sb: SB,
+    lat: LAT,
+    rst: RST,
+    sck: SCK,
+    sda: SDA,
+    rows: [ROW; 8],
+    calibration: Calibration,
+    dithering: bool,
+    /// Number of frames sent, modulo the length of a dithering cycle.
+    frame: usize,
 }
 
-impl Matrix {
-    /// Create a new matrix from the control registers and the individual
-    /// unconfigured pins. SB and LAT will be set high by default, while
-    /// other pins will be set low. After 100ms, RST will be set high, and
-    /// the bank 0 will be initialized by calling `init_bank0()` on the
-    /// newly constructed structure.
-    /// The pins will be set to very high speed mode.
-    #[allow(clippy::too_many_arguments)]   // Necessary to avoid a clippy warning
-    pub fn new(
-        pa2: PA2<Analog>,
-        pa3: PA3<Analog>,
-        pa4: PA4<Analog>,
-        pa5: PA5<Analog>,
-        pa6: PA6<Analog>,
-        pa7: PA7<Analog>,
-        pa15: PA15<Alternate<PushPull, 0>>,
-        pb0: PB0<Analog>,
-        pb1: PB1<Analog>,
-        pb2: PB2<Analog>,
-        pc3: PC3<Analog>,
-        pc4: PC4<Analog>,
-        pc5: PC5<Analog>,
-        gpioa_moder: &mut MODER<'A'>,
-        gpioa_otyper: &mut OTYPER<'A'>,
-        gpiob_moder: &mut MODER<'B'>,
-        gpiob_otyper: &mut OTYPER<'B'>,
-        gpioc_moder: &mut MODER<'C'>,
-        gpioc_otyper: &mut OTYPER<'C'>,
-        clocks: Clocks,
-    ) -> Self {
-        let mut matrix = Matrix {
-            sb: pc5.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, High),
-            lat: pc4.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, High),
-            rst: pc3.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, Low),
-            sck: pb1.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, Low),
-            sda: pa4.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c0: pb2.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, Low),
-            c1: pa15.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c2: pa2.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c3: pa7.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c4: pa6.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c5: pa5.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-            c6: pb0.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, Low),
-            c7: pa3.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, Low),
-        };
-        
-        // The speed of these pins are set at a very high level because of the important traffic
-        // needed on them.
-        matrix.sck = matrix.sck.set_speed(Speed::VeryHigh);
-        matrix.sda = matrix.sda.set_speed(Speed::VeryHigh);
-        matrix.lat = matrix.lat.set_speed(Speed::VeryHigh);
-        
-        // The following lines are used to bring a 100ms delay required for the initialization.
-        let mut delay = stm32l4xx_hal::delay::DelayCM::new(clocks);
-        stm32l4xx_hal::delay::DelayCM::delay_ms(&mut delay, 100_u8);
-
-        matrix.rst.set_high();
+impl<SB, LAT, RST, SCK, SDA, ROW> Matrix<SB, LAT, RST, SCK, SDA, ROW>
+where
+    SB: OutputPin,
+    LAT: OutputPin,
+    RST: OutputPin,
+    SCK: OutputPin,
+    SDA: OutputPin,
+    ROW: OutputPin,
+{
+    /// Create a new matrix from already configured output pins, `rows` being C0 to C7.
+    /// SB and LAT will be set high, while other pins will be set low. After 100ms,
+    /// RST will be set high, and the bank 0 will be initialized by calling `init_bank0()`
+    /// on the newly constructed structure.
+    pub fn new(sb: SB, lat: LAT, rst: RST, sck: SCK, sda: SDA, rows: [ROW; 8], delay: &mut impl DelayMs<u8>) -> Self {
+        let mut matrix = Matrix { sb, lat, rst, sck, sda, rows, calibration: Calibration::default(), dithering: false, frame: 0 };
+        matrix.sb.set_high().ok();
+        matrix.lat.set_high().ok();
+        matrix.rst.set_low().ok();
+        matrix.sck.set_low().ok();
+        matrix.sda.set_low().ok();
+        for row in 0..8 {
+            matrix.row(row, Low);
+        }
+
+        // The following line is used to bring a 100ms delay required for the initialization.
+        delay.delay_ms(100_u8);
+
+        matrix.rst.set_high().ok();
         matrix.init_bank0();
 
         matrix
@@ -87,47 +62,73 @@ impl Matrix {
 
     /// Make a brief high pulse of the SCK pin
     fn pulse_sck(&mut self) {
-        self.sck.set_high();
-        self.sck.set_low();
+        self.sck.set_high().ok();
+        self.sck.set_low().ok();
     }
 
     /// Make a brief low pulse of the LAT pin
     fn pulse_lat(&mut self) {
-        self.lat.set_low();
-        self.lat.set_high();
+        self.lat.set_low().ok();
+        self.lat.set_high().ok();
     }
 
     /// Set the given row output in the chosen state
     fn row(&mut self, row: usize, state: PinState) {
-        match row {
-            0 => self.c0.set_state(state),
-            1 => self.c1.set_state(state),
-            2 => self.c2.set_state(state),
-            3 => self.c3.set_state(state),
-            4 => self.c4.set_state(state),
-            5 => self.c5.set_state(state),
-            6 => self.c6.set_state(state),
-            7 => self.c7.set_state(state),
-            _ => panic!("wrong index"),
+        match self.rows.get_mut(row) {
+            Some(pin) => pin.set_state(state).ok(),
+            None => panic!("wrong index"),
+        };
+    }
+
+    /// Send the given number of low bits of a value on SDA starting with the MSB
+    /// and pulse SCK high after each bit
+    fn send_bits(&mut self, value: u8, bits: usize) {
+        for i in (0..bits).rev() {
+            // The corresponding value is put on the SDA pin
+            self.sda.set_state(PinState::from((value >> i) & 1 == 1)).ok();
+            self.pulse_sck();
         }
     }
 
     /// Send a byte on SDA starting with the MSB and pulse SCK high after each bit
     fn send_byte(&mut self, pixel: u8) {
-        for i in (0..=7).rev() {
-            // The corresponding value is put on the SDA pin
-            self.sda.set_state(PinState::from((pixel >> i) & 1 == 1));
-            self.pulse_sck();
+        self.send_bits(pixel, 8);
+    }
+
+    /// Return the current calibration.
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Change the calibration used for the next rows. Bank0 is initialized again
+    /// if the dot correction changes.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        let reload = calibration.dot_correction != self.calibration.dot_correction;
+        self.calibration = calibration;
+        if reload {
+            self.init_bank0();
         }
     }
 
-    /// Send a full row of bytes in BGR order and pulse LAT low. Gamma correction
-    /// must be applied to every pixel before sending them. The previous row must
-    /// be deactivated and the new one activated.
+    /// Enable or disable the temporal dithering of the rows (see the dither module),
+    /// which is disabled by default.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+    }
+
+    /// Send a full row of bytes in BGR order and pulse LAT low. The calibration,
+    /// gamma correction included, must be applied to every pixel before sending them.
+    /// The previous row must be deactivated and the new one activated. With dithering,
+    /// a frame ends once row 7 is sent, and the phase of every pixel is offset by its
+    /// position so that neighbouring pixels are not rounded up in the same frames.
     pub fn send_row(&mut self, row: usize, pixels: &[Color]) {
         // pixels represents the 8 colors that composes the given row
         for i in (0..=7).rev() {
-            let mycolor = pixels[i].gamma_correct();
+            let mycolor = if self.dithering {
+                self.calibration.correct_dithered(pixels[i], self.frame + row + i)
+            } else {
+                self.calibration.correct(pixels[i])
+            };
             // Here the 3 RGB components of the color are sent one by one on SDA pin
             self.send_byte(mycolor.b);
             self.send_byte(mycolor.g);
@@ -144,19 +145,22 @@ impl Matrix {
         }
         self.pulse_lat();
         self.row(row, High); // the new row is turned on
+        if row == 7 {
+            self.frame = (self.frame + 1) % FRAMES;
+        }
     }
 
-    /// Initialize bank0 by temporarily setting SB to low and sending 144 one bits,
-    /// pulsing SCK high after each bit and pulsing LAT low at the end. SB is then
-    /// restored to high.
-    fn init_bank0(&mut self) {
-        self.sb.set_low();
-        // 18 x 8 = 144 one bits are sent on sda to initialize the bank0
-        for _i in 0..18 {
-            self.send_byte(255_u8);
+    /// Initialize bank0 by temporarily setting SB to low and sending the 6-bit dot
+    /// correction of the 24 channels (144 bits), pulsing SCK high after each bit and
+    /// pulsing LAT low at the end. SB is then restored to high.
+    pub fn init_bank0(&mut self) {
+        self.sb.set_low().ok();
+        // As for bank1, the last channel is sent first
+        for channel in (0..24).rev() {
+            self.send_bits(self.calibration.channel_dot_correction(channel), 6);
         }
         self.pulse_lat();
-        self.sb.set_high();
+        self.sb.set_high().ok();
     }
 
     /// Display a full image, row by row, as fast as possible.
@@ -166,6 +170,83 @@ impl Matrix {
         for i in 0..=7 {
             self.send_row(i, image.row(i));
         }
-        
     }
 }
+
+#[cfg(feature = "board")]
+mod stm32 {
+    use stm32l4xx_hal::{gpio::*, rcc::Clocks};
+    use stm32l4xx_hal::delay::DelayCM;
+    use super::Matrix;
+
+    /// Output pin of the board, whatever its port and number, used for the rows.
+    pub type BoardPin = ErasedPin<Output<PushPull>>;
+
+    /// Matrix wired to the pins of the board.
+    pub type BoardMatrix = Matrix<
+        PC5<Output<PushPull>>,
+        PC4<Output<PushPull>>,
+        PC3<Output<PushPull>>,
+        PB1<Output<PushPull>>,
+        PA4<Output<PushPull>>,
+        BoardPin,
+    >;
+
+    impl BoardMatrix {
+        /// Create a new matrix from the control registers and the individual
+        /// unconfigured pins of the board, then initialize it with `Matrix::new()`.
+        /// The pins used for the serial link with the DM163 will be set to very high
+        /// speed mode.
+        #[allow(clippy::too_many_arguments)]   // Necessary to avoid a clippy warning
+        pub fn new_stm32(
+            pa2: PA2<Analog>,
+            pa3: PA3<Analog>,
+            pa4: PA4<Analog>,
+            pa5: PA5<Analog>,
+            pa6: PA6<Analog>,
+            pa7: PA7<Analog>,
+            pa15: PA15<Alternate<PushPull, 0>>,
+            pb0: PB0<Analog>,
+            pb1: PB1<Analog>,
+            pb2: PB2<Analog>,
+            pc3: PC3<Analog>,
+            pc4: PC4<Analog>,
+            pc5: PC5<Analog>,
+            gpioa_moder: &mut MODER<'A'>,
+            gpioa_otyper: &mut OTYPER<'A'>,
+            gpiob_moder: &mut MODER<'B'>,
+            gpiob_otyper: &mut OTYPER<'B'>,
+            gpioc_moder: &mut MODER<'C'>,
+            gpioc_otyper: &mut OTYPER<'C'>,
+            clocks: Clocks,
+        ) -> Self {
+            // The speed of these pins are set at a very high level because of the important traffic
+            // needed on them.
+            let sck = pb1.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, PinState::Low).set_speed(Speed::VeryHigh);
+            let sda = pa4.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).set_speed(Speed::VeryHigh);
+            let lat = pc4.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, PinState::High).set_speed(Speed::VeryHigh);
+            let rows = [
+                pb2.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, PinState::Low).erase(),
+                pa15.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+                pa2.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+                pa7.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+                pa6.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+                pa5.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+                pb0.into_push_pull_output_in_state(gpiob_moder, gpiob_otyper, PinState::Low).erase(),
+                pa3.into_push_pull_output_in_state(gpioa_moder, gpioa_otyper, PinState::Low).erase(),
+            ];
+            Matrix::new(
+                pc5.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, PinState::High),
+                lat,
+                pc3.into_push_pull_output_in_state(gpioc_moder, gpioc_otyper, PinState::Low),
+                sck,
+                sda,
+                rows,
+                &mut DelayCM::new(clocks),
+            )
+        }
+    }
+}
+
+#[cfg(feature = "board")]
+pub use stm32::{BoardMatrix, BoardPin};
@@ -0,0 +1,49 @@
+//! This module decodes the images received on the serial port.
+//!
+//! An image is sent as a 0xff byte followed by the 192 bytes of its pixels, in the
+//! memory layout of `Image` (RGB components, row by row). Since 0xff always starts
+//! a new image, it never appears in the pixels.
+
+use super::Image;
+
+/// Number of bytes of an image on the serial link, not counting the 0xff marker.
+pub const FRAME_SIZE: usize = 8 * 8 * 3;
+
+/// This structure rebuilds the images from the bytes received one by one.
+///
+/// Bytes received before the first 0xff or after a complete image are ignored until
+/// the next 0xff, and an incomplete image is dropped when a new one starts.
+#[derive(Default)]
+pub struct FrameDecoder {
+    image: Image,
+    /// Position of the next byte in the image, or `None` while waiting for 0xff.
+    pos: Option<usize>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Handle the next received byte, and return the image once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<&Image> {
+        if byte == 0xff {
+            self.pos = Some(0);
+            return None;
+        }
+        let pos = self.pos?;
+        self.image.as_mut()[pos] = byte;
+        if pos + 1 == FRAME_SIZE {
+            self.pos = None;
+            Some(&self.image)
+        } else {
+            self.pos = Some(pos + 1);
+            None
+        }
+    }
+
+    /// Tell whether an image is being received.
+    pub fn in_frame(&self) -> bool {
+        self.pos.is_some()
+    }
+}
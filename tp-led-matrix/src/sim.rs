@@ -0,0 +1,231 @@
+//! This module simulates the DM163 constant-current driver and the rows of the LED
+//! matrix, so that the matrix driver can be run without the board.
+//!
+//! Every pin of the matrix is a `SimPin` sharing the same `Dm163`, which follows the
+//! levels of the lines and reconstructs the colours which would be displayed.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::OutputPin;
+use super::matrix::Matrix;
+use super::{Color, Image};
+
+/// Number of channels of the DM163 (8 pixels of 3 colours).
+pub const CHANNELS: usize = 24;
+
+/// The lines connecting the board to the DM163 and to the rows of the matrix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    Sb,
+    Lat,
+    Rst,
+    Sck,
+    Sda,
+    Row(usize),
+}
+
+/// This structure represents the state of the DM163 and of the rows of the matrix.
+///
+/// Bits are shifted in on the rising edges of SCK, MSB first, into the 8-bit registers
+/// of bank1 when SB is high and into the 6-bit registers of bank0 (dot correction)
+/// when SB is low. The shift register is copied into the selected bank at the end of
+/// a low pulse of LAT. While RST is low, all the registers are cleared and the other
+/// lines are ignored. Channel `3 * n` (resp. `3 * n + 1`, `3 * n + 2`) drives the red
+/// (resp. green, blue) LED of column `n`, and the last byte sent goes to channel 0.
+#[derive(Clone, Debug, Default)]
+pub struct Dm163 {
+    sb: bool,
+    lat: bool,
+    rst: bool,
+    sck: bool,
+    sda: bool,
+    rows: [bool; 8],
+    shift0: [u8; CHANNELS],
+    shift1: [u8; CHANNELS],
+    bank0: [u8; CHANNELS],
+    bank1: [u8; CHANNELS],
+    displayed: [[Color; 8]; 8],
+    latches: usize,
+}
+
+impl Dm163 {
+    /// Create a DM163 with all the lines low, hence held in reset.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Level of the given line.
+    pub fn level(&self, line: Line) -> bool {
+        match line {
+            Line::Sb => self.sb,
+            Line::Lat => self.lat,
+            Line::Rst => self.rst,
+            Line::Sck => self.sck,
+            Line::Sda => self.sda,
+            Line::Row(row) => self.rows[row],
+        }
+    }
+
+    /// Drive the given line, reacting to the edges as the DM163 would.
+    pub fn set(&mut self, line: Line, level: bool) {
+        let previous = self.level(line);
+        match line {
+            Line::Sb => self.sb = level,
+            Line::Lat => self.lat = level,
+            Line::Rst => self.rst = level,
+            Line::Sck => self.sck = level,
+            Line::Sda => self.sda = level,
+            Line::Row(row) => self.rows[row] = level,
+        }
+        if !self.rst {
+            self.shift0 = [0; CHANNELS];
+            self.shift1 = [0; CHANNELS];
+            self.bank0 = [0; CHANNELS];
+            self.bank1 = [0; CHANNELS];
+            return;
+        }
+        match line {
+            Line::Sck if level && !previous => self.shift(),
+            Line::Lat if level && !previous => self.latch(),
+            _ => (),
+        }
+        self.refresh();
+    }
+
+    /// Shift the SDA bit into the shift register of the bank selected by SB.
+    fn shift(&mut self) {
+        let (shift, bits) = if self.sb { (&mut self.shift1, 8) } else { (&mut self.shift0, 6) };
+        let mut carry = self.sda as u8;
+        for channel in shift.iter_mut() {
+            let out = (*channel >> (bits - 1)) & 1;
+            *channel = ((*channel << 1) | carry) & ((1 << bits) - 1) as u8;
+            carry = out;
+        }
+    }
+
+    /// Copy the shift register into the bank selected by SB.
+    fn latch(&mut self) {
+        if self.sb {
+            self.bank1 = self.shift1;
+        } else {
+            self.bank0 = self.shift0;
+        }
+        self.latches += 1;
+    }
+
+    /// Update the colours displayed on the active rows.
+    fn refresh(&mut self) {
+        for row in 0..8 {
+            if self.rows[row] {
+                self.displayed[row] = self.output();
+            }
+        }
+    }
+
+    /// Values of the channels of bank1.
+    pub fn bank1(&self) -> &[u8; CHANNELS] {
+        &self.bank1
+    }
+
+    /// Values of the 6-bit dot correction channels of bank0.
+    pub fn bank0(&self) -> &[u8; CHANNELS] {
+        &self.bank0
+    }
+
+    /// Number of times a bank has been latched.
+    pub fn latches(&self) -> usize {
+        self.latches
+    }
+
+    /// Colours of the 8 columns, as latched in bank1.
+    pub fn colors(&self) -> [Color; 8] {
+        let mut colors = [Color::default(); 8];
+        for (col, color) in colors.iter_mut().enumerate() {
+            *color = Color { r: self.bank1[3 * col], g: self.bank1[3 * col + 1], b: self.bank1[3 * col + 2] };
+        }
+        colors
+    }
+
+    /// Colours produced by the LEDs of an active row: those latched in bank1, scaled
+    /// by the dot correction of bank0 (63 leaving them unchanged).
+    pub fn output(&self) -> [Color; 8] {
+        let scale = |channel: usize| ((self.bank1[channel] as u32 * self.bank0[channel] as u32 + 31) / 63) as u8;
+        let mut colors = [Color::default(); 8];
+        for (col, color) in colors.iter_mut().enumerate() {
+            *color = Color { r: scale(3 * col), g: scale(3 * col + 1), b: scale(3 * col + 2) };
+        }
+        colors
+    }
+
+    /// Rows which are currently switched on.
+    pub fn active_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..8).filter(move |&row| self.rows[row])
+    }
+
+    /// Image made of the last colours displayed on every row, dot correction included.
+    pub fn image(&self) -> Image {
+        let mut image = Image::default();
+        for row in 0..8 {
+            for col in 0..8 {
+                image[(row, col)] = self.displayed[row][col];
+            }
+        }
+        image
+    }
+}
+
+/// An output pin driving one line of a simulated DM163.
+pub struct SimPin<'a> {
+    dm163: &'a RefCell<Dm163>,
+    line: Line,
+}
+
+impl<'a> SimPin<'a> {
+    pub fn new(dm163: &'a RefCell<Dm163>, line: Line) -> Self {
+        SimPin { dm163, line }
+    }
+}
+
+impl OutputPin for SimPin<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.dm163.borrow_mut().set(self.line, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.dm163.borrow_mut().set(self.line, true);
+        Ok(())
+    }
+}
+
+/// A delay which returns immediately, only counting the requested time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SimDelay {
+    pub elapsed_ms: u32,
+}
+
+impl DelayMs<u8> for SimDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.elapsed_ms += ms as u32;
+    }
+}
+
+/// Matrix whose pins are all simulated.
+pub type SimMatrix<'a> = Matrix<SimPin<'a>, SimPin<'a>, SimPin<'a>, SimPin<'a>, SimPin<'a>, SimPin<'a>>;
+
+/// Create and initialize a matrix whose pins drive the given DM163.
+pub fn matrix(dm163: &RefCell<Dm163>) -> SimMatrix<'_> {
+    let pin = |line| SimPin::new(dm163, line);
+    Matrix::new(
+        pin(Line::Sb),
+        pin(Line::Lat),
+        pin(Line::Rst),
+        pin(Line::Sck),
+        pin(Line::Sda),
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|row| pin(Line::Row(row))),
+        &mut SimDelay::default(),
+    )
+}
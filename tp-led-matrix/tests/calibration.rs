@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+
+use tp_led_matrix::sim::{self, Dm163};
+use tp_led_matrix::{Calibration, Color};
+
+const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+
+#[test]
+fn apply() {
+    let color = Color { r: 200, g: 100, b: 255 };
+    assert_eq!(color, Calibration::default().apply(color));
+    let half = Calibration { brightness: 128, ..Default::default() };
+    assert_eq!(Color { r: 100, g: 50, b: 128 }, half.apply(color));
+    let warm = Calibration { white_balance: Color { r: 255, g: 204, b: 0 }, ..Default::default() };
+    assert_eq!(Color { r: 200, g: 80, b: 0 }, warm.apply(color));
+    let off = Calibration { brightness: 0, ..Default::default() };
+    assert_eq!(Color::default(), off.apply(color));
+}
+
+#[test]
+fn brightness_before_gamma() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    matrix.set_calibration(Calibration { brightness: 128, ..Default::default() });
+    matrix.send_row(0, &[WHITE; 8]);
+    let expected = Color { r: 128, g: 128, b: 128 }.gamma_correct();
+    assert_eq!([expected; 8], dm163.borrow().colors());
+}
+
+#[test]
+fn dot_correction() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    let latches = dm163.borrow().latches();
+    // Bank0 is only loaded again when the dot correction changes
+    matrix.set_calibration(Calibration { brightness: 10, ..Default::default() });
+    assert_eq!(latches, dm163.borrow().latches());
+    let dot_correction = Color { r: 63, g: 21, b: 0 };
+    matrix.set_calibration(Calibration { dot_correction, ..matrix.calibration() });
+    assert_eq!(latches + 1, dm163.borrow().latches());
+    let bank0 = *dm163.borrow().bank0();
+    assert_eq!([63, 21, 0].repeat(8), bank0);
+    matrix.set_calibration(Calibration { dot_correction, ..Default::default() });
+    matrix.send_row(4, &[WHITE; 8]);
+    assert_eq!([WHITE; 8], dm163.borrow().colors());
+    assert_eq!(Color { r: 255, g: 85, b: 0 }, dm163.borrow().image()[(4, 2)]);
+}
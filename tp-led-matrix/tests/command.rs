@@ -0,0 +1,146 @@
+use tp_led_matrix::command::{
+    crc8, Command, CommandDecoder, Error, Mode, Status, ESCAPE, NO_ANIMATION, ROTATING_GRADIENTS, START, VERSION,
+};
+use tp_led_matrix::framebuffer::FrameBuffers;
+use tp_led_matrix::gamma::{Curve, Gamma};
+use tp_led_matrix::{Calibration, Color, Image};
+
+fn encode(command: &Command) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    command.encode(|b| bytes.push(b));
+    bytes
+}
+
+/// Encode a command from its raw fields, with the given checksum.
+fn raw(fields: &[u8], crc: u8) -> Vec<u8> {
+    let mut bytes = vec![START];
+    for &b in fields.iter().chain(&[crc]) {
+        if b >= ESCAPE {
+            bytes.extend_from_slice(&[ESCAPE, b - ESCAPE]);
+        } else {
+            bytes.push(b);
+        }
+    }
+    bytes
+}
+
+fn checksum(fields: &[u8]) -> u8 {
+    fields.iter().fold(0, |crc, &b| crc8(crc, b))
+}
+
+/// Push all the bytes, and return the outcome of every complete command.
+fn decode_all(decoder: &mut CommandDecoder, bytes: &[u8]) -> Vec<Result<String, Error>> {
+    bytes
+        .iter()
+        .filter_map(|&b| decoder.push(b).map(|result| result.map(|command| format!("{:?}", command))))
+        .collect()
+}
+
+#[test]
+fn round_trip() {
+    let image = Image::gradient(Color::RED);
+    let commands = [
+        Command::SetImage(&image),
+        Command::SetPixel { row: 7, col: 0, color: Color { r: 0xff, g: 0xfe, b: 0xfd } },
+        Command::SetBrightness(0xff),
+        Command::PlayAnimation(1),
+        Command::QueryStatus,
+        Command::Status(Status { brightness: 12, animation: 1, commands: 0xfffe, errors: 300 }),
+        Command::SetWhiteBalance(Color { r: 0xff, g: 200, b: 0 }),
+        Command::SetDotCorrection(Color { r: 63, g: 0, b: 32 }),
+        Command::SetGamma(Gamma { r: Curve::Srgb, g: Curve::Linear, b: Curve::Gamma28 }),
+    ];
+    let mut decoder = CommandDecoder::new(Mode::Commands);
+    for command in &commands {
+        let bytes = encode(command);
+        // 0xff only starts the command
+        assert_eq!(1, bytes.iter().filter(|&&b| b == START).count());
+        assert_eq!(START, bytes[0]);
+        assert_eq!(vec![Ok(format!("{:?}", command))], decode_all(&mut decoder, &bytes));
+    }
+}
+
+#[test]
+fn layout() {
+    let fields = [VERSION, 1, 0x03, 0x80];
+    assert_eq!(raw(&fields, checksum(&fields)), encode(&Command::SetBrightness(0x80)));
+    // Stuffed bytes
+    let fields = [VERSION, 1, 0x03, 0xff];
+    let crc = checksum(&fields);
+    let mut expected = vec![START, VERSION, 1, 0x03, ESCAPE, 1];
+    if crc >= ESCAPE {
+        expected.extend_from_slice(&[ESCAPE, crc - ESCAPE]);
+    } else {
+        expected.push(crc);
+    }
+    assert_eq!(expected, encode(&Command::SetBrightness(0xff)));
+    assert_eq!(vec![START, VERSION, 0, 0x05, checksum(&[VERSION, 0, 0x05])], encode(&Command::QueryStatus));
+}
+
+#[test]
+fn rejected_commands() {
+    let mut decoder = CommandDecoder::new(Mode::Commands);
+    let fields = [VERSION, 1, 0x03, 0x80];
+    let crc = checksum(&fields);
+    assert_eq!(vec![Err(Error::Checksum)], decode_all(&mut decoder, &raw(&fields, crc ^ 1)));
+    let fields = [2, 1, 0x03, 0x80];
+    assert_eq!(vec![Err(Error::Version(2))], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    let fields = [VERSION, 0, 0x42];
+    assert_eq!(vec![Err(Error::Command(0x42))], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    let fields = [VERSION, 2, 0x03, 0x80, 0x80];
+    assert_eq!(vec![Err(Error::Length(2))], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    assert_eq!(vec![Err(Error::Length(193))], decode_all(&mut decoder, &[START, VERSION, 193]));
+    let fields = [VERSION, 5, 0x02, 8, 0, 1, 2, 3];
+    assert_eq!(vec![Err(Error::Pixel)], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    let fields = [VERSION, 3, 0x07, 63, 64, 0];
+    assert_eq!(vec![Err(Error::DotCorrection)], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    let fields = [VERSION, 3, 0x08, 2, 2, 5];
+    assert_eq!(vec![Err(Error::Curve(5))], decode_all(&mut decoder, &raw(&fields, checksum(&fields))));
+    assert_eq!(vec![Err(Error::Escape(2))], decode_all(&mut decoder, &[START, VERSION, ESCAPE, 2]));
+}
+
+#[test]
+fn resync() {
+    let mut decoder = CommandDecoder::new(Mode::Commands);
+    let query = encode(&Command::QueryStatus);
+    // Garbage, a truncated command, and the bytes following an error are ignored
+    let bytes = [&[1, 2, 3][..], &query[..3], &[START, 7, 1, 2, 3], &query].concat();
+    assert_eq!(
+        vec![Err(Error::Version(7)), Ok(String::from("QueryStatus"))],
+        decode_all(&mut decoder, &bytes)
+    );
+}
+
+#[test]
+fn legacy_mode() {
+    let mut decoder = CommandDecoder::new(Mode::Legacy);
+    assert_eq!(Mode::Legacy, decoder.mode());
+    let image = Image::new_solid(Color { r: 1, g: 2, b: 0xfe });
+    let mut bytes = vec![START];
+    bytes.extend_from_slice(image.as_ref());
+    let decoded: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b).map(|c| c.map(|c| c == Command::SetImage(&image)))).collect();
+    assert_eq!(vec![Ok(true)], decoded);
+}
+
+#[test]
+fn execute() {
+    let mut frames = FrameBuffers::default();
+    let mut calibration = Calibration::default();
+    let mut status = Status::default();
+    let mut execute = |command: Command| command.execute(&mut frames, &mut calibration, &mut status);
+    assert_eq!(None, execute(Command::PlayAnimation(ROTATING_GRADIENTS)));
+    assert_eq!(None, execute(Command::SetBrightness(42)));
+    assert_eq!(None, execute(Command::SetGamma(Gamma::uniform(Curve::Linear))));
+    assert_eq!(
+        Some(Status { brightness: 42, animation: ROTATING_GRADIENTS, commands: 4, errors: 0 }),
+        execute(Command::QueryStatus)
+    );
+    // Images stop the animation, and are displayed from the next frame
+    assert_eq!(None, execute(Command::SetImage(&Image::new_solid(Color::RED))));
+    assert_eq!(None, execute(Command::SetPixel { row: 0, col: 1, color: Color::BLUE }));
+    assert_eq!(NO_ANIMATION, status.animation);
+    assert_eq!(6, status.commands);
+    assert_eq!(42, calibration.brightness);
+    assert_eq!(Gamma::uniform(Curve::Linear), calibration.gamma);
+    assert_eq!([Color::RED, Color::BLUE, Color::RED], frames.row(0)[..3]);
+}
@@ -0,0 +1,65 @@
+use std::cell::RefCell;
+
+use tp_led_matrix::dither::{dither, FRAMES};
+use tp_led_matrix::gamma::Curve;
+use tp_led_matrix::sim::{self, Dm163};
+use tp_led_matrix::{Color, Image};
+
+/// Average of the value displayed over a dithering cycle.
+fn average(value: u16, offset: usize) -> f64 {
+    (0..FRAMES).map(|phase| dither(value, offset + phase) as f64).sum::<f64>() / FRAMES as f64
+}
+
+#[test]
+fn dither_values() {
+    for value in (0..=0xff00).step_by(7) {
+        for offset in 0..FRAMES {
+            let error = average(value, offset) - value as f64 / 256.0;
+            assert!(error.abs() <= 0.5 / FRAMES as f64, "value {:#06x}, error {}", value, error);
+        }
+    }
+    // Integers are displayed as they are, and 1.25 is rounded up in 2 frames out of 8
+    assert!((0..FRAMES).all(|phase| dither(0x1000, phase) == 0x10 && dither(0xff00, phase) == 0xff));
+    let values: Vec<u8> = (0..FRAMES).map(|phase| dither(0x0140, phase)).collect();
+    assert_eq!(2, values.iter().filter(|&&v| v == 2).count());
+    assert_eq!(6, values.iter().filter(|&&v| v == 1).count());
+    // The frames where the value is rounded up are spread over the cycle
+    assert_ne!(values[0], values[1]);
+}
+
+#[test]
+fn time_averaged_output() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    matrix.set_dithering(true);
+    // Dark gradient, mostly collapsed to 0 and 1 by gamma correction
+    let mut image = Image::default();
+    for row in 0..8 {
+        for col in 0..8 {
+            let v = (row * 8 + col) as u8;
+            image[(row, col)] = Color { r: v, g: v / 2, b: 40 - v / 2 };
+        }
+    }
+    let mut sums = [[0.0; 3]; 64];
+    for _ in 0..FRAMES {
+        matrix.display_image(&image);
+        let displayed = dm163.borrow().image();
+        for (sum, color) in sums.iter_mut().zip(displayed.as_ref().chunks(3)) {
+            for (s, &c) in sum.iter_mut().zip(color) {
+                *s += c as f64;
+            }
+        }
+    }
+    let fine = Curve::default().fine_table();
+    for (sum, color) in sums.iter().zip(image.as_ref().chunks(3)) {
+        for (s, &c) in sum.iter().zip(color) {
+            let error = s / FRAMES as f64 - fine[c as usize] as f64 / 256.0;
+            assert!(error.abs() <= 0.5 / FRAMES as f64, "component {}, error {}", c, error);
+        }
+    }
+    // Without dithering, these components are displayed as the gamma table gives them
+    matrix.set_dithering(false);
+    matrix.display_image(&image);
+    let expected: Vec<u8> = image.as_ref().iter().map(|&c| Curve::default().table()[c as usize]).collect();
+    assert_eq!(expected, dm163.borrow().image().as_ref());
+}
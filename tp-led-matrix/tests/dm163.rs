@@ -0,0 +1,121 @@
+use std::cell::{Cell, RefCell};
+
+use embedded_hal::digital::v2::OutputPin;
+use tp_led_matrix::matrix::Matrix;
+use tp_led_matrix::sim::{self, Dm163, Line, SimDelay, SimPin};
+use tp_led_matrix::{Color, Image};
+
+#[test]
+fn initialization() {
+    let dm163 = RefCell::new(Dm163::new());
+    let _matrix = sim::matrix(&dm163);
+    let dm163 = dm163.borrow();
+    assert!(dm163.level(Line::Rst));
+    assert!(dm163.level(Line::Sb));
+    assert!(dm163.level(Line::Lat));
+    // Bank0 is filled with ones, bank1 is left untouched
+    assert_eq!(&[0x3f; 24], dm163.bank0());
+    assert_eq!(&[0; 24], dm163.bank1());
+    assert_eq!(1, dm163.latches());
+    assert_eq!(0, dm163.active_rows().count());
+}
+
+#[test]
+fn reset_clears_registers() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    matrix.send_row(2, &[Color::GREEN; 8]);
+    assert_eq!([Color::GREEN; 8], dm163.borrow().colors());
+    dm163.borrow_mut().set(Line::Rst, false);
+    assert_eq!(&[0; 24], dm163.borrow().bank0());
+    assert_eq!([Color::default(); 8], dm163.borrow().colors());
+}
+
+#[test]
+fn send_row_switches_rows() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    let mut pixels = [Color::default(); 8];
+    pixels[0] = Color { r: 100, g: 150, b: 200 };
+    pixels[7] = Color::BLUE;
+    matrix.send_row(0, &pixels);
+    assert_eq!(vec![0], dm163.borrow().active_rows().collect::<Vec<_>>());
+    // The first byte sent (blue of the last column) ends up in the last channel
+    let bank1 = *dm163.borrow().bank1();
+    let corrected = pixels[0].gamma_correct();
+    assert_eq!([corrected.r, corrected.g, corrected.b], bank1[..3]);
+    assert_eq!([0, 0, 255], bank1[21..]);
+    matrix.send_row(1, &pixels);
+    assert_eq!(vec![1], dm163.borrow().active_rows().collect::<Vec<_>>());
+    // Rows are expected to be sent in order, only the previous one is switched off
+    matrix.send_row(3, &pixels);
+    assert_eq!(vec![1, 3], dm163.borrow().active_rows().collect::<Vec<_>>());
+}
+
+#[test]
+fn display_gradient() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    let image = Image::gradient(Color::RED);
+    matrix.display_image(&image);
+    let displayed = dm163.borrow().image();
+    for row in 0..8 {
+        for col in 0..8 {
+            assert_eq!(image[(row, col)].gamma_correct(), displayed[(row, col)], "pixel ({}, {})", row, col);
+        }
+    }
+    assert_eq!(Color::RED, displayed[(0, 0)]);
+    assert_eq!(Color { r: 0x37, g: 0, b: 0 }, displayed[(0, 1)]);
+    assert_eq!(Color { r: 0x37, g: 0, b: 0 }, displayed[(1, 0)]);
+    // The last row stays on, in BGR order from the last column down to the first one
+    assert_eq!(vec![7], dm163.borrow().active_rows().collect::<Vec<_>>());
+    let bank1 = dm163.borrow().bank1().iter().rev().copied().collect::<Vec<_>>();
+    let expected = image
+        .row(7)
+        .iter()
+        .rev()
+        .flat_map(|color| {
+            let color = color.gamma_correct();
+            [color.b, color.g, color.r]
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(expected, bank1);
+}
+
+/// Row pin which cannot be driven, counting the attempts.
+struct BrokenPin<'a>(&'a Cell<u32>);
+
+impl OutputPin for BrokenPin<'_> {
+    type Error = ();
+
+    fn set_low(&mut self) -> Result<(), ()> {
+        self.0.set(self.0.get() + 1);
+        Err(())
+    }
+
+    fn set_high(&mut self) -> Result<(), ()> {
+        self.0.set(self.0.get() + 1);
+        Err(())
+    }
+}
+
+#[test]
+fn pin_errors_are_ignored() {
+    let dm163 = RefCell::new(Dm163::new());
+    let attempts = Cell::new(0);
+    let pin = |line| SimPin::new(&dm163, line);
+    let mut matrix = Matrix::new(
+        pin(Line::Sb),
+        pin(Line::Lat),
+        pin(Line::Rst),
+        pin(Line::Sck),
+        pin(Line::Sda),
+        [(); 8].map(|_| BrokenPin(&attempts)),
+        &mut SimDelay::default(),
+    );
+    assert_eq!(8, attempts.get());
+    matrix.send_row(1, &[Color::RED; 8]);
+    assert_eq!(10, attempts.get());
+    assert_eq!([Color::RED; 8], dm163.borrow().colors());
+    assert_eq!(0, dm163.borrow().active_rows().count());
+}
@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+
+use tp_led_matrix::framebuffer::FrameBuffers;
+use tp_led_matrix::sim::{self, Dm163};
+use tp_led_matrix::{Color, Image};
+
+#[test]
+fn swap_at_row_zero() {
+    let mut frames = FrameBuffers::new(&Image::new_solid(Color::RED));
+    assert_eq!(0, frames.frames());
+    assert_eq!([Color::RED; 8], frames.row(0));
+    assert_eq!(1, frames.frames());
+    *frames.back() = Image::new_solid(Color::GREEN);
+    // Drawing does not change the displayed image
+    assert_eq!([Color::RED; 8], frames.row(1));
+    frames.publish();
+    assert!(frames.is_pending());
+    // The rest of the frame still shows the previous image
+    for row in 2..8 {
+        assert_eq!([Color::RED; 8], frames.row(row));
+    }
+    assert_eq!([Color::GREEN; 8], frames.row(0));
+    assert!(!frames.is_pending());
+    assert_eq!(&Image::new_solid(Color::GREEN), frames.front());
+    assert_eq!(2, frames.frames());
+}
+
+#[test]
+fn back_buffer_follows_published_images() {
+    let mut frames = FrameBuffers::default();
+    // The back buffer starts as a copy of the last published image, so that pixels
+    // can be set one by one
+    frames.back()[(0, 0)] = Color::RED;
+    frames.publish();
+    frames.back()[(0, 1)] = Color::BLUE;
+    frames.publish();
+    // Only the last published image is displayed, the previous one is dropped
+    let row = frames.row(0);
+    assert_eq!([Color::RED, Color::BLUE], row[..2]);
+    assert_eq!(Color::default(), row[2]);
+    let front = frames.front().clone();
+    assert_eq!(&front, frames.back());
+    // Publishing many images during a frame never changes the displayed one
+    for i in 0..10 {
+        *frames.back() = Image::new_solid(Color { r: i, g: 0, b: 0 });
+        frames.publish();
+        frames.row(1 + i as usize % 7);
+        assert_eq!(&front, frames.front());
+    }
+    assert_eq!([Color { r: 9, g: 0, b: 0 }; 8], frames.row(0));
+}
+
+#[test]
+fn no_tearing_on_the_matrix() {
+    let dm163 = RefCell::new(Dm163::new());
+    let mut matrix = sim::matrix(&dm163);
+    let mut frames = FrameBuffers::default();
+    let mut shown = Vec::new();
+    for frame in 0..6u8 {
+        for row in 0..8 {
+            // A new image is published in the middle of every frame
+            if row == 4 {
+                *frames.back() = Image::new_solid(Color { r: 0, g: 50 * frame, b: 0 });
+                frames.publish();
+            }
+            matrix.send_row(row, frames.row(row));
+        }
+        shown.push(dm163.borrow().image());
+    }
+    // Every frame shows a single image, the one published during the previous frame
+    assert_eq!(Image::default(), shown[0]);
+    for frame in 1..6u8 {
+        let color = Color { r: 0, g: 50 * (frame - 1), b: 0 };
+        assert_eq!(Image::new_solid(color.gamma_correct()), shown[frame as usize]);
+    }
+    assert_eq!(6, frames.frames());
+}
@@ -0,0 +1,49 @@
+use tp_led_matrix::gamma::{gamma_correct, table, Curve, Gamma};
+use tp_led_matrix::Color;
+
+fn check_table(table: &[u8; 256]) {
+    assert_eq!(0, table[0]);
+    assert_eq!(255, table[255]);
+    assert!(table.windows(2).all(|w| w[0] <= w[1]), "{:?} is not monotonic", table);
+}
+
+#[test]
+fn endpoints_and_monotonicity() {
+    for curve in Curve::ALL {
+        check_table(curve.table());
+    }
+}
+
+#[test]
+fn generated_values() {
+    for i in 0..=255 {
+        let x = i as f64 / 255.0;
+        let expected = |e: f64| (x.powf(e) * 255.0).round() as u8;
+        assert_eq!(i, Curve::Linear.table()[i as usize]);
+        assert_eq!(expected(1.8), Curve::Gamma18.table()[i as usize], "{}", i);
+        assert_eq!(expected(2.2), Curve::Gamma22.table()[i as usize], "{}", i);
+        assert_eq!(expected(2.8), Curve::Gamma28.table()[i as usize], "{}", i);
+    }
+    // The tables can be generated at compile time
+    const GAMMA_22: [u8; 256] = table(Curve::Gamma22);
+    assert_eq!(*Curve::Gamma22.table(), GAMMA_22);
+    // sRGB is linear near 0, and brighter than an exponent of 2.2 in the dark tones
+    assert_eq!(1, Curve::Srgb.table()[10]);
+    assert_eq!(0, Curve::Gamma22.table()[10]);
+    assert_eq!(55, Curve::Srgb.table()[128]);
+}
+
+#[test]
+fn curve_selection() {
+    assert_eq!(Curve::Gamma22, Curve::default());
+    assert_eq!(Curve::Gamma22.table()[100], gamma_correct(100));
+    for curve in Curve::ALL {
+        assert_eq!(Some(curve), Curve::from_id(curve.id()));
+    }
+    assert_eq!(None, Curve::from_id(5));
+    let gamma = Gamma { r: Curve::Linear, g: Curve::Gamma28, b: Curve::Srgb };
+    let color = Color { r: 100, g: 100, b: 100 };
+    let corrected = Color { r: 100, g: Curve::Gamma28.table()[100], b: Curve::Srgb.table()[100] };
+    assert_eq!(corrected, gamma.correct(color));
+    assert_eq!(color.gamma_correct(), Gamma::uniform(Curve::Gamma22).correct(color));
+}
@@ -0,0 +1,59 @@
+use tp_led_matrix::protocol::{FrameDecoder, FRAME_SIZE};
+use tp_led_matrix::{Color, Image};
+
+/// Push all the bytes, and return the images completed along the way.
+fn push_all(decoder: &mut FrameDecoder, bytes: &[u8]) -> Vec<Vec<u8>> {
+    bytes.iter().filter_map(|&b| decoder.push(b).map(|image| image.as_ref().to_vec())).collect()
+}
+
+fn frame(image: &Image) -> Vec<u8> {
+    let mut frame = vec![0xff];
+    frame.extend_from_slice(image.as_ref());
+    frame
+}
+
+#[test]
+fn complete_frames() {
+    let mut decoder = FrameDecoder::new();
+    let image = Image::gradient(Color { r: 0, g: 100, b: 200 });
+    let mut bytes = frame(&image);
+    let last = bytes.pop().unwrap();
+    assert!(push_all(&mut decoder, &bytes).is_empty());
+    assert!(decoder.in_frame());
+    assert_eq!(image.as_ref(), decoder.push(last).unwrap().as_ref());
+    assert!(!decoder.in_frame());
+    // Two frames in a row
+    let other = Image::new_solid(Color { r: 1, g: 2, b: 3 });
+    let bytes = [frame(&image), frame(&other)].concat();
+    assert_eq!(vec![image.as_ref().to_vec(), other.as_ref().to_vec()], push_all(&mut decoder, &bytes));
+}
+
+#[test]
+fn resync_on_marker() {
+    let mut decoder = FrameDecoder::new();
+    // Bytes received before the first marker are ignored
+    assert!(push_all(&mut decoder, &[1, 2, 3]).is_empty());
+    assert!(!decoder.in_frame());
+    let image = Image::new_solid(Color { r: 10, g: 20, b: 30 });
+    assert_eq!(vec![image.as_ref().to_vec()], push_all(&mut decoder, &[&[1, 2, 3][..], &frame(&image)].concat()));
+}
+
+#[test]
+fn truncated_frames() {
+    let mut decoder = FrameDecoder::new();
+    let truncated = frame(&Image::new_solid(Color { r: 7, g: 7, b: 7 }));
+    let image = Image::new_solid(Color { r: 1, g: 0, b: 0 });
+    let bytes = [&truncated[..FRAME_SIZE / 2], &truncated[..FRAME_SIZE], &frame(&image)].concat();
+    assert_eq!(vec![image.as_ref().to_vec()], push_all(&mut decoder, &bytes));
+}
+
+#[test]
+fn excess_bytes() {
+    let mut decoder = FrameDecoder::new();
+    let image = Image::new_solid(Color { r: 0, g: 5, b: 0 });
+    let bytes = [frame(&image), vec![9; 2 * FRAME_SIZE]].concat();
+    // Bytes following a complete image neither make a new image nor overflow
+    assert_eq!(vec![image.as_ref().to_vec()], push_all(&mut decoder, &bytes));
+    assert!(!decoder.in_frame());
+    assert_eq!(vec![image.as_ref().to_vec()], push_all(&mut decoder, &frame(&image)));
+}
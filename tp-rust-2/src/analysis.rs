@@ -0,0 +1,454 @@
+//! Control-flow graph recovery and static checks of program images.
+//!
+//! Jumps are writes to r0, so the control flow is recovered by following
+//! the constants flowing into it from address 0:
+//!   - `loadimm r0 <- #target` jumps to `target`;
+//!   - `move r0 <- rX if rY != 0` jumps to the value given to rX by a
+//!     previous `loadimm` of the same block, if rY is not zero;
+//!   - a jump preceded by the storage of the address of the following
+//!     instruction (`loadimm r3 <- #return_point` then `store [r2] <- r3`)
+//!     is a call;
+//!   - `load r0 <- [..]` returns to any of the return points of calls.
+//!
+//! Any other write to r0 is a jump to an unknown destination.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+use crate::{Instruction, IP, NREGS};
+
+/// Registers read by an instruction. The destination of a conditional move is
+/// considered as read, since it is kept when the condition is false.
+pub(crate) fn reads(instr: &Instruction) -> Vec<usize> {
+    match *instr {
+        Instruction::MoveIf { dest, src, cond } => vec![dest, src, cond],
+        Instruction::Store { addr, src } => vec![addr, src],
+        Instruction::Load { addr, .. } => vec![addr],
+        Instruction::LoadImm { .. } | Instruction::Exit => vec![],
+        Instruction::Sub { op1, op2, .. } => vec![op1, op2],
+        Instruction::Out { reg } | Instruction::OutNumber { reg } => vec![reg],
+    }
+}
+
+/// Register written by an instruction, if any. A conditional move counts as a write.
+pub(crate) fn writes(instr: &Instruction) -> Option<usize> {
+    match *instr {
+        Instruction::MoveIf { dest, .. }
+        | Instruction::Load { dest, .. }
+        | Instruction::LoadImm { dest, .. }
+        | Instruction::Sub { dest, .. } => Some(dest),
+        _ => None,
+    }
+}
+
+/// How the execution leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    /// Continue with the following instruction.
+    FallThrough(usize),
+    Jump(usize),
+    /// Conditional jump to `taken`, or continue with `not_taken`.
+    Branch { taken: usize, not_taken: usize },
+    /// Jump to `target`, which is expected to return to `return_to`.
+    Call { target: usize, return_to: usize },
+    /// Jump to an address read from memory, normally a return point.
+    Return,
+    Exit,
+    /// Jump to an address which could not be determined.
+    Unknown,
+}
+
+/// A sequence of instructions only entered at its first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    /// Instructions with their address.
+    pub instructions: Vec<(usize, Instruction)>,
+    pub terminator: Terminator,
+}
+
+impl Block {
+    /// Address following the last instruction of the block.
+    pub fn end(&self) -> usize {
+        self.instructions.last().map_or(self.start, |&(addr, instr)| addr + instr.size())
+    }
+}
+
+/// Problem found by [Analysis::analyze].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// Instructions at `start..end` can never be executed.
+    Unreachable { start: usize, end: usize },
+    /// The instruction at `addr` may continue with `next`, which is not a valid instruction.
+    FallThroughIntoData { addr: usize, next: usize },
+    /// The instruction at `addr` may jump to `target`, which is not a valid instruction.
+    JumpIntoData { addr: usize, target: usize },
+    /// The instruction at `addr` jumps to an address which could not be determined.
+    UnknownJump { addr: usize },
+    /// The instruction at `addr` reads `reg`, which cannot have been written
+    /// yet, thus reads its initial value.
+    UninitializedRead { addr: usize, reg: usize },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::Unreachable { start, end } => write!(f, "{:04}-{:04}: unreachable code", start, end),
+            Diagnostic::FallThroughIntoData { addr, next } => {
+                write!(f, "{:04}: execution may continue into data at {:04}", addr, next)
+            }
+            Diagnostic::JumpIntoData { addr, target } => write!(f, "{:04}: jump into data at {:04}", addr, target),
+            Diagnostic::UnknownJump { addr } => write!(f, "{:04}: jump to an unknown address", addr),
+            Diagnostic::UninitializedRead { addr, reg } => {
+                write!(f, "{:04}: r{} is read before being written", addr, reg)
+            }
+        }
+    }
+}
+
+/// Control-flow graph of a program image.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    /// Basic blocks reachable from address 0, indexed by their first address.
+    pub blocks: BTreeMap<usize, Block>,
+    /// Address of every `loadimm` which loads a code address, with this address.
+    pub code_addresses: BTreeMap<usize, usize>,
+    /// Return points of the calls to the function containing each block which
+    /// ends with a return. Returns outside of any called function may go to
+    /// any return point.
+    pub returns: BTreeMap<usize, BTreeSet<usize>>,
+}
+
+impl Cfg {
+    /// Recover the control-flow graph of a program image.
+    pub fn new(image: &[u8]) -> Self {
+        recover(image).0
+    }
+
+    /// Addresses of the blocks which may follow the block starting at `start`.
+    pub fn successors(&self, start: usize) -> Vec<usize> {
+        match self.blocks[&start].terminator {
+            Terminator::FallThrough(next) | Terminator::Jump(next) => vec![next],
+            Terminator::Branch { taken, not_taken } => vec![taken, not_taken],
+            Terminator::Call { target, .. } => vec![target],
+            Terminator::Return => match self.returns.get(&start) {
+                Some(points) => points.iter().copied().collect(),
+                None => self.return_points().into_iter().collect(),
+            },
+            Terminator::Exit | Terminator::Unknown => vec![],
+        }
+        .into_iter()
+        .filter(|addr| self.blocks.contains_key(addr))
+        .collect()
+    }
+
+    /// Addresses to which calls are expected to return.
+    pub fn return_points(&self) -> BTreeSet<usize> {
+        self.blocks
+            .values()
+            .filter_map(|b| match b.terminator {
+                Terminator::Call { return_to, .. } => Some(return_to),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Reachable instructions with their address, in address order.
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, Instruction)> + '_ {
+        self.blocks.values().flat_map(|b| b.instructions.iter().copied())
+    }
+
+    /// Graphviz description of the graph.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for &(addr, instr) in &block.instructions {
+                let _ = write!(label, "{:04}   {}\\l", addr, instr);
+            }
+            let _ = writeln!(dot, "    b{} [label=\"{}\"];", block.start, label);
+            let edges: Vec<(usize, &str)> = match block.terminator {
+                Terminator::FallThrough(next) => vec![(next, "")],
+                Terminator::Jump(target) => vec![(target, "")],
+                Terminator::Branch { taken, not_taken } => vec![(taken, "taken"), (not_taken, "not taken")],
+                Terminator::Call { target, return_to } => vec![(target, "call"), (return_to, "return")],
+                Terminator::Return | Terminator::Exit | Terminator::Unknown => vec![],
+            };
+            for (to, label) in edges {
+                if !self.blocks.contains_key(&to) {
+                    continue;
+                }
+                let attributes = match label {
+                    "" => String::new(),
+                    "return" => String::from(" [label=\"return\", style=dashed]"),
+                    _ => format!(" [label=\"{}\"]", label),
+                };
+                let _ = writeln!(dot, "    b{} -> b{}{};", block.start, to, attributes);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Result of the analysis of a program image.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub cfg: Cfg,
+    /// Problems found, sorted by address.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Recover the control-flow graph of `image` and check it.
+    pub fn analyze(image: &[u8]) -> Self {
+        let (cfg, mut diagnostics) = recover(image);
+        diagnostics.extend(unreachable(image, &cfg));
+        diagnostics.extend(uninitialized_reads(&cfg));
+        diagnostics.sort_by_key(|d| match *d {
+            Diagnostic::Unreachable { start: addr, .. }
+            | Diagnostic::FallThroughIntoData { addr, .. }
+            | Diagnostic::JumpIntoData { addr, .. }
+            | Diagnostic::UnknownJump { addr }
+            | Diagnostic::UninitializedRead { addr, .. } => addr,
+        });
+        diagnostics.dedup();
+        Analysis { cfg, diagnostics }
+    }
+}
+
+/// Follow the control flow from address 0 and build the basic blocks.
+pub(crate) fn recover(image: &[u8]) -> (Cfg, Vec<Diagnostic>) {
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut terminators: BTreeMap<usize, Terminator> = BTreeMap::new();
+    let mut code_addresses = BTreeMap::new();
+    let mut leaders = BTreeSet::from([0]);
+    let mut diagnostics = Vec::new();
+    // Addresses to explore, with the instruction leading there and whether it is a jump.
+    let mut work: Vec<(usize, Option<(usize, bool)>)> = vec![(0, None)];
+    while let Some((mut addr, mut from)) = work.pop() {
+        let mut known: [Option<u32>; NREGS] = [None; NREGS];
+        // Address of the `loadimm` which gave its value to each register.
+        let mut loaded: [Option<usize>; NREGS] = [None; NREGS];
+        // Values stored since the beginning of the path, with the `loadimm` which produced them.
+        let mut stored: Vec<(u32, usize)> = Vec::new();
+        while !code.contains_key(&addr) {
+            let Some(Ok(instr)) = image.get(addr..).map(Instruction::decode) else {
+                diagnostics.push(match from {
+                    Some((from, true)) => Diagnostic::JumpIntoData { addr: from, target: addr },
+                    Some((from, false)) => Diagnostic::FallThroughIntoData { addr: from, next: addr },
+                    None => Diagnostic::FallThroughIntoData { addr, next: addr },
+                });
+                break;
+            };
+            code.insert(addr, instr);
+            let next = addr + instr.size();
+            let target = match instr {
+                Instruction::LoadImm { dest: IP, value } => {
+                    code_addresses.insert(addr, value as usize);
+                    Some(value as usize)
+                }
+                Instruction::MoveIf { dest: IP, src, cond } => match (known[src], loaded[src]) {
+                    (Some(target), Some(at)) => {
+                        code_addresses.insert(at, target as usize);
+                        if cond != IP {
+                            terminators.insert(addr, Terminator::Branch { taken: target as usize, not_taken: next });
+                            leaders.insert(next);
+                            work.push((next, Some((addr, false))));
+                        }
+                        Some(target as usize)
+                    }
+                    _ => {
+                        terminators.insert(addr, Terminator::Unknown);
+                        diagnostics.push(Diagnostic::UnknownJump { addr });
+                        break;
+                    }
+                },
+                Instruction::Load { dest: IP, .. } => {
+                    terminators.insert(addr, Terminator::Return);
+                    break;
+                }
+                Instruction::Exit => {
+                    terminators.insert(addr, Terminator::Exit);
+                    break;
+                }
+                _ if writes(&instr) == Some(IP) => {
+                    terminators.insert(addr, Terminator::Unknown);
+                    diagnostics.push(Diagnostic::UnknownJump { addr });
+                    break;
+                }
+                Instruction::LoadImm { dest, value } => {
+                    known[dest] = Some(value as i32 as u32);
+                    loaded[dest] = Some(addr);
+                    None
+                }
+                Instruction::Store { src, .. } => {
+                    if let (Some(value), Some(at)) = (known[src], loaded[src]) {
+                        stored.push((value, at));
+                    }
+                    None
+                }
+                _ => {
+                    if let Some(dest) = writes(&instr) {
+                        known[dest] = None;
+                        loaded[dest] = None;
+                    }
+                    None
+                }
+            };
+            if let Some(target) = target {
+                leaders.insert(target);
+                work.push((target, Some((addr, true))));
+                terminators.entry(addr).or_insert(Terminator::Jump(target));
+                // A jump after storing the address of the following instruction is a call.
+                if let Some(&(_, at)) = stored.iter().find(|&&(value, _)| value as usize == next) {
+                    if terminators[&addr] == Terminator::Jump(target) {
+                        code_addresses.insert(at, next);
+                        terminators.insert(addr, Terminator::Call { target, return_to: next });
+                        leaders.insert(next);
+                        work.push((next, Some((addr, false))));
+                    }
+                }
+                break;
+            }
+            from = Some((addr, false));
+            addr = next;
+        }
+    }
+
+    // Group the instructions into blocks.
+    let mut blocks: BTreeMap<usize, Block> = BTreeMap::new();
+    let mut current: Option<Block> = None;
+    for (&addr, &instr) in &code {
+        if let Some(block) = current.take() {
+            if leaders.contains(&addr) || block.end() != addr {
+                let end = block.end();
+                blocks.insert(block.start, Block { terminator: Terminator::FallThrough(end), ..block });
+            } else {
+                current = Some(block);
+            }
+        }
+        let block = current.get_or_insert(Block { start: addr, instructions: Vec::new(), terminator: Terminator::Unknown });
+        block.instructions.push((addr, instr));
+        if let Some(&terminator) = terminators.get(&addr) {
+            let block = current.take().unwrap();
+            blocks.insert(block.start, Block { terminator, ..block });
+        }
+    }
+    if let Some(block) = current {
+        let end = block.end();
+        blocks.insert(block.start, Block { terminator: Terminator::FallThrough(end), ..block });
+    }
+    let returns = returns(&blocks);
+    (Cfg { blocks, code_addresses, returns }, diagnostics)
+}
+
+/// Match the returns with the calls to the function they belong to. A
+/// function is made of the blocks reachable from its entry without entering
+/// other calls.
+fn returns(blocks: &BTreeMap<usize, Block>) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut callers: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for block in blocks.values() {
+        if let Terminator::Call { target, return_to } = block.terminator {
+            callers.entry(target).or_default().insert(return_to);
+        }
+    }
+    let mut returns: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for (&entry, points) in &callers {
+        let mut seen = BTreeSet::new();
+        let mut work = vec![entry];
+        while let Some(start) = work.pop() {
+            let Some(block) = blocks.get(&start) else { continue };
+            if !seen.insert(start) {
+                continue;
+            }
+            match block.terminator {
+                Terminator::FallThrough(next) | Terminator::Jump(next) => work.push(next),
+                Terminator::Branch { taken, not_taken } => work.extend([taken, not_taken]),
+                Terminator::Call { return_to, .. } => work.push(return_to),
+                Terminator::Return => returns.entry(start).or_default().extend(points),
+                Terminator::Exit | Terminator::Unknown => (),
+            }
+        }
+    }
+    returns
+}
+
+/// Unreachable sequences of valid instructions.
+fn unreachable(image: &[u8], cfg: &Cfg) -> Vec<Diagnostic> {
+    let mut reachable = vec![false; image.len()];
+    for (addr, instr) in cfg.instructions() {
+        let end = (addr + instr.size()).min(image.len());
+        reachable[addr..end].fill(true);
+    }
+    let mut diagnostics = Vec::new();
+    let mut addr = 0;
+    while addr < image.len() {
+        if reachable[addr] {
+            addr += 1;
+            continue;
+        }
+        // Decode from the start of the unreachable range, stopping at the
+        // first byte which is not a valid instruction, which is data.
+        let start = addr;
+        while let Ok(instr) = Instruction::decode(&image[addr..]) {
+            if reachable[addr..addr + instr.size()].contains(&true) {
+                break;
+            }
+            addr += instr.size();
+        }
+        if addr > start {
+            diagnostics.push(Diagnostic::Unreachable { start, end: addr });
+        }
+        while addr < image.len() && !reachable[addr] {
+            addr += 1;
+        }
+    }
+    diagnostics
+}
+
+/// Reads of registers which no path from address 0 has written.
+fn uninitialized_reads(cfg: &Cfg) -> Vec<Diagnostic> {
+    // Registers which may have been written when entering each block.
+    let mut written: BTreeMap<usize, u32> = BTreeMap::new();
+    let mut work = vec![0];
+    if cfg.blocks.contains_key(&0) {
+        written.insert(0, 1 << IP);
+    }
+    while let Some(start) = work.pop() {
+        let mut state = written[&start];
+        for (_, instr) in &cfg.blocks[&start].instructions {
+            if let Some(dest) = writes(instr) {
+                state |= 1 << dest;
+            }
+        }
+        for next in cfg.successors(start) {
+            let before = written.get(&next).copied();
+            let after = before.map_or(state, |w| w | state);
+            if before != Some(after) {
+                written.insert(next, after);
+                work.push(next);
+            }
+        }
+    }
+    let mut diagnostics = Vec::new();
+    for (start, block) in &cfg.blocks {
+        let Some(&state) = written.get(start) else { continue };
+        let mut state = state;
+        for &(addr, instr) in &block.instructions {
+            let read = match instr {
+                Instruction::MoveIf { src, cond, .. } => vec![src, cond],
+                _ => reads(&instr),
+            };
+            for reg in read {
+                if state & (1 << reg) == 0 {
+                    diagnostics.push(Diagnostic::UninitializedRead { addr, reg });
+                }
+            }
+            if let Some(dest) = writes(&instr) {
+                state |= 1 << dest;
+            }
+        }
+    }
+    diagnostics
+}
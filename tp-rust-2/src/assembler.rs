@@ -0,0 +1,155 @@
+//! This module turns a list of instructions, labels and data into a memory
+//! image, and prints it as a listing in the same format as the `.dis` files.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{listing_line, Instruction, MEMORY_SIZE};
+
+/// An element of an assembly program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Names the address of the next item.
+    Label(String),
+    /// A regular instruction.
+    Instr(Instruction),
+    /// `loadimm dest <- #label`, loading the address of a label into a register.
+    LoadLabel { dest: usize, label: String },
+    /// Raw bytes, such as string literals.
+    Data(Vec<u8>),
+}
+
+impl From<Instruction> for Item {
+    fn from(instr: Instruction) -> Self {
+        Item::Instr(instr)
+    }
+}
+
+impl Item {
+    /// Size in bytes of the item once assembled.
+    pub fn size(&self) -> usize {
+        match self {
+            Item::Label(_) => 0,
+            Item::Instr(instr) => instr.size(),
+            Item::LoadLabel { .. } => 4,
+            Item::Data(bytes) => bytes.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblyError {
+    /// A label is defined more than once.
+    DuplicateLabel(String),
+    /// A label is used but never defined.
+    UndefinedLabel(String),
+    /// The assembled program does not fit in the machine memory.
+    TooBig(usize),
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblyError::DuplicateLabel(label) => write!(f, "label `{}` is defined more than once", label),
+            AssemblyError::UndefinedLabel(label) => write!(f, "label `{}` is not defined", label),
+            AssemblyError::TooBig(size) => {
+                write!(f, "program needs {} bytes but memory is only {} bytes", size, MEMORY_SIZE)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// An assembly program, made of items laid out one after the other from address 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembly {
+    pub items: Vec<Item>,
+}
+
+impl Assembly {
+    /// Create an empty program.
+    pub fn new() -> Self {
+        Assembly { items: Vec::new() }
+    }
+
+    /// Append an item at the end of the program.
+    pub fn push(&mut self, item: Item) {
+        self.items.push(item);
+    }
+
+    /// Compute the address of every label.
+    pub fn labels(&self) -> Result<HashMap<String, usize>, AssemblyError> {
+        let mut labels = HashMap::new();
+        let mut addr = 0;
+        for item in &self.items {
+            if let Item::Label(name) = item {
+                if labels.insert(name.clone(), addr).is_some() {
+                    return Err(AssemblyError::DuplicateLabel(name.clone()));
+                }
+            }
+            addr += item.size();
+        }
+        if addr > MEMORY_SIZE {
+            return Err(AssemblyError::TooBig(addr));
+        }
+        Ok(labels)
+    }
+
+    /// Produce the memory image of the program.
+    pub fn assemble(&self) -> Result<Vec<u8>, AssemblyError> {
+        let labels = self.labels()?;
+        let mut image = Vec::new();
+        for item in &self.items {
+            match item {
+                Item::Label(_) => (),
+                Item::Instr(instr) => image.extend(instr.encode()),
+                Item::LoadLabel { dest, label } => {
+                    let addr = *labels
+                        .get(label)
+                        .ok_or_else(|| AssemblyError::UndefinedLabel(label.clone()))?;
+                    // Addresses are below MEMORY_SIZE, so they always fit in the immediate.
+                    let value = addr as i16;
+                    image.extend(Instruction::LoadImm { dest: *dest, value }.encode());
+                }
+                Item::Data(bytes) => image.extend(bytes),
+            }
+        }
+        Ok(image)
+    }
+
+    /// Print the program in the `.dis` listing format.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let mut addr = 0;
+        for item in &self.items {
+            match item {
+                Item::Label(name) => out.push_str(&format!("{}:\n", name)),
+                Item::Instr(instr) => out.push_str(&format!("{}\n", listing_line(&[], addr, Some(*instr)))),
+                Item::LoadLabel { dest, label } => {
+                    out.push_str(&format!("  {:04}   loadimm r{} <- #{}\n", addr, dest, label))
+                }
+                Item::Data(bytes) => out.push_str(&format!("  ???? {}\n", byte_string(bytes))),
+            }
+            addr += item.size();
+        }
+        out
+    }
+}
+
+/// Represent bytes as a `b'...'` literal, as found in the `.dis` listings.
+pub fn byte_string(bytes: &[u8]) -> String {
+    let mut s = String::from("b'");
+    for &b in bytes {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\t' => s.push_str("\\t"),
+            b'\\' => s.push_str("\\\\"),
+            b'\'' => s.push_str("\\'"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    s.push('\'');
+    s
+}
@@ -0,0 +1,394 @@
+//! Lower the syntax tree to machine instructions.
+//!
+//! The generated code follows the conventions of the `.dis` listings:
+//!   - r2 is the stack pointer; the stack starts at the end of memory and
+//!     grows downwards, r2 pointing to the last pushed word;
+//!   - r3 is a scratch register used to hold constants and addresses;
+//!   - a call pushes the return address then jumps to the function, which
+//!     returns by popping this address into r0;
+//!   - arguments are passed in r10, r11, ... and the result is returned in r11.
+//!
+//! Each function keeps its frame pointer in r4. Its parameters and variables
+//! live in the frame, just below the saved frame pointer of the caller, and
+//! intermediate values are pushed on the stack. Expressions are evaluated into
+//! r5, r6 holding the left operand of binary operations.
+
+use std::collections::{HashMap, HashSet};
+
+use super::parser::{BinOp, Expr, Function, PrintArg, Stmt, StmtKind};
+use super::CompileError;
+use crate::{Assembly, Instruction, Item, IP};
+
+const SP: usize = 2;
+const SCRATCH: usize = 3;
+const FP: usize = 4;
+const ACC: usize = 5;
+const LEFT: usize = 6;
+const TMP: usize = 7;
+const TARGET: usize = 8;
+const FIRST_ARG: usize = 10;
+const RESULT: usize = 11;
+/// Number of registers available to pass arguments.
+pub const MAX_ARGS: usize = 6;
+
+struct Codegen<'a> {
+    asm: Assembly,
+    /// Arity of every function of the program.
+    functions: HashMap<&'a str, usize>,
+    /// String literals, to be placed after the code.
+    strings: Vec<Vec<u8>>,
+    /// Last number used for each kind of generated label.
+    counters: HashMap<String, usize>,
+    /// Frame slot of every variable of the current function.
+    slots: HashMap<&'a str, usize>,
+    /// Variables of the current function declared so far.
+    declared: HashSet<&'a str>,
+    /// Label of the code returning from the current function.
+    epilogue: String,
+}
+
+/// Collect the variables declared in `stmts`, in order of appearance.
+fn declarations<'a>(stmts: &'a [Stmt], vars: &mut Vec<(&'a str, usize)>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Var(name, _) => vars.push((name, stmt.line)),
+            StmtKind::If(_, then, otherwise) => {
+                declarations(then, vars);
+                declarations(otherwise, vars);
+            }
+            StmtKind::While(_, body) => declarations(body, vars),
+            _ => (),
+        }
+    }
+}
+
+impl<'a> Codegen<'a> {
+    fn emit(&mut self, instr: Instruction) {
+        self.asm.push(Item::Instr(instr));
+    }
+
+    fn label(&mut self, name: &str) {
+        self.asm.push(Item::Label(name.to_owned()));
+    }
+
+    /// Number labels of the same kind, as in `ite_then_3` or `return_from_fact_2`.
+    fn fresh(&mut self, prefix: &str) -> usize {
+        let counter = self.counters.entry(prefix.to_owned()).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    fn loadimm(&mut self, dest: usize, value: i16) {
+        self.emit(Instruction::LoadImm { dest, value });
+    }
+
+    fn sub(&mut self, dest: usize, op1: usize, op2: usize) {
+        self.emit(Instruction::Sub { dest, op1, op2 });
+    }
+
+    /// Unconditional move, using the fact that IP is never 0 once an instruction is fetched.
+    fn mov(&mut self, dest: usize, src: usize) {
+        self.emit(Instruction::MoveIf { dest, src, cond: IP });
+    }
+
+    fn load_label(&mut self, dest: usize, label: &str) {
+        self.asm.push(Item::LoadLabel { dest, label: label.to_owned() });
+    }
+
+    fn jump(&mut self, label: &str) {
+        self.load_label(IP, label);
+    }
+
+    /// Jump to `label` if `cond` is not zero.
+    fn jump_if(&mut self, cond: usize, label: &str) {
+        self.load_label(TARGET, label);
+        self.emit(Instruction::MoveIf { dest: IP, src: TARGET, cond });
+    }
+
+    fn push(&mut self, reg: usize) {
+        self.loadimm(SCRATCH, 4);
+        self.sub(SP, SP, SCRATCH);
+        self.emit(Instruction::Store { addr: SP, src: reg });
+    }
+
+    fn pop(&mut self, reg: usize) {
+        self.loadimm(SCRATCH, -4);
+        self.sub(SP, SP, SCRATCH);
+        self.loadimm(SCRATCH, 4);
+        self.sub(SCRATCH, SP, SCRATCH);
+        self.emit(Instruction::Load { dest: reg, addr: SCRATCH });
+    }
+
+    /// Call `name` whose arguments are already in place, as done in the listings.
+    fn call(&mut self, name: &str) {
+        let prefix = format!("return_from_{}", name);
+        let ret = format!("{}_{}", prefix, self.fresh(&prefix));
+        self.loadimm(SCRATCH, 4);
+        self.sub(SP, SP, SCRATCH);
+        self.load_label(SCRATCH, &ret);
+        self.emit(Instruction::Store { addr: SP, src: SCRATCH });
+        self.jump(name);
+        self.label(&ret);
+    }
+
+    /// Put the address of the frame slot of `name` in r3.
+    fn slot_address(&mut self, name: &str, line: usize) -> Result<(), CompileError> {
+        if !self.declared.contains(name) {
+            return Err(CompileError::new(line, format!("variable `{}` is not declared", name)));
+        }
+        let slot = self.slots[name];
+        self.loadimm(SCRATCH, 4 * (slot as i16 + 1));
+        self.sub(SCRATCH, FP, SCRATCH);
+        Ok(())
+    }
+
+    fn function(&mut self, function: &'a Function) -> Result<(), CompileError> {
+        if function.params.len() > MAX_ARGS {
+            return Err(CompileError::new(
+                function.line,
+                format!("function `{}` has more than {} parameters", function.name, MAX_ARGS),
+            ));
+        }
+        self.slots.clear();
+        self.declared.clear();
+        let mut vars: Vec<(&str, usize)> = function.params.iter().map(|p| (p.as_str(), function.line)).collect();
+        declarations(&function.body, &mut vars);
+        for (slot, (name, line)) in vars.iter().enumerate() {
+            if self.slots.insert(name, slot).is_some() {
+                return Err(CompileError::new(*line, format!("variable `{}` is declared more than once", name)));
+            }
+        }
+        self.epilogue = format!("{}_epilogue", function.name);
+
+        // Prologue: save the caller frame pointer, then spill the arguments into the frame
+        // and reserve room for the variables.
+        self.label(&function.name);
+        self.push(FP);
+        self.mov(FP, SP);
+        for (i, param) in function.params.iter().enumerate() {
+            self.push(FIRST_ARG + i);
+            self.declared.insert(param);
+        }
+        let nvars = vars.len() - function.params.len();
+        if nvars > 0 {
+            self.loadimm(SCRATCH, 4 * nvars as i16);
+            self.sub(SP, SP, SCRATCH);
+        }
+
+        self.block(&function.body)?;
+
+        // Falling off the end of a function returns 0.
+        self.loadimm(RESULT, 0);
+        let epilogue = self.epilogue.clone();
+        self.label(&epilogue);
+        self.mov(SP, FP);
+        self.pop(FP);
+        self.pop(IP);
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt]) -> Result<(), CompileError> {
+        for stmt in stmts {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> Result<(), CompileError> {
+        let line = stmt.line;
+        match &stmt.kind {
+            StmtKind::Var(name, value) => {
+                self.expr(value, line)?;
+                self.declared.insert(name);
+                self.slot_address(name, line)?;
+                self.emit(Instruction::Store { addr: SCRATCH, src: ACC });
+            }
+            StmtKind::Assign(name, value) => {
+                self.expr(value, line)?;
+                self.slot_address(name, line)?;
+                self.emit(Instruction::Store { addr: SCRATCH, src: ACC });
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let n = self.fresh("ite");
+                let (then_label, else_label, end_label) =
+                    (format!("ite_then_{}", n), format!("ite_else_{}", n), format!("ite_end_{}", n));
+                self.expr(cond, line)?;
+                self.jump_if(ACC, &then_label);
+                if otherwise.is_empty() {
+                    self.jump(&end_label);
+                } else {
+                    self.jump(&else_label);
+                }
+                self.label(&then_label);
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.jump(&end_label);
+                    self.label(&else_label);
+                    self.block(otherwise)?;
+                }
+                self.label(&end_label);
+            }
+            StmtKind::While(cond, body) => {
+                let n = self.fresh("while");
+                let (loop_label, body_label, end_label) =
+                    (format!("while_loop_{}", n), format!("while_body_{}", n), format!("while_end_{}", n));
+                self.label(&loop_label);
+                self.expr(cond, line)?;
+                self.jump_if(ACC, &body_label);
+                self.jump(&end_label);
+                self.label(&body_label);
+                self.block(body)?;
+                self.jump(&loop_label);
+                self.label(&end_label);
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => {
+                        self.expr(value, line)?;
+                        self.mov(RESULT, ACC);
+                    }
+                    None => self.loadimm(RESULT, 0),
+                }
+                let epilogue = self.epilogue.clone();
+                self.jump(&epilogue);
+            }
+            StmtKind::Print(args) => {
+                for arg in args {
+                    match arg {
+                        PrintArg::Str(s) => {
+                            self.strings.push(s.clone());
+                            let label = format!("str_{}", self.strings.len());
+                            self.load_label(FIRST_ARG, &label);
+                            self.loadimm(FIRST_ARG + 1, s.len() as i16);
+                            self.call("print");
+                        }
+                        PrintArg::Expr(e) => {
+                            self.expr(e, line)?;
+                            self.emit(Instruction::OutNumber { reg: ACC });
+                        }
+                    }
+                }
+            }
+            StmtKind::Expr(e) => self.expr(e, line)?,
+        }
+        Ok(())
+    }
+
+    /// Evaluate `expr` into r5.
+    fn expr(&mut self, expr: &'a Expr, line: usize) -> Result<(), CompileError> {
+        match expr {
+            Expr::Number(n) => self.loadimm(ACC, *n as i16),
+            Expr::Var(name) => {
+                self.slot_address(name, line)?;
+                self.emit(Instruction::Load { dest: ACC, addr: SCRATCH });
+            }
+            Expr::Neg(e) => {
+                self.expr(e, line)?;
+                self.loadimm(TMP, 0);
+                self.sub(ACC, TMP, ACC);
+            }
+            Expr::Binary(op, left, right) => {
+                self.expr(left, line)?;
+                self.push(ACC);
+                self.expr(right, line)?;
+                self.pop(LEFT);
+                match op {
+                    BinOp::Sub => self.sub(ACC, LEFT, ACC),
+                    BinOp::Add => {
+                        self.loadimm(TMP, 0);
+                        self.sub(ACC, TMP, ACC);
+                        self.sub(ACC, LEFT, ACC);
+                    }
+                    BinOp::Eq | BinOp::Ne => {
+                        let (equal, different) = if *op == BinOp::Eq { (1, 0) } else { (0, 1) };
+                        self.sub(LEFT, LEFT, ACC);
+                        self.loadimm(ACC, equal);
+                        self.loadimm(TMP, different);
+                        self.emit(Instruction::MoveIf { dest: ACC, src: TMP, cond: LEFT });
+                    }
+                }
+            }
+            Expr::Call(name, args) => {
+                match self.functions.get(name.as_str()) {
+                    None => return Err(CompileError::new(line, format!("function `{}` is not defined", name))),
+                    Some(&arity) if arity != args.len() => {
+                        return Err(CompileError::new(
+                            line,
+                            format!("function `{}` expects {} arguments but {} were given", name, arity, args.len()),
+                        ))
+                    }
+                    _ => (),
+                }
+                for arg in args {
+                    self.expr(arg, line)?;
+                    self.push(ACC);
+                }
+                for i in (0..args.len()).rev() {
+                    self.pop(FIRST_ARG + i);
+                }
+                self.call(name);
+                self.mov(ACC, RESULT);
+            }
+        }
+        Ok(())
+    }
+
+    /// Routine printing the r11 bytes starting at the address in r10.
+    fn print_routine(&mut self) {
+        self.label("print");
+        self.label("print_loop");
+        self.jump_if(RESULT, "print_char");
+        self.jump("print_end");
+        self.label("print_char");
+        self.emit(Instruction::Load { dest: SCRATCH, addr: FIRST_ARG });
+        self.emit(Instruction::Out { reg: SCRATCH });
+        self.loadimm(SCRATCH, -1);
+        self.sub(FIRST_ARG, FIRST_ARG, SCRATCH);
+        self.loadimm(SCRATCH, 1);
+        self.sub(RESULT, RESULT, SCRATCH);
+        self.jump("print_loop");
+        self.label("print_end");
+        self.pop(IP);
+    }
+}
+
+/// Generate the program: set up the stack, call `main` then exit.
+pub fn generate(functions: &[Function]) -> Result<Assembly, CompileError> {
+    let mut codegen = Codegen {
+        asm: Assembly::new(),
+        functions: HashMap::new(),
+        strings: Vec::new(),
+        counters: HashMap::new(),
+        slots: HashMap::new(),
+        declared: HashSet::new(),
+        epilogue: String::new(),
+    };
+    for function in functions {
+        if codegen.functions.insert(&function.name, function.params.len()).is_some() {
+            return Err(CompileError::new(
+                function.line,
+                format!("function `{}` is defined more than once", function.name),
+            ));
+        }
+    }
+    match codegen.functions.get("main") {
+        Some(0) => (),
+        Some(_) => return Err(CompileError::new(None, "function `main` must not take parameters")),
+        None => return Err(CompileError::new(None, "function `main` is not defined")),
+    }
+
+    codegen.loadimm(SP, crate::MEMORY_SIZE as i16);
+    codegen.call("main");
+    codegen.emit(Instruction::Exit);
+    for function in functions {
+        codegen.function(function)?;
+    }
+    if !codegen.strings.is_empty() {
+        codegen.print_routine();
+    }
+    for (i, s) in std::mem::take(&mut codegen.strings).into_iter().enumerate() {
+        codegen.label(&format!("str_{}", i + 1));
+        codegen.asm.push(Item::Data(s));
+    }
+    Ok(codegen.asm)
+}
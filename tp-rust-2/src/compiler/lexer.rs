@@ -0,0 +1,169 @@
+//! Split the source text into tokens.
+
+use std::fmt;
+
+use super::CompileError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    Number(i32),
+    Str(Vec<u8>),
+    Fn,
+    Var,
+    If,
+    Else,
+    While,
+    Return,
+    Print,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Semicolon,
+    Assign,
+    Plus,
+    Minus,
+    Eq,
+    Ne,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Token::Ident(name) => return write!(f, "`{}`", name),
+            Token::Number(n) => return write!(f, "`{}`", n),
+            Token::Str(_) => return write!(f, "string literal"),
+            Token::Fn => "fn",
+            Token::Var => "var",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::Return => "return",
+            Token::Print => "print",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Eq => "==",
+            Token::Ne => "!=",
+        };
+        write!(f, "`{}`", s)
+    }
+}
+
+/// A token with the line it was found on.
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub line: usize,
+}
+
+/// Read a string literal whose opening quote has already been consumed.
+fn string<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>, line: usize) -> Result<Vec<u8>, CompileError> {
+    let mut bytes = Vec::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(bytes),
+            Some('\\') => {
+                let b = match chars.next() {
+                    Some('n') => b'\n',
+                    Some('t') => b'\t',
+                    Some('0') => 0,
+                    Some('\\') => b'\\',
+                    Some('"') => b'"',
+                    other => return Err(CompileError::new(line, format!("unknown escape sequence `\\{}`", other.unwrap_or(' ')))),
+                };
+                bytes.push(b);
+            }
+            Some('\n') | None => return Err(CompileError::new(line, "unterminated string literal")),
+            Some(c) if c.is_ascii() => bytes.push(c as u8),
+            Some(c) => return Err(CompileError::new(line, format!("non-ASCII character `{}` in string literal", c))),
+        }
+    }
+}
+
+/// Split `source` into tokens.
+pub fn tokenize(source: &str) -> Result<Vec<Spanned>, CompileError> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '/' if chars.peek() == Some(&'/') => {
+                // Comment until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+                continue;
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '=' if chars.peek() == Some(&'=') => {
+                chars.next();
+                Token::Eq
+            }
+            '=' => Token::Assign,
+            '!' if chars.peek() == Some(&'=') => {
+                chars.next();
+                Token::Ne
+            }
+            '"' => Token::Str(string(&mut chars, line)?),
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_digit(10).unwrap() as i32;
+                while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    chars.next();
+                    number = number * 10 + d as i32;
+                    if number > i16::MAX as i32 {
+                        return Err(CompileError::new(line, format!("number literal is larger than {}", i16::MAX)));
+                    }
+                }
+                Token::Number(number)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                match ident.as_str() {
+                    "fn" => Token::Fn,
+                    "var" => Token::Var,
+                    "if" => Token::If,
+                    "else" => Token::Else,
+                    "while" => Token::While,
+                    "return" => Token::Return,
+                    "print" => Token::Print,
+                    _ => Token::Ident(ident),
+                }
+            }
+            c => return Err(CompileError::new(line, format!("unexpected character `{}`", c))),
+        };
+        tokens.push(Spanned { token, line });
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,79 @@
+//! Compiler from a tiny imperative language to the machine instructions.
+//!
+//! A program is a list of functions, `main` being called first:
+//!
+//! ```text
+//! // Recursive factorial, multiplication being done by repeated additions
+//! fn mult(a, b) {
+//!     var r = 0;
+//!     while b != 0 {
+//!         r = r + a;
+//!         b = b - 1;
+//!     }
+//!     return r;
+//! }
+//!
+//! fn fact(n) {
+//!     if n == 0 {
+//!         return 1;
+//!     }
+//!     return mult(n, fact(n - 1));
+//! }
+//!
+//! fn main() {
+//!     print "5! = ", fact(5), "\n";
+//! }
+//! ```
+//!
+//! Values are 32 bits integers. Conditions are true when not zero, and `==`
+//! and `!=` evaluate to 0 or 1. Number literals must fit in 15 bits, and a
+//! function takes at most [MAX_ARGS](codegen::MAX_ARGS) parameters.
+
+mod codegen;
+mod lexer;
+mod parser;
+
+use std::fmt;
+
+use crate::Assembly;
+
+pub use codegen::MAX_ARGS;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    /// Line of the source where the error was detected, if any.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(line: impl Into<Option<usize>>, message: impl Into<String>) -> Self {
+        CompileError { line: line.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Compile `source` into an assembly program.
+pub fn compile(source: &str) -> Result<Assembly, CompileError> {
+    let tokens = lexer::tokenize(source)?;
+    let functions = parser::parse(tokens)?;
+    let asm = codegen::generate(&functions)?;
+    // Catch label clashes between functions and generated labels, and programs too big for memory.
+    asm.labels().map_err(|e| CompileError::new(None, e.to_string()))?;
+    Ok(asm)
+}
+
+/// Compile `source` into a memory image ready to be loaded in a [Machine](crate::Machine).
+pub fn compile_to_image(source: &str) -> Result<Vec<u8>, CompileError> {
+    compile(source)?.assemble().map_err(|e| CompileError::new(None, e.to_string()))
+}
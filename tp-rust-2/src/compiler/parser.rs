@@ -0,0 +1,301 @@
+//! Build the syntax tree of a program from its tokens.
+//!
+//! ```text
+//! program   := function*
+//! function  := "fn" ident "(" [ident ("," ident)*] ")" block
+//! block     := "{" statement* "}"
+//! statement := "var" ident "=" expr ";"
+//!            | ident "=" expr ";"
+//!            | "if" expr block ["else" (block | if-statement)]
+//!            | "while" expr block
+//!            | "return" [expr] ";"
+//!            | "print" (string | expr) ("," (string | expr))* ";"
+//!            | expr ";"
+//! expr      := sum (("==" | "!=") sum)*
+//! sum       := unary (("+" | "-") unary)*
+//! unary     := "-" unary | number | ident | ident "(" [expr ("," expr)*] ")" | "(" expr ")"
+//! ```
+
+use super::lexer::{Spanned, Token};
+use super::CompileError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i32),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrintArg {
+    Str(Vec<u8>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StmtKind {
+    Var(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Print(Vec<PrintArg>),
+    Expr(Expr),
+}
+
+/// A statement with the line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+    pub line: usize,
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(t) => t.line,
+            None => 1,
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, CompileError> {
+        let found = match self.peek() {
+            Some(token) => token.to_string(),
+            None => String::from("end of file"),
+        };
+        Err(CompileError::new(self.line(), format!("expected {}, found {}", expected, found)))
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), CompileError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(expected)
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => self.error("an identifier"),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        self.expect(Token::Fn, "`fn`")?;
+        let name = self.ident()?;
+        self.expect(Token::LParen, "`(`")?;
+        let mut params = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            params.push(self.ident()?);
+            while self.peek() == Some(&Token::Comma) {
+                self.pos += 1;
+                params.push(self.ident()?);
+            }
+        }
+        self.expect(Token::RParen, "`)`")?;
+        let body = self.block()?;
+        Ok(Function { name, params, body, line })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect(Token::LBrace, "`{`")?;
+        let mut stmts = Vec::new();
+        while self.peek() != Some(&Token::RBrace) {
+            if self.peek().is_none() {
+                return self.error("`}`");
+            }
+            stmts.push(self.statement()?);
+        }
+        self.pos += 1;
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        let kind = match self.peek() {
+            Some(Token::Var) => {
+                self.pos += 1;
+                let name = self.ident()?;
+                self.expect(Token::Assign, "`=`")?;
+                let value = self.expr()?;
+                self.expect(Token::Semicolon, "`;`")?;
+                StmtKind::Var(name, value)
+            }
+            Some(Token::Ident(_)) if self.tokens.get(self.pos + 1).map(|t| &t.token) == Some(&Token::Assign) => {
+                let name = self.ident()?;
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect(Token::Semicolon, "`;`")?;
+                StmtKind::Assign(name, value)
+            }
+            Some(Token::If) => return self.if_statement(),
+            Some(Token::While) => {
+                self.pos += 1;
+                let cond = self.expr()?;
+                StmtKind::While(cond, self.block()?)
+            }
+            Some(Token::Return) => {
+                self.pos += 1;
+                let value = if self.peek() == Some(&Token::Semicolon) {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(Token::Semicolon, "`;`")?;
+                StmtKind::Return(value)
+            }
+            Some(Token::Print) => {
+                self.pos += 1;
+                let mut args = vec![self.print_arg()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    args.push(self.print_arg()?);
+                }
+                self.expect(Token::Semicolon, "`;`")?;
+                StmtKind::Print(args)
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(Token::Semicolon, "`;`")?;
+                StmtKind::Expr(expr)
+            }
+        };
+        Ok(Stmt { kind, line })
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        self.expect(Token::If, "`if`")?;
+        let cond = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if self.peek() == Some(&Token::Else) {
+            self.pos += 1;
+            if self.peek() == Some(&Token::If) {
+                vec![self.if_statement()?]
+            } else {
+                self.block()?
+            }
+        } else {
+            Vec::new()
+        };
+        Ok(Stmt { kind: StmtKind::If(cond, then, otherwise), line })
+    }
+
+    fn print_arg(&mut self) -> Result<PrintArg, CompileError> {
+        if let Some(Token::Str(s)) = self.peek() {
+            let s = s.clone();
+            self.pos += 1;
+            Ok(PrintArg::Str(s))
+        } else {
+            Ok(PrintArg::Expr(self.expr()?))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.sum()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Eq) => BinOp::Eq,
+                Some(Token::Ne) => BinOp::Ne,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.sum()?));
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinOp::Add,
+                Some(Token::Minus) => BinOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        match self.peek() {
+            Some(Token::Minus) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some(Token::Number(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(_)) => {
+                let name = self.ident()?;
+                if self.peek() != Some(&Token::LParen) {
+                    return Ok(Expr::Var(name));
+                }
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.expr()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                }
+                self.expect(Token::RParen, "`)`")?;
+                Ok(Expr::Call(name, args))
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
+
+/// Parse a whole program.
+pub fn parse(tokens: Vec<Spanned>) -> Result<Vec<Function>, CompileError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut functions = Vec::new();
+    while parser.peek().is_some() {
+        functions.push(parser.function()?);
+    }
+    Ok(functions)
+}
@@ -0,0 +1,115 @@
+//! Instruction-level coverage.
+//!
+//! A machine counts how many times each instruction is executed once
+//! [Machine::set_coverage](crate::Machine::set_coverage) is enabled. Counts
+//! of several runs can be merged, then reported against a listing in the
+//! `.dis` format, either annotated with the counts or as an lcov tracefile.
+
+use std::fmt::{self, Write};
+
+use crate::MEMORY_SIZE;
+
+/// Number of executions of the instruction starting at each address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    hits: Vec<u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage { hits: vec![0; MEMORY_SIZE] }
+    }
+}
+
+/// Address of the instruction shown on a listing line such as
+/// `  0032   loadimm r8 <- #1`, if any.
+fn address(line: &str) -> Option<usize> {
+    let addr = line.split_whitespace().next()?;
+    if addr.len() != 4 {
+        return None;
+    }
+    addr.parse().ok().filter(|&addr| addr < MEMORY_SIZE)
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    pub(crate) fn record(&mut self, addr: usize) {
+        self.hits[addr] += 1;
+    }
+
+    /// Number of executions of the instruction at `addr`.
+    pub fn hits(&self, addr: usize) -> u64 {
+        self.hits.get(addr).copied().unwrap_or(0)
+    }
+
+    /// Add the counts of `other`.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (hits, other) in self.hits.iter_mut().zip(&other.hits) {
+            *hits += other;
+        }
+    }
+
+    /// Addresses of the executed instructions, with their counts.
+    pub fn executed(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.hits.iter().enumerate().filter(|(_, &hits)| hits > 0).map(|(addr, &hits)| (addr, hits))
+    }
+
+    /// Prefix every instruction of `listing` with its count, `#####`
+    /// standing for never executed instructions as in gcov reports. Other
+    /// lines are prefixed with `-`.
+    pub fn annotate(&self, listing: &str) -> String {
+        let mut out = String::new();
+        for line in listing.lines() {
+            match address(line) {
+                Some(addr) if self.hits[addr] > 0 => writeln!(out, "{:>9}:{}", self.hits[addr], line),
+                Some(_) => writeln!(out, "{:>9}:{}", "#####", line),
+                None => writeln!(out, "{:>9}:{}", "-", line),
+            }
+            .unwrap();
+        }
+        out
+    }
+
+    /// Produce an lcov tracefile, the lines being those of `listing` and the
+    /// source file being named `name`.
+    pub fn lcov(&self, name: &str, listing: &str) -> String {
+        let mut out = format!("TN:\nSF:{}\n", name);
+        for (number, line) in listing.lines().enumerate() {
+            if let Some(addr) = address(line) {
+                writeln!(out, "DA:{},{}", number + 1, self.hits[addr]).unwrap();
+            }
+        }
+        let summary = self.summary(listing);
+        write!(out, "LF:{}\nLH:{}\nend_of_record\n", summary.instructions, summary.executed).unwrap();
+        out
+    }
+
+    /// Count the instructions of `listing` which were executed.
+    pub fn summary(&self, listing: &str) -> Summary {
+        let addrs: Vec<usize> = listing.lines().filter_map(address).collect();
+        Summary {
+            instructions: addrs.len(),
+            executed: addrs.iter().filter(|&&addr| self.hits[addr] > 0).count(),
+        }
+    }
+}
+
+/// How many instructions of a listing were executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub instructions: usize,
+    pub executed: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = match self.instructions {
+            0 => 100.0,
+            n => 100.0 * self.executed as f64 / n as f64,
+        };
+        write!(f, "{:.1}% of {} instructions executed", percent, self.instructions)
+    }
+}
@@ -0,0 +1,400 @@
+//! GDB remote serial protocol server.
+//!
+//! A debugger connects over TCP, for example with `target remote :1234`, and
+//! controls a [Machine]: it can read and write registers and memory, set
+//! software breakpoints, step, continue, and step or continue backwards when
+//! the machine history is enabled (see [Machine::set_history_size]).
+//!
+//! The registers are r0 to r15, r0 being the program counter. The program
+//! output is written to the [Output] given to the server.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::{Machine, MachineError, Output, IP, MEMORY_SIZE, NREGS};
+
+/// Target description sent to the debugger.
+const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+    r#"<target version="1.0"><feature name="se202.interpreter">"#,
+    r#"<reg name="r0" bitsize="32" type="code_ptr"/>"#,
+    r#"<reg name="r1" bitsize="32"/><reg name="r2" bitsize="32" type="data_ptr"/>"#,
+    r#"<reg name="r3" bitsize="32"/><reg name="r4" bitsize="32"/><reg name="r5" bitsize="32"/>"#,
+    r#"<reg name="r6" bitsize="32"/><reg name="r7" bitsize="32"/><reg name="r8" bitsize="32"/>"#,
+    r#"<reg name="r9" bitsize="32"/><reg name="r10" bitsize="32"/><reg name="r11" bitsize="32"/>"#,
+    r#"<reg name="r12" bitsize="32"/><reg name="r13" bitsize="32"/><reg name="r14" bitsize="32"/>"#,
+    r#"<reg name="r15" bitsize="32"/></feature></target>"#,
+);
+
+/// Byte sent by the debugger to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+/// Number of instructions executed between two checks for an interruption.
+const POLL_PERIOD: usize = 4096;
+
+/// Why the machine stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    /// Stopped after a step, on a breakpoint or on an interruption.
+    Trap,
+    /// The program executed `exit`.
+    Exited,
+    Fault(MachineError),
+    /// The history does not go further back.
+    HistoryStart,
+}
+
+impl Stop {
+    fn reply(self) -> String {
+        match self {
+            Stop::Trap => String::from("S05"),
+            Stop::Exited => String::from("W00"),
+            // SIGILL for bad instructions, SIGSEGV for memory accesses, SIGFPE for overflows
+            Stop::Fault(MachineError::OutOfMemory(_)) => String::from("S0b"),
+            Stop::Fault(MachineError::SignedOverflow) => String::from("S08"),
+            Stop::Fault(_) => String::from("S04"),
+            Stop::HistoryStart => String::from("T05replaylog:begin;"),
+        }
+    }
+}
+
+/// Server waiting for debugger connections.
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    /// Listen on `addr`, such as `127.0.0.1:1234`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(GdbServer { listener: TcpListener::bind(addr)? })
+    }
+
+    /// Address the server listens on, useful when binding port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for a debugger and let it control `machine` until it detaches,
+    /// kills the program or disconnects.
+    pub fn serve<T: Output + ?Sized>(&self, machine: &mut Machine, out: &mut T) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        Session::new(stream, machine, out)?.run()
+    }
+}
+
+/// A connection with a debugger.
+struct Session<'a, T: Output + ?Sized> {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    machine: &'a mut Machine,
+    out: &'a mut T,
+    breakpoints: BTreeSet<u32>,
+    ack: bool,
+    /// Whether the program exited or faulted, and thus cannot be resumed.
+    ended: bool,
+}
+
+impl<'a, T: Output + ?Sized> Session<'a, T> {
+    fn new(stream: TcpStream, machine: &'a mut Machine, out: &'a mut T) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Session {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            machine,
+            out,
+            breakpoints: BTreeSet::new(),
+            ack: true,
+            ended: false,
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.send(&reply)?,
+                None => return Ok(()),
+            }
+            // The acknowledgment of the reply is the last one
+            if packet == "QStartNoAckMode" {
+                self.ack = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next packet, acknowledging it. Returns `None` when the
+    /// connection is closed.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                b'$' => (),
+                // Acknowledgments, and interruptions of a program which is not running
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            self.reader.read_until(b'#', &mut data)?;
+            data.pop();
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+            if expected != Some(checksum_of(&data)) {
+                if self.ack {
+                    self.writer.write_all(b"-")?;
+                }
+                continue;
+            }
+            if self.ack {
+                self.writer.write_all(b"+")?;
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        loop {
+            self.writer.write_all(packet.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            let mut byte = [0];
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                match byte[0] {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Reply to a packet, or return `None` to end the session.
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => Stop::Trap.reply(),
+            "g" => self.machine.regs().iter().map(|&r| hex(&r.to_le_bytes())).collect(),
+            "G" => match parse_hex(args) {
+                Some(bytes) if bytes.len() == 4 * NREGS => {
+                    for (reg, value) in bytes.chunks(4).enumerate() {
+                        self.set_reg(reg, u32::from_le_bytes(value.try_into().unwrap()));
+                    }
+                    ok()
+                }
+                _ => error(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < NREGS => hex(&self.machine.regs()[reg].to_le_bytes()),
+                _ => error(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(reg, value)| {
+                    let reg = usize::from_str_radix(reg, 16).ok().filter(|&r| r < NREGS)?;
+                    let value: [u8; 4] = parse_hex(value)?.try_into().ok()?;
+                    Some((reg, u32::from_le_bytes(value)))
+                });
+                match parsed {
+                    Some((reg, value)) => {
+                        self.set_reg(reg, value);
+                        ok()
+                    }
+                    None => error(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some(range) => hex(&self.machine.memory()[range]),
+                None => error(),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, parse_hex(data)?)));
+                match parsed {
+                    Some((range, data)) if range.len() == data.len() => {
+                        self.machine.memory_mut()[range].copy_from_slice(&data);
+                        ok()
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" | "z" => {
+                let addr = args
+                    .strip_prefix("0,")
+                    .and_then(|rest| rest.split(',').next())
+                    .and_then(|addr| u32::from_str_radix(addr, 16).ok());
+                match addr {
+                    Some(addr) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(addr);
+                        } else {
+                            self.breakpoints.remove(&addr);
+                        }
+                        ok()
+                    }
+                    // Only software breakpoints are supported
+                    None => String::new(),
+                }
+            }
+            "s" => self.resume(args, false)?.reply(),
+            "c" => self.resume(args, true)?.reply(),
+            "b" => match args {
+                "s" => self.back(false).reply(),
+                "c" => self.back(true).reply(),
+                _ => String::new(),
+            },
+            "H" => ok(),
+            "D" => {
+                self.send(&ok())?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    /// Reply to general queries.
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            String::from("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;ReverseStep+;ReverseContinue+")
+        } else if packet == "QStartNoAckMode" {
+            ok()
+        } else if packet == "qAttached" {
+            String::from("1")
+        } else if packet == "qC" {
+            String::from("QC1")
+        } else if packet == "qfThreadInfo" {
+            String::from("m1")
+        } else if packet == "qsThreadInfo" {
+            String::from("l")
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, length) = range.split_once(',').unwrap_or(("0", "0"));
+            let offset = usize::from_str_radix(offset, 16).unwrap_or(0).min(TARGET_XML.len());
+            let length = usize::from_str_radix(length, 16).unwrap_or(0);
+            let chunk = &TARGET_XML[offset..(offset + length).min(TARGET_XML.len())];
+            let more = offset + chunk.len() < TARGET_XML.len();
+            format!("{}{}", if more { 'm' } else { 'l' }, chunk)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Change a register on behalf of the debugger. As for memory writes, the
+    /// history is cleared, since it could not undo the change.
+    fn set_reg(&mut self, reg: usize, value: u32) {
+        self.machine.set_reg(reg, value).unwrap();
+        self.machine.clear_history();
+        if reg == IP {
+            self.ended = false;
+        }
+    }
+
+    /// Execute one instruction, or until a breakpoint when `continue` is set.
+    /// `args` is the optional address to resume at.
+    fn resume(&mut self, args: &str, r#continue: bool) -> io::Result<Stop> {
+        if let Ok(addr) = u32::from_str_radix(args, 16) {
+            self.set_reg(IP, addr);
+        }
+        if self.ended {
+            return Ok(Stop::Exited);
+        }
+        let mut steps = 0;
+        loop {
+            let ip = self.machine.regs()[IP];
+            match self.machine.step_on(self.out) {
+                Ok(true) => {
+                    self.ended = true;
+                    return Ok(Stop::Exited);
+                }
+                Ok(false) => (),
+                Err(e) => {
+                    // Show the faulting instruction to the debugger
+                    self.machine.set_reg(IP, ip).unwrap();
+                    self.ended = true;
+                    return Ok(Stop::Fault(e));
+                }
+            }
+            if !r#continue || self.breakpoints.contains(&self.machine.regs()[IP]) {
+                return Ok(Stop::Trap);
+            }
+            steps += 1;
+            if steps % POLL_PERIOD == 0 && self.interrupted()? {
+                return Ok(Stop::Trap);
+            }
+        }
+    }
+
+    /// Whether the debugger sent an interruption while the program runs.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            let interrupted = self.reader.buffer()[0] == INTERRUPT;
+            if interrupted {
+                self.reader.consume(1);
+            }
+            return Ok(interrupted);
+        }
+        self.writer.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.writer.peek(&mut byte);
+        self.writer.set_nonblocking(false)?;
+        match result {
+            Ok(1) if byte[0] == INTERRUPT => {
+                self.reader.read_exact(&mut byte)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Undo one instruction, or until a breakpoint when `continue` is set.
+    fn back(&mut self, r#continue: bool) -> Stop {
+        loop {
+            if !self.machine.step_back() {
+                return Stop::HistoryStart;
+            }
+            self.ended = false;
+            if !r#continue || self.breakpoints.contains(&self.machine.regs()[IP]) {
+                return Stop::Trap;
+            }
+        }
+    }
+}
+
+fn ok() -> String {
+    String::from("OK")
+}
+
+fn error() -> String {
+    String::from("E01")
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Parse `addr,length` into a memory range, if it fits in memory.
+fn parse_range(s: &str) -> Option<std::ops::Range<usize>> {
+    let (addr, length) = s.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let end = addr.checked_add(usize::from_str_radix(length, 16).ok()?)?;
+    (end <= MEMORY_SIZE).then_some(addr..end)
+}
@@ -0,0 +1,58 @@
+//! Undo log used to execute programs backwards.
+
+use std::collections::VecDeque;
+
+/// What a single step changed, with the previous values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Undo {
+    /// IP before the step.
+    pub(crate) ip: u32,
+    /// Register written by the instruction, other than IP, and its previous value.
+    pub(crate) reg: Option<(usize, u32)>,
+    /// Memory word written by the instruction, and its previous content.
+    pub(crate) word: Option<(usize, [u8; 4])>,
+    /// Status register written by the instruction, and its previous value.
+    pub(crate) status: Option<(usize, u32)>,
+}
+
+/// The last steps executed by a machine, the oldest being forgotten first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    entries: VecDeque<Undo>,
+    capacity: usize,
+}
+
+impl History {
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn push(&mut self, undo: Undo) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(undo);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Undo> {
+        self.entries.pop_back()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
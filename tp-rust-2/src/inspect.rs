@@ -0,0 +1,136 @@
+//! Helpers to look at the memory and registers of machines: hexadecimal
+//! dumps, differences between two states and printable strings.
+
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use crate::{Machine, NREGS};
+
+/// Number of bytes shown on each line of a dump.
+const LINE: usize = 16;
+
+fn printable(b: u8) -> bool {
+    b.is_ascii_graphic() || b == b' '
+}
+
+/// Append one dump line showing `bytes`, located at `addr`.
+fn dump_line(out: &mut String, addr: usize, bytes: &[u8]) {
+    write!(out, "{:04x} ", addr).unwrap();
+    for i in 0..LINE {
+        match bytes.get(i) {
+            Some(b) => write!(out, " {:02x}", b).unwrap(),
+            None => out.push_str("   "),
+        }
+    }
+    let ascii: String = bytes.iter().map(|&b| if printable(b) { b as char } else { '.' }).collect();
+    writeln!(out, "  |{}|", ascii).unwrap();
+}
+
+/// Dump `memory[range]` as hexadecimal bytes, 16 per line, followed by their
+/// ASCII representation. Lines start with the address of their first byte.
+///
+/// # Panics
+/// This function panics when `range` is not within `memory`.
+pub fn hexdump(memory: &[u8], range: Range<usize>) -> String {
+    let mut out = String::new();
+    let start = range.start;
+    for (line, chunk) in memory[range].chunks(LINE).enumerate() {
+        dump_line(&mut out, start + line * LINE, chunk);
+    }
+    out
+}
+
+/// Differences between two machine states.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    /// Registers which differ, with their values in the first and second states.
+    pub regs: Vec<(usize, u32, u32)>,
+    /// Memory bytes which differ, with their values in the first and second states.
+    pub memory: Vec<(usize, u8, u8)>,
+}
+
+impl Diff {
+    /// Compare the registers and memory of two machines.
+    pub fn between(before: &Machine, after: &Machine) -> Self {
+        // Machines always have NREGS registers
+        let regs = |machine: &Machine| -> [u32; NREGS] { machine.regs().try_into().unwrap() };
+        Diff::of(&regs(before), before.memory(), &regs(after), after.memory())
+    }
+
+    /// Compare two states given by their registers and memory.
+    pub fn of(regs_before: &[u32; NREGS], mem_before: &[u8], regs_after: &[u32; NREGS], mem_after: &[u8]) -> Self {
+        let regs = (0..NREGS)
+            .map(|r| (r, regs_before[r], regs_after[r]))
+            .filter(|&(_, before, after)| before != after)
+            .collect();
+        let memory = mem_before
+            .iter()
+            .zip(mem_after)
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(addr, (&before, &after))| (addr, before, after))
+            .collect();
+        Diff { regs, memory }
+    }
+
+    /// Whether both states are the same.
+    pub fn is_empty(&self) -> bool {
+        self.regs.is_empty() && self.memory.is_empty()
+    }
+}
+
+impl fmt::Display for Diff {
+    /// Print the changed registers, then the dump lines containing changed
+    /// bytes, before (`-`) and after (`+`). Unchanged bytes are shown as `..`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(reg, before, after) in &self.regs {
+            writeln!(f, "r{:<2}  {:#010x} -> {:#010x}", reg, before, after)?;
+        }
+        let mut changes = self.memory.iter().peekable();
+        while let Some(&&(addr, _, _)) = changes.peek() {
+            let start = addr - addr % LINE;
+            let mut before = [None; LINE];
+            let mut after = [None; LINE];
+            while let Some(&&(addr, b, a)) = changes.peek().filter(|&&&(addr, _, _)| addr < start + LINE) {
+                before[addr - start] = Some(b);
+                after[addr - start] = Some(a);
+                changes.next();
+            }
+            for (sign, bytes) in [('-', before), ('+', after)] {
+                write!(f, "{} {:04x} ", sign, start)?;
+                for b in bytes {
+                    match b {
+                        Some(b) => write!(f, " {:02x}", b)?,
+                        None => write!(f, " ..")?,
+                    }
+                }
+                let ascii: String = bytes
+                    .iter()
+                    .map(|b| match b {
+                        Some(b) if printable(*b) => *b as char,
+                        Some(_) => '.',
+                        None => ' ',
+                    })
+                    .collect();
+                writeln!(f, "  |{}|", ascii)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the runs of at least `min_len` printable ASCII characters in
+/// `memory`, with their addresses, as the `strings` tool does.
+pub fn strings(memory: &[u8], min_len: usize) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    for (addr, &b) in memory.iter().chain([0].iter()).enumerate() {
+        if !printable(b) {
+            if addr - start >= min_len.max(1) {
+                found.push((start, memory[start..addr].iter().map(|&b| b as char).collect()));
+            }
+            start = addr + 1;
+        }
+    }
+    found
+}
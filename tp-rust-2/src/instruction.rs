@@ -0,0 +1,183 @@
+//! This module describes the instructions understood by the machine, how they are
+//! encoded in memory and how they are printed in listings.
+
+use core::fmt;
+
+use crate::{MachineError, NREGS};
+
+/// A decoded instruction. Register operands are always valid register numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// `move dest <- src if cond != 0`
+    MoveIf { dest: usize, src: usize, cond: usize },
+    /// `store [addr] <- src`
+    Store { addr: usize, src: usize },
+    /// `load dest <- [addr]`
+    Load { dest: usize, addr: usize },
+    /// `loadimm dest <- #value`, the value being sign-extended to 32 bits
+    LoadImm { dest: usize, value: i16 },
+    /// `sub dest <- op1 - op2`
+    Sub { dest: usize, op1: usize, op2: usize },
+    /// `out reg`, prints the low byte of the register as a character
+    Out { reg: usize },
+    /// `exit`
+    Exit,
+    /// `out_number reg`, prints the register as a signed decimal number
+    OutNumber { reg: usize },
+}
+
+/// Check that a register operand designates an existing register.
+fn register(number: u8) -> Result<usize, MachineError> {
+    let reg = number as usize;
+    if reg < NREGS {
+        Ok(reg)
+    } else {
+        Err(MachineError::BadRegisterName)
+    }
+}
+
+impl Instruction {
+    /// Size in bytes of the instruction whose first byte is `opcode`.
+    pub fn size_of(opcode: u8) -> Result<usize, MachineError> {
+        match opcode {
+            1 | 4 | 5 => Ok(4),
+            2 | 3 => Ok(3),
+            6 | 8 => Ok(2),
+            7 => Ok(1),
+            _ => Err(MachineError::InvalidInstruction(opcode)),
+        }
+    }
+
+    /// Decode the instruction located at the beginning of `bytes`.
+    ///
+    /// An error is returned if the opcode is unknown, if an operand names
+    /// a register which does not exist or if `bytes` is too short to hold
+    /// the whole instruction.
+    pub fn decode(bytes: &[u8]) -> Result<Instruction, MachineError> {
+        let opcode = *bytes.first().ok_or(MachineError::TruncatedInstruction)?;
+        let size = Instruction::size_of(opcode)?;
+        if bytes.len() < size {
+            return Err(MachineError::TruncatedInstruction);
+        }
+        let instr = match opcode {
+            1 => Instruction::MoveIf {
+                dest: register(bytes[1])?,
+                src: register(bytes[2])?,
+                cond: register(bytes[3])?,
+            },
+            2 => Instruction::Store {
+                addr: register(bytes[1])?,
+                src: register(bytes[2])?,
+            },
+            3 => Instruction::Load {
+                dest: register(bytes[1])?,
+                addr: register(bytes[2])?,
+            },
+            4 => Instruction::LoadImm {
+                dest: register(bytes[1])?,
+                value: i16::from_le_bytes([bytes[2], bytes[3]]),
+            },
+            5 => Instruction::Sub {
+                dest: register(bytes[1])?,
+                op1: register(bytes[2])?,
+                op2: register(bytes[3])?,
+            },
+            6 => Instruction::Out { reg: register(bytes[1])? },
+            7 => Instruction::Exit,
+            8 => Instruction::OutNumber { reg: register(bytes[1])? },
+            _ => unreachable!(),
+        };
+        Ok(instr)
+    }
+
+    /// Size in bytes of the encoded instruction.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::MoveIf { .. } | Instruction::LoadImm { .. } | Instruction::Sub { .. } => 4,
+            Instruction::Store { .. } | Instruction::Load { .. } => 3,
+            Instruction::Out { .. } | Instruction::OutNumber { .. } => 2,
+            Instruction::Exit => 1,
+        }
+    }
+
+    /// Encode the instruction into its memory representation.
+    #[cfg(feature = "std")]
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Instruction::MoveIf { dest, src, cond } => vec![1, dest as u8, src as u8, cond as u8],
+            Instruction::Store { addr, src } => vec![2, addr as u8, src as u8],
+            Instruction::Load { dest, addr } => vec![3, dest as u8, addr as u8],
+            Instruction::LoadImm { dest, value } => {
+                let [l, h] = value.to_le_bytes();
+                vec![4, dest as u8, l, h]
+            }
+            Instruction::Sub { dest, op1, op2 } => vec![5, dest as u8, op1 as u8, op2 as u8],
+            Instruction::Out { reg } => vec![6, reg as u8],
+            Instruction::Exit => vec![7],
+            Instruction::OutNumber { reg } => vec![8, reg as u8],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Print the instruction using the same syntax as the `.dis` listings.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::MoveIf { dest, src, cond } => {
+                write!(f, "move r{} <- r{} if r{} != 0", dest, src, cond)
+            }
+            Instruction::Store { addr, src } => write!(f, "store [r{}] <- r{}", addr, src),
+            Instruction::Load { dest, addr } => write!(f, "load r{} <- [r{}]", dest, addr),
+            Instruction::LoadImm { dest, value } => write!(f, "loadimm r{} <- #{}", dest, value),
+            Instruction::Sub { dest, op1, op2 } => write!(f, "sub r{} <- r{} - r{}", dest, op1, op2),
+            Instruction::Out { reg } => write!(f, "out r{}", reg),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::OutNumber { reg } => write!(f, "out_number r{}", reg),
+        }
+    }
+}
+
+/// Line of the `.dis` listings, without the final newline, for the address
+/// `addr` of `memory` where `instr` was decoded. Bytes which do not start an
+/// instruction are printed as `.byte` directives, `memory` being only read
+/// for them.
+#[cfg(feature = "std")]
+pub fn listing_line(memory: &[u8], addr: usize, instr: Option<Instruction>) -> String {
+    match instr {
+        Some(instr) => format!("  {:04}   {}", addr, instr),
+        None => format!("  {:04}   .byte {:#04x}", addr, memory[addr]),
+    }
+}
+
+/// Disassemble `memory` in the format of the `.dis` listings.
+#[cfg(feature = "std")]
+pub fn listing(memory: &[u8]) -> String {
+    let mut out = String::new();
+    for (addr, instr) in disassemble(memory) {
+        out.push_str(&listing_line(memory, addr, instr));
+        out.push('\n');
+    }
+    out
+}
+
+/// Disassemble `memory` by decoding instructions one after the other starting
+/// at address 0. Bytes which do not start a valid instruction are reported as
+/// `None` and skipped one at a time.
+#[cfg(feature = "std")]
+pub fn disassemble(memory: &[u8]) -> Vec<(usize, Option<Instruction>)> {
+    let mut listing = Vec::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        match Instruction::decode(&memory[addr..]) {
+            Ok(instr) => {
+                listing.push((addr, Some(instr)));
+                addr += instr.size();
+            }
+            Err(_) => {
+                listing.push((addr, None));
+                addr += 1;
+            }
+        }
+    }
+    listing
+}
@@ -1,3 +1,35 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+mod assembler;
+#[cfg(feature = "std")]
+pub mod compiler;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+mod history;
+#[cfg(feature = "std")]
+pub mod inspect;
+mod instruction;
 mod machine;
+#[cfg(feature = "std")]
+pub mod network;
+#[cfg(feature = "std")]
+pub mod optimizer;
+mod output;
+#[cfg(feature = "std")]
+pub mod safety;
+#[cfg(feature = "std")]
+pub mod translator;
+#[cfg(feature = "tui")]
+pub mod tui;
 
+#[cfg(feature = "std")]
+pub use assembler::*;
+pub use instruction::*;
 pub use machine::*;
+pub use output::Output;
@@ -1,22 +1,91 @@
-use std::io::{self, Write};
+use core::fmt;
+use core::fmt::Write as _;
+#[cfg(feature = "std")]
+use std::io;
 
-const MEMORY_SIZE: usize = 4096;
-const NREGS: usize = 16;
+#[cfg(feature = "std")]
+use crate::coverage::Coverage;
+#[cfg(feature = "std")]
+use crate::history::{History, Undo};
+use crate::output::Formatter;
+use crate::{Instruction, Output};
 
-const IP: usize = 0;
+/// Size of the machine memory in bytes.
+pub const MEMORY_SIZE: usize = 4096;
+/// Number of registers of the machine.
+pub const NREGS: usize = 16;
 
+/// Register used as the instruction pointer.
+pub const IP: usize = 0;
+
+/// Status bit set when the result of `sub` is zero.
+pub const FLAG_ZERO: u32 = 1 << 0;
+/// Status bit set when the result of `sub` is negative, that is has its top bit set.
+pub const FLAG_NEGATIVE: u32 = 1 << 1;
+/// Status bit set when `sub` borrows, that is when `op1 < op2` as unsigned numbers.
+pub const FLAG_CARRY: u32 = 1 << 2;
+/// Status bit set when `sub` overflows as a signed operation.
+pub const FLAG_OVERFLOW: u32 = 1 << 3;
+
+/// Why [Machine::run_until] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// An exit instruction was executed.
+    Exit,
+    /// The limit of executed instructions was reached.
+    StepLimit,
+    /// The instruction at the given address faulted.
+    Fault(MachineError, u32),
+}
+
+#[derive(Clone)]
 pub struct Machine {
     mem : [u8; MEMORY_SIZE],
     reg : [u32; NREGS],
+    /// Register receiving the flags of arithmetic instructions, if any.
+    status : Option<usize>,
+    trap_overflow : bool,
+    #[cfg(feature = "std")]
+    history : History,
+    #[cfg(feature = "std")]
+    coverage : Option<Coverage>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MachineError {
     TooBigSize,
     BadRegisterName,
     InvalidChar,
+    /// The opcode found at IP does not correspond to any instruction.
+    InvalidInstruction(u8),
+    /// The instruction at IP does not fit in the machine memory.
+    TruncatedInstruction,
+    /// An access was attempted outside of the machine memory.
+    OutOfMemory(u32),
+    /// The output instruction could not write its result.
+    OutputFailure,
+    /// A signed arithmetic operation overflowed while overflows are trapped.
+    SignedOverflow,
 }
 
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MachineError::TooBigSize => write!(f, "value too big"),
+            MachineError::BadRegisterName => write!(f, "bad register name"),
+            MachineError::InvalidChar => write!(f, "invalid character"),
+            MachineError::InvalidInstruction(opcode) => write!(f, "invalid instruction (opcode {})", opcode),
+            MachineError::TruncatedInstruction => write!(f, "instruction truncated by the end of memory"),
+            MachineError::OutOfMemory(addr) => write!(f, "access outside of memory at address {:#x}", addr),
+            MachineError::OutputFailure => write!(f, "unable to write output"),
+            MachineError::SignedOverflow => write!(f, "signed arithmetic overflow"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MachineError {}
+
 impl Machine {
     /// Create a new machine in its reset state. The `memory` parameter will
     /// be copied at the beginning of the machine memory.
@@ -29,23 +98,49 @@ impl Machine {
         } else {
             let mut tab : [u8; MEMORY_SIZE] = [0; MEMORY_SIZE];
             tab[0..memory.len()].copy_from_slice(memory);
-            Machine {mem : tab, reg : [0; NREGS]}
+            Machine {
+                mem : tab,
+                reg : [0; NREGS],
+                status : None,
+                trap_overflow : false,
+                #[cfg(feature = "std")]
+                history : History::default(),
+                #[cfg(feature = "std")]
+                coverage : None,
+            }
         }
     }
 
     /// Run until the program terminates or until an error happens.
     /// If output instructions are run, they print on `fd`.
-    pub fn run_on<T: Write>(&mut self, fd: &mut T) -> Result<(), MachineError> {
+    pub fn run_on<T: Output + ?Sized>(&mut self, fd: &mut T) -> Result<(), MachineError> {
         while !self.step_on(fd)? {}
         Ok(())
     }
 
     /// Run until the program terminates or until an error happens.
     /// If output instructions are run, they print on standard output.
+    #[cfg(feature = "std")]
     pub fn run(&mut self) -> Result<(), MachineError> {
         self.run_on(&mut io::stdout().lock())
     }
 
+    /// Run until the program terminates, an error happens or `limit`
+    /// instructions have been executed. If output instructions are run, they
+    /// print on `fd`. Returns why the execution stopped and the number of
+    /// instructions executed, the faulting one included.
+    pub fn run_until<T: Output + ?Sized>(&mut self, limit: usize, fd: &mut T) -> (Halt, usize) {
+        for steps in 1..=limit {
+            let ip = self.reg[IP];
+            match self.step_on(fd) {
+                Ok(true) => return (Halt::Exit, steps),
+                Ok(false) => (),
+                Err(e) => return (Halt::Fault(e, ip), steps),
+            }
+        }
+        (Halt::StepLimit, limit)
+    }
+
     /// Execute the next instruction by doing the following steps:
     ///   - decode the instruction located at IP (register 0)
     ///   - increment the IP by the size of the instruction
@@ -58,43 +153,189 @@ impl Machine {
     /// In case of success, `true` is returned if the program is
     /// terminated (upon encountering an exit instruction), or
     /// `false` if the execution must continue.
-    pub fn step_on<T: Write>(&mut self, fd: &mut T) -> Result<bool, MachineError> {
+    pub fn step_on<T: Output + ?Sized>(&mut self, fd: &mut T) -> Result<bool, MachineError> {
         let ip = self.reg[IP];
-        self.set_reg(IP, ip + 1).unwrap();
-        let nip : usize = ip.try_into().unwrap();
-        let instr = &self.mem[nip..=nip+3];
-        let op1 = *instr.get(1).unwrap();
-        let op2 = *instr.get(2).unwrap();
-        let op3 = *instr.get(3).unwrap();
-        match *instr.get(0).unwrap() {
-            1 => self.moveif(op1.try_into().unwrap(), op2.try_into().unwrap(), op3.try_into().unwrap()),
-            2 => self.store(op1.try_into().unwrap(), op2.try_into().unwrap()),
-            3 => self.load(op1.try_into().unwrap(), op2.try_into().unwrap()),
-            4 => self.loadimm(op1.try_into().unwrap(), op2, op3),
-            5 => self.sub(op1.try_into().unwrap(), op2.try_into().unwrap(), op3.try_into().unwrap()),
-            6 => {
-                let number : usize = op1.try_into().unwrap();
-                let content : u32 = self.reg[number];
-                write!(fd, "{}", content as u8 as char).unwrap();
-                return Ok(false);
+        let nip = ip as usize;
+        if nip >= MEMORY_SIZE {
+            return Err(MachineError::OutOfMemory(ip));
+        }
+        let instr = Instruction::decode(&self.mem[nip..])?;
+        #[cfg(feature = "std")]
+        self.record(ip, &instr);
+        #[cfg(feature = "std")]
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(nip);
+        }
+        // The instruction fits in memory, so this addition cannot overflow.
+        self.reg[IP] = ip + instr.size() as u32;
+        match instr {
+            Instruction::MoveIf { dest, src, cond } => self.moveif(dest, src, cond),
+            Instruction::Store { addr, src } => self.store(addr, src),
+            Instruction::Load { dest, addr } => self.load(dest, addr),
+            Instruction::LoadImm { dest, value } => {
+                let [l, h] = value.to_le_bytes();
+                self.loadimm(dest, l, h)
+            }
+            Instruction::Sub { dest, op1, op2 } => self.sub(dest, op1, op2),
+            Instruction::Out { reg } => {
+                let content : u32 = self.reg[reg];
+                let mut buf = [0; 4];
+                fd.write_bytes((content as u8 as char).encode_utf8(&mut buf).as_bytes())?;
+                Ok(false)
             },
-            7 => return Ok(true),
-            8 => {
-                let number : usize = op1.try_into().unwrap();
-                let content : u32 = self.reg[number];
-                write!(fd, "{}", content).unwrap();
-                return Ok(false);
+            Instruction::Exit => Ok(true),
+            Instruction::OutNumber { reg } => {
+                let content : u32 = self.reg[reg];
+                write!(Formatter(fd), "{}", content as i32).map_err(|_| MachineError::OutputFailure)?;
+                Ok(false)
             },
-            _ => panic!("Bad number"),
         }
     }
 
     /// Similar to [step_on](Machine::step_on).
     /// If output instructions are run, they print on standard output.
+    #[cfg(feature = "std")]
     pub fn step(&mut self) -> Result<bool, MachineError> {
         self.step_on(&mut io::stdout().lock())
     }
 
+    /// Write the flags of every `sub` ([FLAG_ZERO], [FLAG_NEGATIVE],
+    /// [FLAG_CARRY] and [FLAG_OVERFLOW]) into register `reg` after its
+    /// result, so that programs can test them. `None`, the default, keeps
+    /// every register other than the destination unchanged. IP cannot hold
+    /// the flags.
+    ///
+    /// The static analyses and the optimizer assume the default mode.
+    pub fn set_status_register(&mut self, reg: Option<usize>) -> Result<(), MachineError> {
+        if reg.is_some_and(|reg| reg >= NREGS || reg == IP) {
+            return Err(MachineError::BadRegisterName);
+        }
+        self.status = reg;
+        Ok(())
+    }
+
+    /// Register receiving the flags, if any.
+    pub fn status_register(&self) -> Option<usize> {
+        self.status
+    }
+
+    /// Make `sub` fail with [MachineError::SignedOverflow], leaving its
+    /// destination unchanged, when it overflows as a signed operation,
+    /// instead of wrapping around.
+    pub fn set_trap_on_overflow(&mut self, trap: bool) {
+        self.trap_overflow = trap;
+    }
+
+    /// Whether signed overflows are trapped.
+    pub fn trap_on_overflow(&self) -> bool {
+        self.trap_overflow
+    }
+
+    /// Count the executions of every instruction from now on, or stop
+    /// counting and forget the counts.
+    #[cfg(feature = "std")]
+    pub fn set_coverage(&mut self, enabled: bool) {
+        match (enabled, self.coverage.is_some()) {
+            (true, false) => self.coverage = Some(Coverage::new()),
+            (false, _) => self.coverage = None,
+            (true, true) => (),
+        }
+    }
+
+    /// Executions counted since coverage was enabled, if it is.
+    #[cfg(feature = "std")]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Keep the changes made by the last `steps` executed instructions, so
+    /// that they can be undone with [step_back](Machine::step_back). The
+    /// history is disabled with 0, which is the default.
+    #[cfg(feature = "std")]
+    pub fn set_history_size(&mut self, steps: usize) {
+        self.history.set_capacity(steps);
+    }
+
+    /// Maximum number of steps kept in the history.
+    #[cfg(feature = "std")]
+    pub fn history_size(&self) -> usize {
+        self.history.capacity()
+    }
+
+    /// Number of steps which can currently be undone.
+    #[cfg(feature = "std")]
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Forget the steps recorded in the history, which cannot be undone
+    /// anymore once the state is changed outside of the execution.
+    #[cfg(feature = "std")]
+    pub(crate) fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Record the registers and memory about to be changed by `instr`.
+    #[cfg(feature = "std")]
+    fn record(&mut self, ip: u32, instr: &Instruction) {
+        if self.history.capacity() == 0 {
+            return;
+        }
+        let reg = match *instr {
+            Instruction::MoveIf { dest, .. }
+            | Instruction::Load { dest, .. }
+            | Instruction::LoadImm { dest, .. }
+            | Instruction::Sub { dest, .. } if dest != IP => Some((dest, self.reg[dest])),
+            _ => None,
+        };
+        let word = match *instr {
+            Instruction::Store { addr, .. } => Machine::word_range(self.reg[addr])
+                .ok()
+                .map(|range| (range.start, self.mem[range].try_into().unwrap())),
+            _ => None,
+        };
+        let status = match *instr {
+            Instruction::Sub { .. } => self.status.map(|status| (status, self.reg[status])),
+            _ => None,
+        };
+        self.history.push(Undo { ip, reg, word, status });
+    }
+
+    /// Undo the last executed instruction, as recorded in the history.
+    /// Returns `false` if the history is empty. The output already
+    /// produced is not taken back, and changes made through
+    /// [set_reg](Machine::set_reg) are not recorded.
+    #[cfg(feature = "std")]
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.pop() else {
+            return false;
+        };
+        if let Some((start, word)) = undo.word {
+            self.mem[start..start + 4].copy_from_slice(&word);
+        }
+        if let Some((reg, value)) = undo.status {
+            self.reg[reg] = value;
+        }
+        if let Some((reg, value)) = undo.reg {
+            self.reg[reg] = value;
+        }
+        self.reg[IP] = undo.ip;
+        true
+    }
+
+    /// Go back in time until the instruction at `addr` is about to be executed.
+    /// Returns `false`, leaving the machine in the oldest recorded state, if
+    /// the history does not go back this far.
+    #[cfg(feature = "std")]
+    pub fn run_back_to(&mut self, addr: u32) -> bool {
+        while self.step_back() {
+            if self.reg[IP] == addr {
+                return true;
+            }
+        }
+        false
+    }
+
     /// Reference onto the machine current set of registers.
     pub fn regs(&self) -> &[u32] {
         &self.reg
@@ -102,8 +343,8 @@ impl Machine {
 
     /// Sets a register to the given value.
     pub fn set_reg(&mut self, reg: usize, value: u32) -> Result<(), MachineError> {
-        if reg > NREGS {
-            return Err(MachineError::TooBigSize);
+        if reg >= NREGS {
+            return Err(MachineError::BadRegisterName);
         }
         self.reg[reg] = value;
         Ok(())
@@ -114,60 +355,92 @@ impl Machine {
         &self.mem
     }
 
+    /// Mutable reference onto the machine current memory, for the debugger.
+    /// The history is cleared, as it could not undo the changes made through
+    /// it, nor the steps executed before them.
+    #[cfg(feature = "std")]
+    pub(crate) fn memory_mut(&mut self) -> &mut [u8] {
+        self.clear_history();
+        &mut self.mem
+    }
+
+    /// Copy register `reg2` into register `reg1` if register `reg3` is not zero.
     pub fn moveif(&mut self, reg1 : usize, reg2 : usize, reg3 : usize) -> Result<bool, MachineError> {
-        let cond = self.reg[reg3];
-        if cond != 0 {
-            if (reg1 < NREGS) & (reg2 < NREGS) {
-                self.set_reg(reg1, self.reg[reg2]).unwrap();
-                Ok(false)
-            } else {
-                Err(MachineError::BadRegisterName)
+        if (reg1 < NREGS) & (reg2 < NREGS) & (reg3 < NREGS) {
+            if self.reg[reg3] != 0 {
+                self.set_reg(reg1, self.reg[reg2])?;
             }
-        } else {
             Ok(false)
+        } else {
+            Err(MachineError::BadRegisterName)
+        }
+    }
+
+    /// Compute the address range of the 32 bits word located at `adr`, or
+    /// return an error if this word is not entirely inside the memory.
+    fn word_range(adr : u32) -> Result<core::ops::Range<usize>, MachineError> {
+        let start = adr as usize;
+        match start.checked_add(4) {
+            Some(end) if end <= MEMORY_SIZE => Ok(start..end),
+            _ => Err(MachineError::OutOfMemory(adr)),
         }
     }
 
+    /// Store the content of register `reg2` at the address contained in register `reg1`.
     pub fn store(&mut self, reg1 : usize, reg2 : usize) -> Result<bool, MachineError> {
         if (reg1 < NREGS) & (reg2 < NREGS) {
-            let content : &[u8] = &self.reg[reg2].to_le_bytes();
-            let adr : usize = self.reg[reg1].try_into().unwrap();
-            self.mem[adr] = content[0];
-            self.mem[adr+1] = content[1];
-            self.mem[adr+2] = content[2];
-            self.mem[adr+3] = content[3];
+            let range = Machine::word_range(self.reg[reg1])?;
+            self.mem[range].copy_from_slice(&self.reg[reg2].to_le_bytes());
             Ok(false)
         } else {
             Err(MachineError::BadRegisterName)
         }
     }
 
+    /// Load the word located at the address contained in register `reg2` into register `reg1`.
     pub fn load(&mut self, reg1 : usize, reg2 : usize) -> Result<bool, MachineError> {
         if (reg1 < NREGS) & (reg2 < NREGS) {
-            let adr : usize = self.reg[reg2].try_into().unwrap();
-            let tab : [u8; 4] = self.mem[adr..=adr+3].try_into().unwrap();
+            let range = Machine::word_range(self.reg[reg2])?;
+            let tab : [u8; 4] = self.mem[range].try_into().unwrap();
             let content = u32::from_le_bytes(tab);
-            self.set_reg(reg1, content).unwrap();
+            self.set_reg(reg1, content)?;
             Ok(false)
         } else {
             Err(MachineError::BadRegisterName)
         }
     }
 
+    /// Load the sign-extended 16 bits value `h:l` into register `reg1`.
     pub fn loadimm(&mut self, reg1 : usize, l : u8, h : u8) -> Result<bool, MachineError> {
         if reg1 < NREGS {
             let val : i32 = i16::from_le_bytes([l, h]) as i32;
-            self.set_reg(reg1, val as u32).unwrap();
+            self.set_reg(reg1, val as u32)?;
             Ok(false)
         } else {
             Err(MachineError::BadRegisterName)
         }
     }
 
+    /// Store `op1 - op2` into register `dest`, wrapping around on overflow
+    /// unless overflows are trapped, and update the status register if any.
     pub fn sub(&mut self, dest : usize, op1 : usize, op2 : usize) -> Result<bool, MachineError> {
         if (op1 < NREGS) & (op2 < NREGS) & (dest < NREGS) {
-            let result = self.reg[op1].wrapping_sub(self.reg[op2]);
-            self.set_reg(dest, result).unwrap();
+            let (a, b) = (self.reg[op1], self.reg[op2]);
+            let result = a.wrapping_sub(b);
+            let overflow = (a as i32).overflowing_sub(b as i32).1;
+            if overflow && self.trap_overflow {
+                return Err(MachineError::SignedOverflow);
+            }
+            self.set_reg(dest, result)?;
+            if let Some(status) = self.status {
+                let flags = [
+                    (result == 0, FLAG_ZERO),
+                    ((result as i32) < 0, FLAG_NEGATIVE),
+                    (a < b, FLAG_CARRY),
+                    (overflow, FLAG_OVERFLOW),
+                ];
+                self.reg[status] = flags.iter().filter(|(set, _)| *set).fold(0, |acc, (_, flag)| acc | flag);
+            }
             Ok(false)
         } else {
             Err(MachineError::BadRegisterName)
@@ -1,19 +1,699 @@
-use interpreter::{Machine, MachineError};
+use clap::{Args, Parser, Subcommand};
+use interpreter::compiler::{self, CompileError};
+use interpreter::gdb::GdbServer;
+use interpreter::inspect::{self, Diff};
+use interpreter::network::{MAX_NODES, Network, NetworkError, Policy, Status};
+use interpreter::analysis::Analysis;
+use interpreter::optimizer;
+use interpreter::safety::{Interval, Report};
+use interpreter::translator;
+use interpreter::{listing, listing_line, Halt, Instruction, Machine, MachineError, IP, MEMORY_SIZE, NREGS};
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() -> Result<(), MachineError> {
-    // Take a filename as argument on the command line
-    let filename = std::env::args().nth(1).unwrap();
+/// Exit code used when the interpreted program faults, does not compile, is
+/// not preserved by the optimizer or deadlocks with other programs.
+const EXIT_FAULT: u8 = 1;
+/// Exit code used when a file cannot be read or written.
+const EXIT_IO: u8 = 3;
 
-    // Read content to buffer
-    let mut fs = File::open(&filename).unwrap();
+#[derive(Parser)]
+#[clap(author, version, about = "Interpreter for the SE202 virtual machine", long_about = None)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a program until it exits
+    Run(RunArgs),
+    /// Run a program, printing every executed instruction on standard error
+    Trace(RunArgs),
+    /// Print the disassembly of a program
+    Disasm(FileArgs),
+    /// Run a program silently and print its final registers
+    DumpRegs(RunArgs),
+    /// Print the content of a program image, or of the memory after running it, in hexadecimal
+    Hexdump(HexdumpArgs),
+    /// Print the printable strings found in a program image, or in the memory after running it
+    Strings(StringsArgs),
+    /// Run two programs and print the differences between their final registers and memory
+    Diff(DiffArgs),
+    /// Compile a source program into a program image
+    Compile(CompileArgs),
+    /// Optimize a program image
+    Optimize(OptimizeArgs),
+    /// Report possible problems found in the control flow of a program
+    Analyze(AnalyzeArgs),
+    /// Report which memory accesses may fault and which outputs may not be ASCII
+    Check(CheckArgs),
+    /// Wait for a GDB connection and let it debug a program
+    Debug(DebugArgs),
+    /// Run several programs exchanging words through ports
+    Network(NetworkArgs),
+    /// Translate a program image into the source code of an equivalent Rust program
+    Translate(FileArgs),
+    /// Run a program and print its listing annotated with the number of executions of each instruction
+    Coverage(CoverageArgs),
+    /// Debug a program in a full-screen terminal interface
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+}
+
+#[derive(Args)]
+struct FileArgs {
+    /// Program image to load at address 0
+    file: PathBuf,
+
+    /// Write the output to this file instead of standard output
+    #[clap(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    /// Initial register value, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Print the registers on standard error once the program has stopped
+    #[clap(long = "dump-regs")]
+    dump_regs: bool,
+
+    /// Write the zero, negative, carry and overflow flags of every `sub` into this register, as `rN`
+    #[clap(long = "status-reg", parse(try_from_str = parse_status_register))]
+    status_reg: Option<usize>,
+
+    /// Fault when `sub` overflows as a signed operation instead of wrapping around
+    #[clap(long = "trap-overflow")]
+    trap_overflow: bool,
+}
+
+#[derive(Args)]
+struct MemoryArgs {
+    /// Run the program first and inspect the memory once it has stopped
+    #[clap(long = "run")]
+    run: bool,
+
+    /// Initial register value used by `--run`, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+}
+
+#[derive(Args)]
+struct HexdumpArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    #[clap(flatten)]
+    memory: MemoryArgs,
+
+    /// Address of the first byte to print
+    #[clap(long = "start", default_value = "0", parse(try_from_str = parse_number))]
+    start: usize,
+
+    /// Number of bytes to print, up to the end of the image (or of memory with `--run`) by default
+    #[clap(long = "length", parse(try_from_str = parse_number))]
+    length: Option<usize>,
+}
+
+#[derive(Args)]
+struct StringsArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    #[clap(flatten)]
+    memory: MemoryArgs,
+
+    /// Minimum number of printable characters in a string
+    #[clap(short = 'n', long = "min-len", default_value = "4")]
+    min_len: usize,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// First program image
+    first: PathBuf,
+
+    /// Second program image
+    second: PathBuf,
+
+    /// Initial register value of both programs, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Compare the initial states instead of running the programs
+    #[clap(long = "no-run")]
+    no_run: bool,
+}
+
+#[derive(Args)]
+struct CoverageArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    /// Initial register value, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Listing to annotate, such as the `.dis` file of the program, instead of its disassembly
+    #[clap(long = "listing")]
+    listing: Option<PathBuf>,
+
+    /// Also write the coverage as an lcov tracefile
+    #[clap(long = "lcov")]
+    lcov: Option<PathBuf>,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args)]
+struct TuiArgs {
+    /// Program image to load at address 0
+    file: PathBuf,
+
+    /// Initial register value, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+}
+
+#[derive(Args)]
+struct CompileArgs {
+    /// Source program
+    file: PathBuf,
+
+    /// Image to produce, the source file with a `.bin` extension by default
+    #[clap(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+
+    /// Print the generated code in the `.dis` format instead of producing an image
+    #[clap(long = "listing")]
+    listing: bool,
+}
+
+#[derive(Args)]
+struct OptimizeArgs {
+    /// Program image to optimize
+    file: PathBuf,
+
+    /// Image to produce
+    #[clap(short = 'o', long = "output")]
+    output: PathBuf,
+
+    /// Run the original and optimized programs and check that they behave the same
+    #[clap(long = "verify")]
+    verify: bool,
+
+    /// Initial register value used by `--verify`, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Maximum number of instructions executed by each program when verifying
+    #[clap(long = "max-steps", default_value = "10000000")]
+    max_steps: usize,
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    /// Print the control-flow graph in the Graphviz format instead
+    #[clap(long = "dot")]
+    dot: bool,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    #[clap(flatten)]
+    file: FileArgs,
+
+    /// Initial register value, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Consider that registers not given by `--reg` may initially hold any value, instead of 0
+    #[clap(long = "any-regs")]
+    any_regs: bool,
+}
+
+#[derive(Args)]
+struct DebugArgs {
+    /// Program image to load at address 0
+    file: PathBuf,
+
+    /// Initial register value, as `rN=VALUE` (may be repeated)
+    #[clap(short = 'r', long = "reg", parse(try_from_str = parse_reg))]
+    regs: Vec<(usize, u32)>,
+
+    /// Local TCP port to listen on
+    #[clap(short = 'p', long = "port", default_value = "1234")]
+    port: u16,
+
+    /// Number of instructions which can be executed backwards
+    #[clap(long = "history", default_value = "100000")]
+    history: usize,
+}
+
+#[derive(Args)]
+struct NetworkArgs {
+    /// Program images, the first one being node 0, at most 1024 of them
+    #[clap(required = true, max_values = MAX_NODES)]
+    files: Vec<PathBuf>,
+
+    /// Number of words each channel can hold
+    #[clap(long = "capacity", default_value = "1")]
+    capacity: usize,
+
+    /// Number of instructions each node runs before the next one, in round-robin scheduling
+    #[clap(long = "quantum", default_value = "1")]
+    quantum: usize,
+
+    /// Run the nodes in a random order generated from this seed instead of round-robin
+    #[clap(long = "seed", conflicts_with = "quantum")]
+    seed: Option<u64>,
+
+    /// Maximum number of instructions executed by all the nodes
+    #[clap(long = "max-steps", default_value = "10000000")]
+    max_steps: usize,
+}
+
+/// Errors which can stop the runner.
+enum Error {
+    Io(PathBuf, io::Error),
+    Machine(MachineError, u32),
+    Compile(PathBuf, CompileError),
+    Verify(optimizer::Mismatch),
+    Network(Option<NetworkError>),
+}
+
+/// Parse a register name such as `r10` or `10`.
+fn parse_register(reg: &str) -> Result<usize, String> {
+    let reg: usize = reg
+        .strip_prefix('r')
+        .unwrap_or(reg)
+        .parse()
+        .map_err(|_| format!("invalid register `{}`", reg))?;
+    if reg >= NREGS {
+        return Err(format!("register r{} does not exist", reg));
+    }
+    Ok(reg)
+}
+
+/// Parse the name of a register which can hold the flags.
+fn parse_status_register(reg: &str) -> Result<usize, String> {
+    match parse_register(reg)? {
+        IP => Err(String::from("r0 is the instruction pointer")),
+        reg => Ok(reg),
+    }
+}
+
+/// Parse an initial register assignment such as `r10=5`, `r3=-1` or `r1=0x40`.
+fn parse_reg(s: &str) -> Result<(usize, u32), String> {
+    let (reg, value) = s.split_once('=').ok_or("expected rN=VALUE")?;
+    let reg = parse_register(reg)?;
+    let value = if let Some(hex) = value.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse::<i64>().ok().and_then(|v| {
+            if (i32::MIN as i64..=u32::MAX as i64).contains(&v) {
+                Some(v as u32)
+            } else {
+                None
+            }
+        })
+    };
+    value.map(|v| (reg, v)).ok_or(format!("invalid value `{}`", s))
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid number `{}`", s))
+}
+
+/// Read the program image contained in `path`.
+fn read_image(path: &Path) -> Result<Vec<u8>, Error> {
     let mut buffer = Vec::new();
-    fs.read_to_end(&mut buffer).unwrap();
+    File::open(path)
+        .and_then(|mut fs| fs.read_to_end(&mut buffer))
+        .map_err(|e| Error::Io(path.to_owned(), e))?;
+    if buffer.len() > MEMORY_SIZE {
+        let e = io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("image is larger than the {} bytes of memory", MEMORY_SIZE),
+        );
+        return Err(Error::Io(path.to_owned(), e));
+    }
+    Ok(buffer)
+}
+
+/// Open the requested output, standard output being used by default.
+fn open_output(path: &Option<PathBuf>) -> Result<Box<dyn Write>, Error> {
+    match path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| Error::Io(path.clone(), e))?;
+            Ok(Box::new(io::BufWriter::new(file)))
+        }
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+/// Print the registers of `machine`, four per line.
+fn dump_regs<T: Write>(machine: &Machine, out: &mut T) -> io::Result<()> {
+    for (i, value) in machine.regs().iter().enumerate() {
+        let sep = if i % 4 == 3 { "\n" } else { "  " };
+        write!(out, "r{:<2} = {:#010x}{}", i, value, sep)?;
+    }
+    Ok(())
+}
+
+/// Run the program described by `args`. When `trace` is set, every instruction
+/// is printed on standard error before being executed.
+fn run(args: &RunArgs, trace: bool, silent: bool) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let mut machine = Machine::new(&image);
+    for &(reg, value) in &args.regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    machine.set_status_register(args.status_reg).unwrap();
+    machine.set_trap_on_overflow(args.trap_overflow);
+    let mut out: Box<dyn Write> = if silent {
+        Box::new(io::sink())
+    } else {
+        open_output(&args.file.output)?
+    };
+    let stderr = io::stderr();
+    let mut err = stderr.lock();
+    let halt = if trace {
+        // Execute one instruction at a time, printing it beforehand
+        loop {
+            let ip = machine.regs()[IP] as usize;
+            if let Some(Ok(instr)) = machine.memory().get(ip..).map(Instruction::decode) {
+                let _ = writeln!(err, "{}", listing_line(machine.memory(), ip, Some(instr)));
+            }
+            match machine.run_until(1, &mut out).0 {
+                Halt::StepLimit => (),
+                halt => break halt,
+            }
+        }
+    } else {
+        machine.run_until(usize::MAX, &mut out).0
+    };
+    let result = match halt {
+        Halt::Fault(e, ip) => Err(Error::Machine(e, ip)),
+        _ => Ok(()),
+    };
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    out.flush().map_err(|e| Error::Io(output.clone(), e))?;
+    if silent {
+        let mut out = open_output(&args.file.output)?;
+        dump_regs(&machine, &mut out).and_then(|_| out.flush()).map_err(|e| Error::Io(output, e))?;
+    } else if args.dump_regs {
+        let _ = dump_regs(&machine, &mut err);
+    }
+    result
+}
 
-    // Create a machine with this memory content
-    let mut machine = Machine::new(&buffer);
+/// Print the disassembly of the image, in the same format as the `.dis` listings.
+fn disasm(args: &FileArgs) -> Result<(), Error> {
+    let image = read_image(&args.file)?;
+    let mut out = open_output(&args.output)?;
+    let output = args.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    out.write_all(listing(&image).as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::Io(output, e))
+}
+
+/// Load a program with its initial registers, and run it silently when `run` is set.
+fn load_machine(image: &[u8], regs: &[(usize, u32)], run: bool) -> Result<Machine, Error> {
+    let mut machine = Machine::new(image);
+    for &(reg, value) in regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    if run {
+        if let (Halt::Fault(e, ip), _) = machine.run_until(usize::MAX, &mut io::sink()) {
+            return Err(Error::Machine(e, ip));
+        }
+    }
+    Ok(machine)
+}
+
+/// Print the image, or the memory after running it, as hexadecimal bytes, 16
+/// per line, followed by their ASCII representation.
+fn hexdump(args: &HexdumpArgs) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let machine = load_machine(&image, &args.memory.regs, args.memory.run)?;
+    let size = if args.memory.run { MEMORY_SIZE } else { image.len() };
+    let start = args.start.min(size);
+    let end = args.length.map_or(size, |length| start.saturating_add(length).min(size));
+    let mut out = open_output(&args.file.output)?;
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    out.write_all(inspect::hexdump(machine.memory(), start..end).as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::Io(output, e))
+}
+
+/// Print the printable strings of the image, or of the memory after running it.
+fn strings(args: &StringsArgs) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let machine = load_machine(&image, &args.memory.regs, args.memory.run)?;
+    let size = if args.memory.run { MEMORY_SIZE } else { image.len() };
+    let mut out = open_output(&args.file.output)?;
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    let mut write_strings = || -> io::Result<()> {
+        for (addr, string) in inspect::strings(&machine.memory()[..size], args.min_len) {
+            writeln!(out, "{:04x}  {}", addr, string)?;
+        }
+        out.flush()
+    };
+    write_strings().map_err(|e| Error::Io(output, e))
+}
+
+/// Print the differences between the final states of two programs.
+fn diff(args: &DiffArgs) -> Result<(), Error> {
+    let first = load_machine(&read_image(&args.first)?, &args.regs, !args.no_run)?;
+    let second = load_machine(&read_image(&args.second)?, &args.regs, !args.no_run)?;
+    let mut out = io::stdout().lock();
+    write!(out, "{}", Diff::between(&first, &second))
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::Io(PathBuf::from("<stdout>"), e))
+}
+
+/// Compile a source file into an image, or print its listing.
+fn compile(args: &CompileArgs) -> Result<(), Error> {
+    let source = std::fs::read_to_string(&args.file).map_err(|e| Error::Io(args.file.clone(), e))?;
+    let compile_error = |e| Error::Compile(args.file.clone(), e);
+    let asm = compiler::compile(&source).map_err(compile_error)?;
+    if args.listing {
+        let mut out = open_output(&args.output)?;
+        let output = args.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+        out.write_all(asm.listing().as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::Io(output, e))
+    } else {
+        let image = asm
+            .assemble()
+            .map_err(|e| compile_error(CompileError { line: None, message: e.to_string() }))?;
+        let output = args.output.clone().unwrap_or_else(|| args.file.with_extension("bin"));
+        std::fs::write(&output, image).map_err(|e| Error::Io(output, e))
+    }
+}
+
+/// Optimize an image, and check the result when requested.
+fn optimize(args: &OptimizeArgs) -> Result<(), Error> {
+    let image = read_image(&args.file)?;
+    let optimized = optimizer::optimize(&image);
+    eprintln!("{} instructions removed", optimized.removed);
+    if args.verify {
+        let (before, after) =
+            optimizer::verify(&image, &optimized, &args.regs, args.max_steps).map_err(Error::Verify)?;
+        eprintln!("{} instructions executed instead of {}", after.steps, before.steps);
+    }
+    std::fs::write(&args.output, &optimized.image).map_err(|e| Error::Io(args.output.clone(), e))
+}
+
+/// Print the problems found in the control flow of a program, or its graph.
+fn analyze(args: &AnalyzeArgs) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let analysis = Analysis::analyze(&image);
+    let mut out = open_output(&args.file.output)?;
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    let mut write_report = || -> io::Result<()> {
+        if args.dot {
+            out.write_all(analysis.cfg.to_dot().as_bytes())?;
+        } else {
+            for diagnostic in &analysis.diagnostics {
+                writeln!(out, "{}", diagnostic)?;
+            }
+        }
+        out.flush()
+    };
+    write_report().map_err(|e| Error::Io(output, e))
+}
+
+/// Print the checks of the memory accesses and outputs of a program.
+fn check(args: &CheckArgs) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let mut regs = [if args.any_regs { Interval::TOP } else { Interval::constant(0) }; NREGS];
+    regs[IP] = Interval::constant(0);
+    for &(reg, value) in &args.regs {
+        regs[reg] = Interval::constant(value);
+    }
+    let report = Report::analyze(&image, &regs);
+    let mut out = open_output(&args.file.output)?;
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    write!(out, "{}", report).and_then(|_| out.flush()).map_err(|e| Error::Io(output, e))
+}
+
+/// Serve a single GDB session debugging a program.
+fn debug(args: &DebugArgs) -> Result<(), Error> {
+    let image = read_image(&args.file)?;
+    let mut machine = Machine::new(&image);
+    for &(reg, value) in &args.regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    machine.set_history_size(args.history);
+    let address = PathBuf::from(format!("127.0.0.1:{}", args.port));
+    let server = GdbServer::bind(("127.0.0.1", args.port)).map_err(|e| Error::Io(address.clone(), e))?;
+    eprintln!("waiting for GDB on port {}", args.port);
+    server.serve(&mut machine, &mut io::stdout()).map_err(|e| Error::Io(address, e))
+}
+
+/// Run a network of programs, then print the output of every node.
+fn network(args: &NetworkArgs) -> Result<(), Error> {
+    let images = args.files.iter().map(|file| read_image(file)).collect::<Result<Vec<_>, _>>()?;
+    let policy = match args.seed {
+        Some(seed) => Policy::Random { seed },
+        None => Policy::RoundRobin { quantum: args.quantum.max(1) },
+    };
+    let machines = images.iter().map(|image| Machine::new(image)).collect();
+    let mut network = Network::new(machines, args.capacity.max(1), policy);
+    let result = network.run(args.max_steps);
+    let mut out = io::stdout();
+    let mut faulted = false;
+    for (i, (node, file)) in network.nodes().iter().zip(&args.files).enumerate() {
+        let status = match node.status {
+            Status::Running => String::from("blocked"),
+            Status::Exited => String::from("exited"),
+            Status::Faulted(e) => {
+                faulted = true;
+                format!("fault at address {}: {}", node.machine.regs()[IP], e)
+            }
+        };
+        eprintln!("node {} ({}): {} after {} instructions", i, file.display(), status, node.steps);
+        out.write_all(&node.output).map_err(|e| Error::Io(PathBuf::from("<stdout>"), e))?;
+    }
+    match result {
+        Ok(_) if !faulted => Ok(()),
+        Ok(_) => Err(Error::Network(None)),
+        Err(e) => Err(Error::Network(Some(e))),
+    }
+}
+
+/// Write the Rust translation of a program image.
+fn translate(args: &FileArgs) -> Result<(), Error> {
+    let image = read_image(&args.file)?;
+    let mut out = open_output(&args.output)?;
+    let output = args.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    out.write_all(translator::translate(&image).as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::Io(output, e))
+}
+
+/// Run a program, then print its annotated listing and write its lcov tracefile.
+fn coverage(args: &CoverageArgs) -> Result<(), Error> {
+    let image = read_image(&args.file.file)?;
+    let (name, listing) = match &args.listing {
+        Some(path) => {
+            let listing = std::fs::read_to_string(path).map_err(|e| Error::Io(path.clone(), e))?;
+            (path.clone(), listing)
+        }
+        None => (args.file.file.clone(), listing(&image)),
+    };
+    let mut machine = Machine::new(&image);
+    for &(reg, value) in &args.regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    machine.set_coverage(true);
+    let result = match machine.run_until(usize::MAX, &mut io::sink()) {
+        (Halt::Fault(e, ip), _) => Err(Error::Machine(e, ip)),
+        _ => Ok(()),
+    };
+    let coverage = machine.coverage().unwrap();
+    let mut out = open_output(&args.file.output)?;
+    let output = args.file.output.clone().unwrap_or_else(|| PathBuf::from("<stdout>"));
+    out.write_all(coverage.annotate(&listing).as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::Io(output, e))?;
+    eprintln!("{}", coverage.summary(&listing));
+    if let Some(path) = &args.lcov {
+        std::fs::write(path, coverage.lcov(&name.display().to_string(), &listing))
+            .map_err(|e| Error::Io(path.clone(), e))?;
+    }
+    result
+}
+
+/// Debug a program in the terminal.
+#[cfg(feature = "tui")]
+fn tui(args: &TuiArgs) -> Result<(), Error> {
+    let image = read_image(&args.file)?;
+    let machine = load_machine(&image, &args.regs, false)?;
+    interpreter::tui::run(machine).map_err(|e| Error::Io(PathBuf::from("<terminal>"), e))
+}
 
-    // Run the machine until the end
-    machine.run()
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Run(args) => run(args, false, false),
+        Command::Trace(args) => run(args, true, false),
+        Command::DumpRegs(args) => run(args, false, true),
+        Command::Disasm(args) => disasm(args),
+        Command::Hexdump(args) => hexdump(args),
+        Command::Strings(args) => strings(args),
+        Command::Diff(args) => diff(args),
+        Command::Compile(args) => compile(args),
+        Command::Optimize(args) => optimize(args),
+        Command::Analyze(args) => analyze(args),
+        Command::Check(args) => check(args),
+        Command::Debug(args) => debug(args),
+        Command::Network(args) => network(args),
+        Command::Translate(args) => translate(args),
+        Command::Coverage(args) => coverage(args),
+        #[cfg(feature = "tui")]
+        Command::Tui(args) => tui(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Error::Io(path, e)) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            ExitCode::from(EXIT_IO)
+        }
+        Err(Error::Machine(e, ip)) => {
+            eprintln!("error: program fault at address {}: {}", ip, e);
+            ExitCode::from(EXIT_FAULT)
+        }
+        Err(Error::Compile(path, e)) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            ExitCode::from(EXIT_FAULT)
+        }
+        Err(Error::Verify(e)) => {
+            eprintln!("error: optimized program differs: {}", e);
+            ExitCode::from(EXIT_FAULT)
+        }
+        Err(Error::Network(e)) => {
+            if let Some(e) = e {
+                eprintln!("error: {}", e);
+            }
+            ExitCode::from(EXIT_FAULT)
+        }
+    }
 }
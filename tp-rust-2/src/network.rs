@@ -0,0 +1,307 @@
+//! Several machines running concurrently and exchanging words.
+//!
+//! Every node of a [Network] is a [Machine] with its own memory and output.
+//! Nodes talk through memory-mapped ports located above the end of memory:
+//! storing a word at [port(n)](port) sends it to node `n`, and loading a word
+//! from `port(n)` receives the next word sent by node `n`. Each pair of nodes
+//! is connected by a bounded channel, so sending blocks while the channel is
+//! full and receiving blocks while it is empty.
+//!
+//! The scheduler executes one instruction at a time with
+//! [Machine::step_on], choosing the next node according to a [Policy]. When
+//! every node which did not terminate is blocked, the network is deadlocked.
+//! Port accesses are performed by the scheduler instead of [Machine::step_on],
+//! so they are neither recorded in the machine history nor counted in its
+//! coverage, and stepping back over them is not possible.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::{Instruction, Machine, MachineError, IP, MEMORY_SIZE};
+
+/// Address of the port of node 0. `loadimm r <- #-4096` loads it.
+pub const PORT_BASE: u32 = 0xFFFF_F000;
+/// Maximum number of nodes, so that all ports fit above [PORT_BASE].
+pub const MAX_NODES: usize = 1024;
+
+/// Address of the port used to communicate with node `node`.
+pub const fn port(node: usize) -> u32 {
+    PORT_BASE + 4 * node as u32
+}
+
+/// How the scheduler interleaves the execution of the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Run each node in turn for `quantum` instructions, or until it blocks
+    /// or terminates.
+    RoundRobin { quantum: usize },
+    /// Run a node chosen at random among those which can progress, one
+    /// instruction at a time. The same seed always gives the same interleaving.
+    Random { seed: u64 },
+}
+
+/// State of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Exited,
+    Faulted(MachineError),
+}
+
+/// A blocked port access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wait {
+    /// The node sends to `to`, whose channel is full.
+    Send { to: usize },
+    /// The node receives from `from`, whose channel is empty.
+    Recv { from: usize },
+}
+
+impl fmt::Display for Wait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Wait::Send { to } => write!(f, "sending to node {}", to),
+            Wait::Recv { from } => write!(f, "receiving from node {}", from),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    /// Every node which did not terminate is blocked, as described for each
+    /// of them by `(node, wait)`.
+    Deadlock(Vec<(usize, Wait)>),
+    /// The maximum number of instructions was executed.
+    StepLimit,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Deadlock(waits) => {
+                write!(f, "deadlock:")?;
+                for (i, (node, wait)) in waits.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(f, "{} node {} is {}", sep, node, wait)?;
+                }
+                Ok(())
+            }
+            NetworkError::StepLimit => write!(f, "step limit reached"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// A machine of the network.
+pub struct Node {
+    pub machine: Machine,
+    /// What the node printed.
+    pub output: Vec<u8>,
+    pub status: Status,
+    /// Number of instructions executed, port accesses included.
+    pub steps: usize,
+}
+
+/// What the next instruction of a node does with the ports.
+enum Access {
+    Send { to: usize, value: u32 },
+    Recv { from: usize, dest: usize },
+}
+
+/// State of the xorshift generator for `seed`, scrambled by the splitmix64
+/// finalizer so that different seeds give different schedules. It is a
+/// bijection, and only the seed mapped to 0, which xorshift cannot start
+/// from, shares its state with another one.
+fn initial_state(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    match z ^ (z >> 31) {
+        0 => 0x9E37_79B9_7F4A_7C15,
+        state => state,
+    }
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    /// Channel from node `s` to node `r` at index `s * nodes.len() + r`.
+    channels: Vec<VecDeque<u32>>,
+    capacity: usize,
+    policy: Policy,
+    /// Node currently running, and number of instructions left in its
+    /// quantum, for round-robin scheduling.
+    current: usize,
+    quantum_left: usize,
+    rng: u64,
+}
+
+impl Network {
+    /// Create a network of `machines`, node `n` being `machines[n]`. Every
+    /// channel holds at most `capacity` words.
+    ///
+    /// # Panics
+    /// This function panics when there are more than [MAX_NODES] machines,
+    /// when `capacity` is 0 or when a round-robin quantum is 0.
+    pub fn new(machines: Vec<Machine>, capacity: usize, policy: Policy) -> Self {
+        assert!(machines.len() <= MAX_NODES, "too many nodes");
+        assert!(capacity > 0, "channels must hold at least one word");
+        let quantum_left = match policy {
+            Policy::RoundRobin { quantum } => {
+                assert!(quantum > 0, "quantum must be at least one instruction");
+                quantum
+            }
+            Policy::Random { .. } => 0,
+        };
+        let rng = match policy {
+            Policy::Random { seed } => initial_state(seed),
+            Policy::RoundRobin { .. } => 1,
+        };
+        let n = machines.len();
+        Network {
+            nodes: machines
+                .into_iter()
+                .map(|machine| Node { machine, output: Vec::new(), status: Status::Running, steps: 0 })
+                .collect(),
+            channels: vec![VecDeque::new(); n * n],
+            capacity,
+            policy,
+            current: 0,
+            quantum_left,
+            rng,
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Words sent by node `from` and not yet received by node `to`.
+    pub fn pending(&self, from: usize, to: usize) -> &VecDeque<u32> {
+        &self.channels[from * self.nodes.len() + to]
+    }
+
+    /// Whether every node exited or faulted.
+    pub fn finished(&self) -> bool {
+        self.nodes.iter().all(|node| node.status != Status::Running)
+    }
+
+    /// Execute one instruction of one node. `true` is returned once every
+    /// node has terminated.
+    pub fn step(&mut self) -> Result<bool, NetworkError> {
+        if self.finished() {
+            return Ok(true);
+        }
+        let node = self.schedule()?;
+        self.execute(node);
+        Ok(self.finished())
+    }
+
+    /// Run until every node terminates, executing at most `max_steps`
+    /// instructions. The number of instructions executed is returned.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize, NetworkError> {
+        let mut steps = 0;
+        while !self.finished() {
+            if steps == max_steps {
+                return Err(NetworkError::StepLimit);
+            }
+            self.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Choose the node executing the next instruction.
+    fn schedule(&mut self) -> Result<usize, NetworkError> {
+        let n = self.nodes.len();
+        let ready: Vec<usize> = (0..n)
+            .filter(|&node| self.nodes[node].status == Status::Running && self.wait(node).is_none())
+            .collect();
+        if ready.is_empty() {
+            let waits = (0..n).filter_map(|node| Some((node, self.wait(node)?))).collect();
+            return Err(NetworkError::Deadlock(waits));
+        }
+        match self.policy {
+            Policy::RoundRobin { quantum } => {
+                if self.quantum_left == 0 || !ready.contains(&self.current) {
+                    self.current = (1..=n).map(|i| (self.current + i) % n).find(|node| ready.contains(node)).unwrap();
+                    self.quantum_left = quantum;
+                }
+                self.quantum_left -= 1;
+                Ok(self.current)
+            }
+            Policy::Random { .. } => Ok(ready[self.random() as usize % ready.len()]),
+        }
+    }
+
+    /// xorshift64 generator.
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    /// Why a running node cannot execute its next instruction, if it cannot.
+    /// Terminated nodes are never ready but are not waiting either.
+    fn wait(&self, node: usize) -> Option<Wait> {
+        if self.nodes[node].status != Status::Running {
+            return None;
+        }
+        match self.access(node) {
+            Some(Access::Send { to, .. }) if self.pending(node, to).len() >= self.capacity => Some(Wait::Send { to }),
+            Some(Access::Recv { from, .. }) if self.pending(from, node).is_empty() => Some(Wait::Recv { from }),
+            _ => None,
+        }
+    }
+
+    /// The port access done by the next instruction of `node`, if any.
+    fn access(&self, node: usize) -> Option<Access> {
+        let machine = &self.nodes[node].machine;
+        let ip = machine.regs()[IP] as usize;
+        let instr = Instruction::decode(machine.memory().get(ip..MEMORY_SIZE)?).ok()?;
+        let peer = |addr: u32| {
+            let offset = addr.checked_sub(PORT_BASE)?;
+            let peer = offset as usize / 4;
+            (offset % 4 == 0 && peer < self.nodes.len()).then_some(peer)
+        };
+        match instr {
+            Instruction::Store { addr, src } => {
+                Some(Access::Send { to: peer(machine.regs()[addr])?, value: machine.regs()[src] })
+            }
+            Instruction::Load { dest, addr } => Some(Access::Recv { from: peer(machine.regs()[addr])?, dest }),
+            _ => None,
+        }
+    }
+
+    /// Execute the next instruction of `node`, which must be ready.
+    fn execute(&mut self, node: usize) {
+        let n = self.nodes.len();
+        let result = match self.access(node) {
+            Some(access) => {
+                // Loads and stores are 3 bytes long, and IP is advanced first as with any instruction
+                let machine = &mut self.nodes[node].machine;
+                machine.set_reg(IP, machine.regs()[IP] + 3).unwrap();
+                match access {
+                    Access::Send { to, value } => self.channels[node * n + to].push_back(value),
+                    Access::Recv { from, dest } => {
+                        let value = self.channels[from * n + node].pop_front().unwrap();
+                        self.nodes[node].machine.set_reg(dest, value).unwrap();
+                    }
+                }
+                Ok(false)
+            }
+            None => {
+                let Node { machine, output, .. } = &mut self.nodes[node];
+                machine.step_on(output)
+            }
+        };
+        let node = &mut self.nodes[node];
+        node.steps += 1;
+        match result {
+            Ok(false) => (),
+            Ok(true) => node.status = Status::Exited,
+            Err(e) => node.status = Status::Faulted(e),
+        }
+    }
+}
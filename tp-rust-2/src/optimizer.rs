@@ -0,0 +1,448 @@
+//! Peephole optimizer for program images.
+//!
+//! The basic blocks are recovered by [crate::analysis], and each of them is
+//! simplified:
+//!   - `loadimm` of a value already held by the register are removed, and
+//!     `sub` of two known constants become a `loadimm`;
+//!   - chains of `sub rD <- rD - rK` with constant `rK` are folded;
+//!   - writes to a register which is overwritten before being read are removed.
+//!
+//! Finally the code is laid out again, jump targets and return addresses
+//! being patched. Bytes which are not reachable code, such as strings, keep
+//! their original address so that references to them remain valid.
+//!
+//! Images with jumps to unknown addresses or into data are returned unchanged.
+//! So are images returning through memory (`load r0 <- [..]`) which may hold
+//! other code addresses than the return points of calls, such as function
+//! pointers or jump tables, as the optimizer cannot relocate them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::analysis::{self, reads, writes};
+use crate::{Halt, Instruction, Machine, MachineError, IP, NREGS};
+
+/// An instruction of the original program.
+#[derive(Debug, Clone, Copy)]
+struct Code {
+    /// Original address.
+    addr: usize,
+    instr: Instruction,
+    /// For a `loadimm` of a code address, the original address it refers to.
+    target: Option<usize>,
+}
+
+/// Result of [optimize].
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    /// The optimized program image.
+    pub image: Vec<u8>,
+    /// New address of the beginning of every original basic block and of the
+    /// end of every instruction kept, that is the possible values of IP.
+    pub relocation: BTreeMap<u32, u32>,
+    /// Number of instructions removed.
+    pub removed: usize,
+}
+
+impl Optimized {
+    /// Program returned unchanged.
+    fn unchanged(image: &[u8]) -> Self {
+        let relocation = (0..=image.len() as u32).map(|a| (a, a)).collect();
+        Optimized { image: image.to_vec(), relocation, removed: 0 }
+    }
+}
+
+/// Whether executing the instruction may fail or have a visible effect.
+fn has_effect(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::Store { .. } | Instruction::Load { .. } | Instruction::Out { .. } | Instruction::OutNumber { .. }
+    )
+}
+
+/// Whether the instruction ends a basic block.
+fn ends_block(instr: &Instruction) -> bool {
+    *instr == Instruction::Exit || writes(instr) == Some(IP)
+}
+
+/// Simplify a basic block by propagating constants.
+fn propagate_constants(block: &mut Vec<Code>) {
+    let mut known: [Option<u32>; NREGS] = [None; NREGS];
+    let mut result = Vec::with_capacity(block.len());
+    for code in block.drain(..) {
+        let mut code = code;
+        if let Instruction::Sub { dest, op1, op2 } = code.instr {
+            if let (Some(a), Some(b)) = (known[op1], known[op2]) {
+                let value = a.wrapping_sub(b) as i32;
+                if dest != IP && i16::try_from(value).is_ok() {
+                    code.instr = Instruction::LoadImm { dest, value: value as i16 };
+                }
+            }
+        }
+        match code.instr {
+            Instruction::LoadImm { dest, value } if code.target.is_none() => {
+                let value = value as i32 as u32;
+                if known[dest] == Some(value) {
+                    continue;
+                }
+                known[dest] = Some(value);
+            }
+            Instruction::MoveIf { dest, src, cond } if dest != IP => {
+                let taken = if cond == IP { Some(true) } else { known[cond].map(|c| c != 0) };
+                match taken {
+                    Some(false) => continue,
+                    Some(true) if known[src].is_some() && known[src] == known[dest] => continue,
+                    Some(true) => {
+                        known[dest] = known[src];
+                        if let Some(value) = known[src].and_then(|v| i16::try_from(v as i32).ok()) {
+                            code.instr = Instruction::LoadImm { dest, value };
+                        }
+                    }
+                    None if known[src] == known[dest] => (),
+                    None => known[dest] = None,
+                }
+            }
+            Instruction::Sub { dest, op1, op2 } => {
+                known[dest] = match (known[op1], known[op2]) {
+                    (Some(a), Some(b)) => Some(a.wrapping_sub(b)),
+                    _ => None,
+                }
+            }
+            ref instr => {
+                if let Some(dest) = writes(instr) {
+                    known[dest] = None;
+                }
+            }
+        }
+        result.push(code);
+    }
+    *block = result;
+}
+
+/// Fold `sub rD <- rD - rK1` followed by `sub rD <- rD - rK2`, both with
+/// constant operands, when nothing in between uses rD or may fail.
+fn fold_sub_chains(block: &mut Vec<Code>) {
+    let mut known: Vec<[Option<u32>; NREGS]> = Vec::with_capacity(block.len());
+    let mut state: [Option<u32>; NREGS] = [None; NREGS];
+    for code in block.iter() {
+        known.push(state);
+        match code.instr {
+            Instruction::LoadImm { dest, value } if code.target.is_none() => state[dest] = Some(value as i32 as u32),
+            ref instr => {
+                if let Some(dest) = writes(instr) {
+                    state[dest] = None;
+                }
+            }
+        }
+    }
+    for i in 0..block.len() {
+        let (d, c1) = match block[i].instr {
+            Instruction::Sub { dest, op1, op2 } if dest == op1 && dest != IP && op2 != dest => match known[i][op2] {
+                Some(c) => (dest, c),
+                None => continue,
+            },
+            _ => continue,
+        };
+        for j in i + 1..block.len() {
+            let instr = block[j].instr;
+            if let Instruction::Sub { dest, op1, op2 } = instr {
+                if dest == d && op1 == d && op2 != d {
+                    if let Some(c2) = known[j][op2] {
+                        let total = c1.wrapping_add(c2);
+                        if total == 0 {
+                            block.remove(j);
+                            block.remove(i);
+                            return;
+                        }
+                        if let Some(reg) = (1..NREGS).find(|&r| r != d && known[j][r] == Some(total)) {
+                            block[j].instr = Instruction::Sub { dest: d, op1: d, op2: reg };
+                            block.remove(i);
+                            return;
+                        }
+                    }
+                }
+            }
+            if reads(&instr).contains(&d) || writes(&instr) == Some(d) || has_effect(&instr) || ends_block(&instr) {
+                break;
+            }
+        }
+    }
+}
+
+/// Remove writes to registers which are overwritten before being read.
+/// Every register is considered as read at the end of the block and by
+/// instructions which may fail.
+fn remove_dead_writes(block: &mut Vec<Code>) {
+    let mut live = [true; NREGS];
+    let mut keep = vec![true; block.len()];
+    for (i, code) in block.iter().enumerate().rev() {
+        let instr = &code.instr;
+        let unconditional = match *instr {
+            Instruction::MoveIf { cond, .. } => cond == IP,
+            Instruction::LoadImm { .. } | Instruction::Sub { .. } => true,
+            _ => false,
+        };
+        if let Some(dest) = writes(instr) {
+            if unconditional && dest != IP && !live[dest] {
+                keep[i] = false;
+                continue;
+            }
+            if unconditional {
+                live[dest] = false;
+            }
+        }
+        if has_effect(instr) {
+            live = [true; NREGS];
+        }
+        let read = match *instr {
+            Instruction::MoveIf { src, cond, .. } if unconditional => vec![src, cond],
+            _ => reads(instr),
+        };
+        for r in read {
+            live[r] = true;
+        }
+    }
+    let mut i = 0;
+    block.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+}
+
+/// Optimize a program image.
+pub fn optimize(image: &[u8]) -> Optimized {
+    let (cfg, problems) = analysis::recover(image);
+    let mut end = 0;
+    for (addr, instr) in cfg.instructions() {
+        // Overlapping instructions cannot be moved independently.
+        if addr < end {
+            return Optimized::unchanged(image);
+        }
+        end = addr + instr.size();
+    }
+    if !problems.is_empty() {
+        return Optimized::unchanged(image);
+    }
+
+    let mut blocks: BTreeMap<usize, Vec<Code>> = cfg
+        .blocks
+        .values()
+        .map(|block| {
+            let code = block.instructions.iter().map(|&(addr, instr)| Code {
+                addr,
+                instr,
+                target: cfg.code_addresses.get(&addr).copied(),
+            });
+            (block.start, code.collect())
+        })
+        .collect();
+    let size = |block: &[Code]| block.iter().map(|c| c.instr.size()).sum::<usize>();
+    let original: BTreeMap<usize, usize> = blocks.iter().map(|(&start, block)| (start, size(block))).collect();
+
+    for block in blocks.values_mut() {
+        loop {
+            let before: Vec<Instruction> = block.iter().map(|c| c.instr).collect();
+            propagate_constants(block);
+            fold_sub_chains(block);
+            remove_dead_writes(block);
+            if block.iter().map(|c| c.instr).eq(before) {
+                break;
+            }
+        }
+    }
+
+    // Bytes which are not reachable code, such as strings, stay where they were.
+    let mut output = image.to_vec();
+    let mut is_code = vec![false; image.len()];
+    for (addr, instr) in cfg.instructions() {
+        let range = addr..addr + instr.size();
+        is_code[range.clone()].fill(true);
+        output[range].fill(0);
+    }
+
+    // Move every block as close as possible to the previous one. As blocks
+    // only shrink, they never overlap the bytes kept in place.
+    let mut relocation = BTreeMap::new();
+    let mut cursor = 0;
+    for (&start, block) in &blocks {
+        if let Some(last) = (cursor..start).rev().find(|&a| !is_code[a]) {
+            cursor = last + 1;
+        }
+        relocation.insert(start as u32, cursor as u32);
+        for code in block {
+            cursor += code.instr.size();
+            relocation.insert((code.addr + code.instr.size()) as u32, cursor as u32);
+        }
+        relocation.insert((start + original[&start]) as u32, cursor as u32);
+    }
+    if !returns_are_relocated(image, &cfg, &is_code, &relocation) {
+        return Optimized::unchanged(image);
+    }
+    for (&start, block) in &blocks {
+        let mut cursor = relocation[&(start as u32)] as usize;
+        for code in block {
+            let mut instr = code.instr;
+            if let (Some(target), Instruction::LoadImm { dest, .. }) = (code.target, instr) {
+                instr = Instruction::LoadImm { dest, value: relocation[&(target as u32)] as i16 };
+            }
+            let bytes = instr.encode();
+            output[cursor..cursor + bytes.len()].copy_from_slice(&bytes);
+            cursor += bytes.len();
+        }
+    }
+    let removed = cfg.instructions().count() - blocks.values().map(Vec::len).sum::<usize>();
+    Optimized { image: output, relocation, removed }
+}
+
+/// Whether the jumps through memory of the program can only go to relocated
+/// addresses. The code addresses which may be stored in memory must not be
+/// moved: those loaded by a `loadimm` which is not patched (unlike the return
+/// points of calls) and which may reach a `store`, and those found in the data.
+fn returns_are_relocated(
+    image: &[u8],
+    cfg: &analysis::Cfg,
+    is_code: &[bool],
+    relocation: &BTreeMap<u32, u32>,
+) -> bool {
+    if !cfg.blocks.values().any(|b| b.terminator == analysis::Terminator::Return) {
+        return true;
+    }
+    let moved = |value: u32| {
+        let addr = value as usize;
+        addr < image.len()
+            && is_code[addr]
+            && cfg.instructions().any(|(a, _)| a == addr)
+            && relocation.get(&value) != Some(&value)
+    };
+    let mut data = image
+        .windows(4)
+        .enumerate()
+        .filter(|&(addr, _)| !is_code[addr..addr + 4].contains(&true))
+        .map(|(_, word)| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+    if data.any(moved) {
+        return false;
+    }
+    // Registers which may hold a moved code address when entering every block.
+    let mut entry: BTreeMap<usize, [bool; NREGS]> = BTreeMap::from([(0, [false; NREGS])]);
+    let mut visited = BTreeSet::new();
+    let mut work = vec![0];
+    while let Some(start) = work.pop() {
+        visited.insert(start);
+        let mut held = entry[&start];
+        for &(addr, instr) in &cfg.blocks[&start].instructions {
+            match instr {
+                Instruction::Store { src, .. } if held[src] => return false,
+                Instruction::LoadImm { dest, value } => {
+                    held[dest] = !cfg.code_addresses.contains_key(&addr) && moved(value as i32 as u32)
+                }
+                Instruction::MoveIf { dest, src, cond } if cond == IP => held[dest] = held[src],
+                Instruction::MoveIf { dest, src, .. } => held[dest] |= held[src],
+                Instruction::Sub { dest, op1, op2 } => held[dest] = held[op1] || held[op2],
+                ref instr => {
+                    if let Some(dest) = writes(instr) {
+                        held[dest] = false;
+                    }
+                }
+            }
+        }
+        for next in cfg.successors(start) {
+            let state = entry.entry(next).or_insert([false; NREGS]);
+            let joined: [bool; NREGS] = std::array::from_fn(|r| state[r] || held[r]);
+            if joined != *state || !visited.contains(&next) {
+                *state = joined;
+                if !work.contains(&next) {
+                    work.push(next);
+                }
+            }
+        }
+    }
+    true
+}
+
+/// How a program run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Exit,
+    Fault(MachineError),
+    StepLimit,
+}
+
+/// Result of a run used by [verify].
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub output: Vec<u8>,
+    pub regs: Vec<u32>,
+    pub outcome: Outcome,
+    pub steps: usize,
+}
+
+/// Run `image` with the given initial registers for at most `max_steps` instructions.
+pub fn run(image: &[u8], regs: &[(usize, u32)], max_steps: usize) -> Run {
+    let mut machine = Machine::new(image);
+    for &(reg, value) in regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    let mut output = Vec::new();
+    let (halt, steps) = machine.run_until(max_steps, &mut output);
+    let outcome = match halt {
+        Halt::Exit => Outcome::Exit,
+        Halt::StepLimit => Outcome::StepLimit,
+        Halt::Fault(e, _) => Outcome::Fault(e),
+    };
+    Run { output, regs: machine.regs().to_vec(), outcome, steps }
+}
+
+/// Difference found by [verify] between the original and optimized programs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Output { original: Vec<u8>, optimized: Vec<u8> },
+    Outcome { original: Outcome, optimized: Outcome },
+    Register { reg: usize, original: u32, optimized: u32 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Output { original, optimized } => write!(
+                f,
+                "outputs differ: {:?} instead of {:?}",
+                String::from_utf8_lossy(optimized),
+                String::from_utf8_lossy(original)
+            ),
+            Mismatch::Outcome { original, optimized } => {
+                write!(f, "program ends with {:?} instead of {:?}", optimized, original)
+            }
+            Mismatch::Register { reg, original, optimized } => {
+                write!(f, "r{} is {:#x} instead of {:#x}", reg, optimized, original)
+            }
+        }
+    }
+}
+
+/// Run the original and optimized programs, and check that they print the
+/// same output, end the same way and leave the same registers, code addresses
+/// being compared after relocation. On success, return both runs.
+pub fn verify(
+    original: &[u8],
+    optimized: &Optimized,
+    regs: &[(usize, u32)],
+    max_steps: usize,
+) -> Result<(Run, Run), Mismatch> {
+    let before = run(original, regs, max_steps);
+    let after = run(&optimized.image, regs, max_steps);
+    if before.output != after.output {
+        return Err(Mismatch::Output { original: before.output, optimized: after.output });
+    }
+    if before.outcome != after.outcome {
+        return Err(Mismatch::Outcome { original: before.outcome, optimized: after.outcome });
+    }
+    if before.outcome == Outcome::Exit {
+        for reg in 0..NREGS {
+            let (a, b) = (before.regs[reg], after.regs[reg]);
+            if a != b && optimized.relocation.get(&a) != Some(&b) {
+                return Err(Mismatch::Register { reg, original: a, optimized: b });
+            }
+        }
+    }
+    Ok((before, after))
+}
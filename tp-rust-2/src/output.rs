@@ -0,0 +1,30 @@
+//! This module defines where the output instructions print their result.
+//! The machine only needs a byte sink, which lets it run without the standard
+//! library, for example on the STM32L475 board with a serial port as output.
+
+use core::fmt;
+
+use crate::MachineError;
+
+/// A destination for the bytes printed by the `out` and `out_number` instructions.
+pub trait Output {
+    /// Write all the given bytes, or fail with [MachineError::OutputFailure].
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), MachineError>;
+}
+
+/// Any `std::io::Write` implementation can be used as an output.
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> Output for W {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), MachineError> {
+        self.write_all(bytes).map_err(|_| MachineError::OutputFailure)
+    }
+}
+
+/// Adapter used to format values directly into an [Output] without allocating.
+pub(crate) struct Formatter<'a, T: Output + ?Sized>(pub(crate) &'a mut T);
+
+impl<T: Output + ?Sized> fmt::Write for Formatter<'_, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
@@ -0,0 +1,375 @@
+//! Abstract interpretation of program images over register value ranges.
+//!
+//! Every register is approximated by an interval of unsigned values, which is
+//! propagated along the control-flow graph recovered by [crate::analysis]
+//! until a fixed point is reached. Memory content is not tracked: a `load`
+//! may give any value. The result tells, for every `load` and `store`,
+//! whether the accessed word may lie outside of the memory, and for every
+//! `out`, whether the printed byte may not be ASCII.
+//!
+//! The analysis is sound as long as the control-flow graph is complete, that
+//! is when no jump goes to an unknown address.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::analysis::{writes, Cfg, Terminator};
+use crate::{Instruction, IP, MEMORY_SIZE, NREGS};
+
+/// Set of the values between `min` and `max`, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Interval {
+    /// Any value.
+    pub const TOP: Interval = Interval { min: 0, max: u32::MAX };
+
+    pub fn new(min: u32, max: u32) -> Self {
+        assert!(min <= max);
+        Interval { min, max }
+    }
+
+    pub fn constant(value: u32) -> Self {
+        Interval { min: value, max: value }
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+
+    /// Smallest interval containing both intervals.
+    fn join(self, other: Interval) -> Interval {
+        Interval { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    /// Intersection of both intervals, if not empty.
+    fn meet(self, other: Interval) -> Option<Interval> {
+        let (min, max) = (self.min.max(other.min), self.max.min(other.max));
+        (min <= max).then_some(Interval { min, max })
+    }
+
+    /// Extend the bounds which grew since `self` to the next threshold, so
+    /// that loops reach a fixed point quickly.
+    fn widen(self, next: Interval) -> Interval {
+        const THRESHOLDS: [u32; 8] =
+            [0, 127, 255, MEMORY_SIZE as u32 - 4, MEMORY_SIZE as u32 - 1, 0x7fff, 0xffff, u32::MAX];
+        let min = if next.min < self.min {
+            THRESHOLDS.iter().rev().copied().find(|&t| t <= next.min).unwrap()
+        } else {
+            self.min
+        };
+        let max = if next.max > self.max {
+            THRESHOLDS.iter().copied().find(|&t| t >= next.max).unwrap()
+        } else {
+            self.max
+        };
+        Interval { min, max }
+    }
+
+    /// Values of `a - b` with the wrapping arithmetic of the machine.
+    fn sub(a: Interval, b: Interval) -> Interval {
+        let min = a.min as i64 - b.max as i64;
+        let max = a.max as i64 - b.min as i64;
+        let modulus = 1i64 << 32;
+        if min >= 0 {
+            Interval::new(min as u32, max as u32)
+        } else if max < 0 {
+            Interval::new((min + modulus) as u32, (max + modulus) as u32)
+        } else {
+            Interval::TOP
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "[{}, {}]", self.min, self.max)
+        }
+    }
+}
+
+/// Outcome of a check, for all the executions of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Safe,
+    MayFail,
+    Fails,
+}
+
+/// Check of a single instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Finding {
+    pub instr: Instruction,
+    /// Possible values of the address for `load` and `store`, or of the
+    /// printed register for `out`.
+    pub values: Interval,
+    pub verdict: Verdict,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.instr {
+            Instruction::Out { .. } => match self.verdict {
+                Verdict::Safe => "prints ASCII",
+                Verdict::MayFail => "may print non-ASCII",
+                Verdict::Fails => "prints non-ASCII",
+            },
+            _ => match self.verdict {
+                Verdict::Safe => "accesses memory",
+                Verdict::MayFail => "may access outside of memory",
+                Verdict::Fails => "accesses outside of memory",
+            },
+        };
+        let operand = if let Instruction::Out { .. } = self.instr { "value" } else { "address" };
+        write!(f, "{}: {} {} {}", self.instr, what, operand, self.values)
+    }
+}
+
+/// Registers, or `None` when the point cannot be reached.
+type State = Option<[Interval; NREGS]>;
+
+/// Checks of every reachable `load`, `store` and `out` instruction.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Findings indexed by instruction address.
+    pub findings: BTreeMap<usize, Finding>,
+    /// Whether the control-flow graph is complete. If not, the findings
+    /// ignore the instructions reached through unknown jumps.
+    pub complete: bool,
+}
+
+impl Report {
+    /// Analyze `image`, whose registers initially hold values within `regs`.
+    pub fn analyze(image: &[u8], regs: &[Interval; NREGS]) -> Self {
+        let cfg = Cfg::new(image);
+        let complete = cfg.blocks.values().all(|b| b.terminator != Terminator::Unknown);
+        let entries = fixed_point(&cfg, regs);
+        // Join the values seen in every context.
+        let mut values: BTreeMap<usize, (Instruction, Interval)> = BTreeMap::new();
+        for (&(start, _), &state) in &entries {
+            let mut state = state;
+            for &(addr, instr) in &cfg.blocks[&start].instructions {
+                let Some(regs) = state.as_mut() else { break };
+                regs[IP] = Interval::constant((addr + instr.size()) as u32);
+                if let Some(reg) = checked_register(&instr) {
+                    let joined = values.get(&addr).map_or(regs[reg], |&(_, v)| v.join(regs[reg]));
+                    values.insert(addr, (instr, joined));
+                }
+                state = execute(addr, &instr, state);
+            }
+        }
+        let findings = values
+            .into_iter()
+            .map(|(addr, (instr, values))| (addr, Finding { instr, values, verdict: verdict(&instr, values) }))
+            .collect();
+        Report { findings, complete }
+    }
+
+    /// Addresses of the instructions which may fail or print non-ASCII bytes.
+    pub fn unsafe_addresses(&self) -> Vec<usize> {
+        self.findings.iter().filter(|(_, f)| f.verdict != Verdict::Safe).map(|(&addr, _)| addr).collect()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (addr, finding) in &self.findings {
+            writeln!(f, "  {:04}   {}", addr, finding)?;
+        }
+        if !self.complete {
+            writeln!(f, "warning: some jumps go to unknown addresses, the report is incomplete")?;
+        }
+        Ok(())
+    }
+}
+
+/// Register checked by a `load`, `store` or `out` instruction.
+fn checked_register(instr: &Instruction) -> Option<usize> {
+    match *instr {
+        Instruction::Load { addr, .. } | Instruction::Store { addr, .. } => Some(addr),
+        Instruction::Out { reg } => Some(reg),
+        _ => None,
+    }
+}
+
+/// Verdict of a `load`, `store` or `out` instruction whose checked register holds `values`.
+fn verdict(instr: &Instruction, values: Interval) -> Verdict {
+    if let Instruction::Out { .. } = instr {
+        let bytes: Vec<u8> = if values.max - values.min >= 255 {
+            (0..=255).collect()
+        } else {
+            (values.min..=values.max).map(|v| v as u8).collect()
+        };
+        if bytes.iter().all(u8::is_ascii) {
+            Verdict::Safe
+        } else if bytes.iter().any(u8::is_ascii) {
+            Verdict::MayFail
+        } else {
+            Verdict::Fails
+        }
+    } else {
+        let last = MEMORY_SIZE as u32 - 4;
+        if values.max <= last {
+            Verdict::Safe
+        } else if values.min > last {
+            Verdict::Fails
+        } else {
+            Verdict::MayFail
+        }
+    }
+}
+
+/// Effect of an instruction on the registers. Memory accesses which do not
+/// fault restrict the possible values of their address.
+fn execute(addr: usize, instr: &Instruction, state: State) -> State {
+    let mut regs = state?;
+    regs[IP] = Interval::constant((addr + instr.size()) as u32);
+    let valid = Interval::new(0, MEMORY_SIZE as u32 - 4);
+    match *instr {
+        Instruction::MoveIf { dest, src, cond } if dest != IP => {
+            regs[dest] = if regs[cond] == Interval::constant(0) {
+                regs[dest]
+            } else if regs[cond].contains(0) {
+                regs[dest].join(regs[src])
+            } else {
+                regs[src]
+            }
+        }
+        Instruction::Store { addr, .. } => regs[addr] = regs[addr].meet(valid)?,
+        Instruction::Load { dest, addr } => {
+            regs[addr] = regs[addr].meet(valid)?;
+            regs[dest] = Interval::TOP;
+        }
+        Instruction::LoadImm { dest, value } => regs[dest] = Interval::constant(value as i32 as u32),
+        Instruction::Sub { dest, op1, op2 } => regs[dest] = Interval::sub(regs[op1], regs[op2]),
+        _ => (),
+    }
+    if let Some(dest) = writes(instr) {
+        if dest == IP {
+            regs[IP] = Interval::TOP;
+        }
+    }
+    Some(regs)
+}
+
+/// Return point of the innermost call, `None` outside of any call.
+type Context = Option<usize>;
+
+/// Registers at the entry of blocks, with the blocks to analyze again.
+#[derive(Default)]
+struct Entries {
+    states: BTreeMap<(usize, Context), State>,
+    visits: BTreeMap<(usize, Context), usize>,
+    work: Vec<(usize, Context)>,
+}
+
+impl Entries {
+    /// Join `regs` into the entry of a block, widening after a few visits.
+    fn propagate(&mut self, key: (usize, Context), regs: [Interval; NREGS]) {
+        let previous = self.states.get(&key).copied().flatten();
+        let count = self.visits.entry(key).or_default();
+        *count += 1;
+        let merged = match previous {
+            None => regs,
+            Some(previous) => {
+                let mut merged = previous;
+                for r in 0..NREGS {
+                    let joined = previous[r].join(regs[r]);
+                    merged[r] = if *count > 3 { previous[r].widen(joined) } else { joined };
+                }
+                merged
+            }
+        };
+        if previous != Some(merged) {
+            self.states.insert(key, Some(merged));
+            self.work.push(key);
+        }
+    }
+}
+
+/// Registers at the entry of every block in every context, once stable.
+/// Blocks are analyzed separately for each call site of the function they
+/// belong to, so that a return only gives back the registers to its caller.
+fn fixed_point(cfg: &Cfg, initial: &[Interval; NREGS]) -> BTreeMap<(usize, Context), State> {
+    let mut entries = Entries::default();
+    // Contexts of the blocks calling with each return point.
+    let mut callers: BTreeMap<usize, BTreeSet<Context>> = BTreeMap::new();
+    // Registers returned to each return point.
+    let mut returned: BTreeMap<usize, [Interval; NREGS]> = BTreeMap::new();
+    if cfg.blocks.contains_key(&0) {
+        entries.propagate((0, None), *initial);
+    }
+    while let Some((start, context)) = entries.work.pop() {
+        let block = &cfg.blocks[&start];
+        let mut state = entries.states[&(start, context)];
+        for (addr, instr) in &block.instructions {
+            state = execute(*addr, instr, state);
+        }
+        let Some(exit) = state else { continue };
+        match (block.terminator, context) {
+            (Terminator::Call { target, return_to }, _) => {
+                if cfg.blocks.contains_key(&target) {
+                    entries.propagate((target, Some(return_to)), exit);
+                }
+                if callers.entry(return_to).or_default().insert(context) {
+                    if let Some(&regs) = returned.get(&return_to) {
+                        entries.propagate((return_to, context), regs);
+                    }
+                }
+            }
+            (Terminator::Return, Some(site)) => {
+                let mut regs = exit;
+                if let Some(previous) = returned.get(&site) {
+                    for r in 0..NREGS {
+                        regs[r] = regs[r].join(previous[r]);
+                    }
+                }
+                returned.insert(site, regs);
+                if cfg.blocks.contains_key(&site) {
+                    for &caller in callers.get(&site).into_iter().flatten() {
+                        entries.propagate((site, caller), regs);
+                    }
+                }
+            }
+            _ => {
+                for (next, regs) in edges(cfg, start, exit) {
+                    if let Some(regs) = regs {
+                        entries.propagate((next, context), regs);
+                    }
+                }
+            }
+        }
+    }
+    entries.states
+}
+
+/// Successors of a block, with the registers on the way there. The
+/// condition of a branch is known to be zero or not depending on the edge.
+fn edges(cfg: &Cfg, start: usize, exit: [Interval; NREGS]) -> Vec<(usize, State)> {
+    let block = &cfg.blocks[&start];
+    if let (Terminator::Branch { taken, not_taken }, Some(&(_, Instruction::MoveIf { cond, .. }))) =
+        (block.terminator, block.instructions.last())
+    {
+        let restrict = |values: Option<Interval>| {
+            let mut regs = exit;
+            regs[cond] = values?;
+            Some(regs)
+        };
+        let nonzero = if exit[cond].min == 0 {
+            (exit[cond].max > 0).then(|| Interval::new(1, exit[cond].max))
+        } else {
+            Some(exit[cond])
+        };
+        return vec![(taken, restrict(nonzero)), (not_taken, restrict(exit[cond].meet(Interval::constant(0))))]
+            .into_iter()
+            .filter(|(next, _)| cfg.blocks.contains_key(next))
+            .collect();
+    }
+    cfg.successors(start).into_iter().map(|next| (next, Some(exit))).collect()
+}
@@ -0,0 +1,228 @@
+//! Ahead-of-time translation of program images into Rust source code.
+//!
+//! [translate] produces a standalone program, without dependencies, which
+//! behaves like [Machine::run_on](crate::Machine::run_on) on the image and
+//! prints on its standard output. Every basic block recovered by
+//! [Cfg](crate::analysis::Cfg) becomes an arm of a `match` on r0. Jumps to
+//! other addresses are executed by a small interpreter embedded in the
+//! program, which also takes over for good once the program overwrites its
+//! own code.
+//!
+//! The produced program accepts initial register values as arguments, such
+//! as `r10=5`, and exits with code 1 when the program faults.
+
+use std::fmt::Write;
+
+use crate::analysis::{self, Cfg};
+use crate::{Instruction, IP, MEMORY_SIZE};
+
+/// Part of the produced program which does not depend on the image.
+const RUNTIME: &str = r#"
+/// A fault, with the address of the faulting instruction.
+struct Fault(u32, String);
+
+struct State {
+    mem: [u8; MEMORY_SIZE],
+    r: [u32; 16],
+    /// Set once the code has been overwritten: the translated blocks are not used anymore.
+    modified: bool,
+}
+
+impl State {
+    fn load(&self, ip: u32, addr: u32) -> Result<u32, Fault> {
+        let a = addr as usize;
+        if a >= MEMORY_SIZE - 3 {
+            return Err(Fault(ip, format!("access outside of memory at address {:#x}", addr)));
+        }
+        Ok(u32::from_le_bytes([self.mem[a], self.mem[a + 1], self.mem[a + 2], self.mem[a + 3]]))
+    }
+
+    fn store(&mut self, ip: u32, addr: u32, value: u32) -> Result<(), Fault> {
+        let a = addr as usize;
+        if a >= MEMORY_SIZE - 3 {
+            return Err(Fault(ip, format!("access outside of memory at address {:#x}", addr)));
+        }
+        self.mem[a..a + 4].copy_from_slice(&value.to_le_bytes());
+        if addr < CODE_END && CODE.iter().any(|&(start, end)| addr < end && addr + 4 > start) {
+            self.modified = true;
+        }
+        Ok(())
+    }
+
+    /// Execute the instruction at r0, returning `true` on `exit`.
+    fn interpret<W: Write>(&mut self, out: &mut W) -> Result<bool, Fault> {
+        let ip = self.r[0];
+        let i = ip as usize;
+        if i >= MEMORY_SIZE {
+            return Err(Fault(ip, format!("access outside of memory at address {:#x}", ip)));
+        }
+        let opcode = self.mem[i];
+        let size = match opcode {
+            1 | 4 | 5 => 4,
+            2 | 3 => 3,
+            6 | 8 => 2,
+            7 => 1,
+            _ => return Err(Fault(ip, format!("invalid instruction (opcode {})", opcode))),
+        };
+        if i + size > MEMORY_SIZE {
+            return Err(Fault(ip, String::from("instruction truncated by the end of memory")));
+        }
+        let b = [self.mem[i], self.mem[(i + 1) % MEMORY_SIZE], self.mem[(i + 2) % MEMORY_SIZE], self.mem[(i + 3) % MEMORY_SIZE]];
+        let reg = |n: u8| if (n as usize) < 16 { Ok(n as usize) } else { Err(Fault(ip, String::from("bad register name"))) };
+        self.r[0] = ip + size as u32;
+        match opcode {
+            1 => {
+                let (dest, src, cond) = (reg(b[1])?, reg(b[2])?, reg(b[3])?);
+                if self.r[cond] != 0 {
+                    self.r[dest] = self.r[src];
+                }
+            }
+            2 => {
+                let (addr, src) = (reg(b[1])?, reg(b[2])?);
+                self.store(ip, self.r[addr], self.r[src])?;
+            }
+            3 => {
+                let (dest, addr) = (reg(b[1])?, reg(b[2])?);
+                self.r[dest] = self.load(ip, self.r[addr])?;
+            }
+            4 => self.r[reg(b[1])?] = i16::from_le_bytes([b[2], b[3]]) as u32,
+            5 => {
+                let (dest, op1, op2) = (reg(b[1])?, reg(b[2])?, reg(b[3])?);
+                self.r[dest] = self.r[op1].wrapping_sub(self.r[op2]);
+            }
+            6 => out_char(out, ip, self.r[reg(b[1])?])?,
+            7 => return Ok(true),
+            _ => out_number(out, ip, self.r[reg(b[1])?])?,
+        }
+        Ok(false)
+    }
+}
+
+fn out_char<W: Write>(out: &mut W, ip: u32, value: u32) -> Result<(), Fault> {
+    write!(out, "{}", value as u8 as char).map_err(|_| Fault(ip, String::from("unable to write output")))
+}
+
+fn out_number<W: Write>(out: &mut W, ip: u32, value: u32) -> Result<(), Fault> {
+    write!(out, "{}", value as i32).map_err(|_| Fault(ip, String::from("unable to write output")))
+}
+
+fn main() -> ExitCode {
+    let mut state = State { mem: [0; MEMORY_SIZE], r: [0; 16], modified: false };
+    state.mem[..IMAGE.len()].copy_from_slice(&IMAGE);
+    for arg in std::env::args().skip(1) {
+        let parsed = arg.strip_prefix('r').and_then(|arg| arg.split_once('=')).and_then(|(reg, value)| {
+            let reg: usize = reg.parse().ok().filter(|&reg| reg < 16)?;
+            let value = value.parse::<i32>().map(|value| value as u32).or_else(|_| value.parse::<u32>()).ok()?;
+            Some((reg, value))
+        });
+        match parsed {
+            Some((reg, value)) => state.r[reg] = value,
+            None => {
+                eprintln!("error: expected rN=VALUE, found `{}`", arg);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let result = run(&mut state, &mut out);
+    let _ = out.flush();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Fault(ip, e)) => {
+            eprintln!("error: program fault at address {}: {}", ip, e);
+            ExitCode::from(1)
+        }
+    }
+}
+"#;
+
+/// Translate a program image into the source code of an equivalent Rust program.
+///
+/// # Panics
+/// This function panics when `image` is larger than the machine memory.
+pub fn translate(image: &[u8]) -> String {
+    assert!(image.len() <= MEMORY_SIZE, "image is larger than memory");
+    let cfg = Cfg::new(image);
+    let mut code: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in cfg.blocks.values().map(|block| (block.start, block.end())) {
+        match code.last_mut() {
+            Some(last) if last.1 >= start => last.1 = last.1.max(end),
+            _ => code.push((start, end)),
+        }
+    }
+
+    let mut src = String::new();
+    src.push_str("//! Program translated from a machine image by `tp-rust-2 translate`.\n\n");
+    src.push_str("use std::io::{self, Write};\nuse std::process::ExitCode;\n\n");
+    writeln!(src, "const MEMORY_SIZE: usize = {};", MEMORY_SIZE).unwrap();
+    src.push_str("/// Ranges of translated instructions, and the end of the last one.\n");
+    writeln!(src, "const CODE: [(u32, u32); {}] = {:?};", code.len(), code).unwrap();
+    writeln!(src, "const CODE_END: u32 = {};\n", code.last().map_or(0, |c| c.1)).unwrap();
+    writeln!(src, "static IMAGE: [u8; {}] = [", image.len()).unwrap();
+    for line in image.chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|b| format!("{:#04x}", b)).collect();
+        writeln!(src, "    {},", bytes.join(", ")).unwrap();
+    }
+    src.push_str("];\n");
+    src.push_str(RUNTIME);
+
+    src.push_str("\nfn run<W: Write>(s: &mut State, out: &mut W) -> Result<(), Fault> {\n");
+    src.push_str("    loop {\n");
+    src.push_str("        if s.modified {\n");
+    src.push_str("            if s.interpret(out)? {\n                return Ok(());\n            }\n");
+    src.push_str("            continue;\n        }\n");
+    src.push_str("        match s.r[0] {\n");
+    for block in cfg.blocks.values() {
+        if block.instructions.is_empty() {
+            continue;
+        }
+        writeln!(src, "            {} => {{", block.start).unwrap();
+        let last = block.instructions.len() - 1;
+        for (i, &(addr, instr)) in block.instructions.iter().enumerate() {
+            translate_instruction(&mut src, addr, &instr, i == last);
+        }
+        src.push_str("            }\n");
+    }
+    src.push_str("            _ => {\n");
+    src.push_str("                if s.interpret(out)? {\n                    return Ok(());\n                }\n");
+    src.push_str("            }\n        }\n    }\n}\n");
+    src
+}
+
+/// Emit the statements executing `instr`, located at `addr`. IP is only
+/// updated when the instruction reads or writes it, and at the end of the block.
+fn translate_instruction(src: &mut String, addr: usize, instr: &Instruction, last: bool) {
+    let next = addr + instr.size();
+    let indent = "                ";
+    writeln!(src, "{}// {:04}   {}", indent, addr, instr).unwrap();
+    let jumps = analysis::writes(instr) == Some(IP);
+    // Only a conditional move may keep the previous value of its destination
+    let overwrites = jumps && !matches!(instr, Instruction::MoveIf { .. });
+    if analysis::reads(instr).contains(&IP) || ((last || jumps) && !overwrites) {
+        writeln!(src, "{}s.r[0] = {};", indent, next).unwrap();
+    }
+    let statement = match *instr {
+        Instruction::MoveIf { dest, src, cond } => {
+            format!("if s.r[{}] != 0 {{\n{i}    s.r[{}] = s.r[{}];\n{i}}}", cond, dest, src, i = indent)
+        }
+        Instruction::Store { addr: a, src: s } => format!(
+            "s.store({}, s.r[{}], s.r[{}])?;\n{i}if s.modified {{\n{i}    s.r[0] = {};\n{i}    continue;\n{i}}}",
+            addr,
+            a,
+            s,
+            next,
+            i = indent
+        ),
+        Instruction::Load { dest, addr: a } => format!("s.r[{}] = s.load({}, s.r[{}])?;", dest, addr, a),
+        Instruction::LoadImm { dest, value } => format!("s.r[{}] = {:#x};", dest, value as u32),
+        Instruction::Sub { dest, op1, op2 } => format!("s.r[{}] = s.r[{}].wrapping_sub(s.r[{}]);", dest, op1, op2),
+        Instruction::Out { reg } => format!("out_char(out, {}, s.r[{}])?;", addr, reg),
+        Instruction::Exit => String::from("return Ok(());"),
+        Instruction::OutNumber { reg } => format!("out_number(out, {}, s.r[{}])?;", addr, reg),
+    };
+    writeln!(src, "{}{}", indent, statement).unwrap();
+    if jumps && !last {
+        writeln!(src, "{}continue;", indent).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! Full-screen terminal debugger.
+//!
+//! The screen shows the disassembly around IP, the registers (those changed
+//! by the last step or run being highlighted), a scrollable dump of the
+//! memory and the output of the program. It only relies on the public API
+//! of [Machine], so that [App] can also be driven and rendered in tests.
+//!
+//! Keys:
+//!   - `space` runs or pauses the program, `s` executes one instruction;
+//!   - `+` and `-` double or halve the number of instructions run per frame;
+//!   - arrows, `PageUp`, `PageDown` and `Home` scroll the memory dump;
+//!   - `q` or `Esc` quits.
+
+use std::io;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use crate::{disassemble, inspect, listing_line, Instruction, Machine, MachineError, IP, MEMORY_SIZE, NREGS};
+
+/// Bytes per line of the memory dump.
+const LINE: usize = 16;
+/// Maximum number of instructions run per frame.
+const MAX_SPEED: usize = 1 << 20;
+/// Time between two frames while the program runs.
+const FRAME: Duration = Duration::from_millis(30);
+
+/// Whether the program can still execute instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Exited,
+    /// The instruction at the given address faulted.
+    Fault(MachineError, u32),
+}
+
+/// The debugger state, independent from the terminal.
+pub struct App {
+    machine: Machine,
+    /// Registers before the last step or run, to highlight those which changed.
+    previous: [u32; NREGS],
+    output: Vec<u8>,
+    state: State,
+    running: bool,
+    speed: usize,
+    /// First line of the memory dump.
+    scroll: usize,
+}
+
+impl App {
+    pub fn new(machine: Machine) -> Self {
+        let mut previous = [0; NREGS];
+        previous.copy_from_slice(machine.regs());
+        App { machine, previous, output: Vec::new(), state: State::Ready, running: false, speed: 1, scroll: 0 }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// What the program printed.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// Number of instructions executed per frame while running.
+    pub fn speed(&self) -> usize {
+        self.speed
+    }
+
+    /// Registers which differ from those before the last step or run.
+    pub fn changed(&self) -> Vec<usize> {
+        (0..NREGS).filter(|&r| self.machine.regs()[r] != self.previous[r]).collect()
+    }
+
+    /// Execute up to `count` instructions, stopping at the end of the program.
+    fn execute(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.state != State::Ready {
+                break;
+            }
+            let ip = self.machine.regs()[IP];
+            match self.machine.step_on(&mut self.output) {
+                Ok(false) => (),
+                Ok(true) => self.state = State::Exited,
+                Err(e) => self.state = State::Fault(e, ip),
+            }
+        }
+        if self.state != State::Ready {
+            self.running = false;
+        }
+    }
+
+    /// Execute a single instruction.
+    pub fn step(&mut self) {
+        self.previous.copy_from_slice(self.machine.regs());
+        self.execute(1);
+    }
+
+    /// Advance by one frame: execute `speed` instructions if the program runs.
+    pub fn tick(&mut self) {
+        if self.running {
+            self.previous.copy_from_slice(self.machine.regs());
+            self.execute(self.speed);
+        }
+    }
+
+    /// React to a key. Returns `false` when the debugger must quit.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let last_line = MEMORY_SIZE / LINE - 1;
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => self.running = !self.running && self.state == State::Ready,
+            KeyCode::Char('s') => {
+                self.running = false;
+                self.step();
+            }
+            KeyCode::Char('+') => self.speed = (self.speed * 2).min(MAX_SPEED),
+            KeyCode::Char('-') => self.speed = (self.speed / 2).max(1),
+            KeyCode::Down => self.scroll = (self.scroll + 1).min(last_line),
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageDown => self.scroll = (self.scroll + LINE).min(last_line),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(LINE),
+            KeyCode::Home => self.scroll = 0,
+            _ => (),
+        }
+        true
+    }
+
+    /// Draw the whole screen.
+    pub fn draw(&self, frame: &mut Frame) {
+        let [top, memory, output, help] = Layout::vertical([
+            Constraint::Length(NREGS as u16 + 2),
+            Constraint::Min(4),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [code, regs] = Layout::horizontal([Constraint::Min(30), Constraint::Length(30)]).areas(top);
+        self.draw_code(frame, code);
+        self.draw_regs(frame, regs);
+        self.draw_memory(frame, memory);
+        self.draw_output(frame, output);
+        frame.render_widget(Paragraph::new(self.status_line()), help);
+    }
+
+    /// Instructions around IP: the linear disassembly before it, then the
+    /// instructions decoded from IP, which may not be aligned with the former.
+    fn draw_code(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let memory = self.machine.memory();
+        let ip = self.machine.regs()[IP] as usize;
+        let before: Vec<(usize, Option<Instruction>)> =
+            disassemble(memory).into_iter().take_while(|&(addr, _)| addr < ip).collect();
+        let mut lines: Vec<Line> = before[before.len().saturating_sub(height / 3)..]
+            .iter()
+            .map(|&(addr, instr)| Line::from(listing_line(memory, addr, instr)))
+            .collect();
+        let mut addr = ip;
+        while lines.len() < height && addr < MEMORY_SIZE {
+            let instr = Instruction::decode(&memory[addr..]).ok();
+            let text = listing_line(memory, addr, instr);
+            lines.push(if addr == ip {
+                Line::styled(format!("> {}", &text[2..]), Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::from(text)
+            });
+            addr += instr.map_or(1, |instr| instr.size());
+        }
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Code ")), area);
+    }
+
+    fn draw_regs(&self, frame: &mut Frame, area: Rect) {
+        let changed = self.changed();
+        let lines: Vec<Line> = self
+            .machine
+            .regs()
+            .iter()
+            .enumerate()
+            .map(|(r, value)| {
+                let text = format!("r{:<2} {:#010x} {:>11}", r, value, *value as i32);
+                if changed.contains(&r) {
+                    Line::styled(text, Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                } else {
+                    Line::from(text)
+                }
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Registers ")), area);
+    }
+
+    fn draw_memory(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let start = self.scroll * LINE;
+        let end = (start + height * LINE).min(MEMORY_SIZE);
+        let dump = inspect::hexdump(self.machine.memory(), start..end);
+        let lines: Vec<Line> = dump.lines().map(|line| Line::from(line.to_owned())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Memory ")), area);
+    }
+
+    fn draw_output(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let text = String::from_utf8_lossy(&self.output);
+        let lines: Vec<&str> = text.lines().collect();
+        let lines: Vec<Line> = lines[lines.len().saturating_sub(height)..]
+            .iter()
+            .map(|&line| Line::from(line.to_owned()))
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Output ")), area);
+    }
+
+    fn status_line(&self) -> Line<'static> {
+        let state = match self.state {
+            State::Ready if self.running => String::from("running"),
+            State::Ready => String::from("paused"),
+            State::Exited => String::from("exited"),
+            State::Fault(e, ip) => format!("fault at address {}: {}", ip, e),
+        };
+        Line::from(vec![
+            Span::styled(format!(" {} ", state), Style::new().add_modifier(Modifier::REVERSED)),
+            Span::raw(format!(
+                "  speed {}/frame   space run/pause   s step   +/- speed   arrows scroll   q quit",
+                self.speed
+            )),
+        ])
+    }
+}
+
+/// Debug `machine` in the terminal until the user quits.
+pub fn run(machine: Machine) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut app = App::new(machine);
+    let result = (|| loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        let timeout = if app.running() { FRAME } else { Duration::from_secs(3600) };
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+        app.tick();
+    })();
+    ratatui::restore();
+    result
+}
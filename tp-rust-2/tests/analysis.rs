@@ -0,0 +1,119 @@
+use interpreter::analysis::{Analysis, Cfg, Diagnostic, Terminator};
+use interpreter::{Instruction, Item};
+
+mod common;
+use common::image;
+
+#[test]
+fn calls_branches_and_returns() {
+    let cfg = Cfg::new(include_bytes!("fact.bin"));
+    // Entry calls `fact`, which returns to the `exit`
+    assert_eq!(Terminator::Call { target: 87, return_to: 23 }, cfg.blocks[&0].terminator);
+    assert_eq!(Terminator::Exit, cfg.blocks[&23].terminator);
+    // `mult_loop`
+    assert_eq!(Terminator::Branch { taken: 52, not_taken: 48 }, cfg.blocks[&32].terminator);
+    assert_eq!(Terminator::Jump(32), cfg.blocks[&52].terminator);
+    assert_eq!(Terminator::Return, cfg.blocks[&68].terminator);
+    // `mult` only returns to `fact`, which returns to the entry
+    assert_eq!(vec![134], cfg.successors(68));
+    assert_eq!(vec![23], cfg.successors(146));
+    // `mult` is only reached through the call from `fact`
+    assert_eq!(Terminator::FallThrough(32), cfg.blocks[&24].terminator);
+    assert_eq!(Some(&23), cfg.code_addresses.get(&12));
+    assert_eq!(Some(&87), cfg.code_addresses.get(&19));
+}
+
+#[test]
+fn examples_have_a_complete_graph() {
+    for name in ["99bottles", "count", "factorial", "fibonacci", "hello_world"] {
+        let image = std::fs::read(format!("examples/{}.bin", name)).unwrap();
+        let analysis = Analysis::analyze(&image);
+        for diagnostic in &analysis.diagnostics {
+            assert!(
+                matches!(diagnostic, Diagnostic::Unreachable { .. } | Diagnostic::UninitializedRead { .. }),
+                "{}: {}",
+                name,
+                diagnostic
+            );
+        }
+    }
+}
+
+#[test]
+fn unreachable_code() {
+    // `loadimm r0 <- #ite_end_1` just after an unconditional jump
+    let analysis = Analysis::analyze(include_bytes!("../examples/99bottles.bin"));
+    assert_eq!(vec![Diagnostic::Unreachable { start: 240, end: 244 }], analysis.diagnostics);
+}
+
+#[test]
+fn fall_through_and_jump_into_data() {
+    let analysis = Analysis::analyze(&image(&[
+        Item::Instr(Instruction::LoadImm { dest: 1, value: 1 }),
+        Item::LoadLabel { dest: 8, label: String::from("data") },
+        Item::Instr(Instruction::MoveIf { dest: 0, src: 8, cond: 1 }),
+        Item::Label(String::from("data")),
+        Item::Data(b"Hi".to_vec()),
+    ]));
+    assert_eq!(
+        vec![
+            Diagnostic::JumpIntoData { addr: 8, target: 12 },
+            Diagnostic::FallThroughIntoData { addr: 8, next: 12 },
+        ],
+        analysis.diagnostics
+    );
+}
+
+#[test]
+fn unknown_jump() {
+    let analysis = Analysis::analyze(&image(&[
+        Item::Instr(Instruction::LoadImm { dest: 3, value: 8 }),
+        Item::Instr(Instruction::Sub { dest: 0, op1: 3, op2: 1 }),
+        Item::Instr(Instruction::Exit),
+    ]));
+    assert_eq!(Terminator::Unknown, analysis.cfg.blocks[&0].terminator);
+    assert!(analysis.diagnostics.contains(&Diagnostic::UnknownJump { addr: 4 }));
+    // The `exit` cannot be reached as far as the analysis knows
+    assert!(analysis.diagnostics.contains(&Diagnostic::Unreachable { start: 8, end: 9 }));
+}
+
+#[test]
+fn uninitialized_reads() {
+    // `mult` expects its arguments in r11 and r12, and uses r1 as zero
+    let image = image(&[
+        Item::Instr(Instruction::Sub { dest: 13, op1: 1, op2: 11 }),
+        Item::Instr(Instruction::MoveIf { dest: 14, src: 12, cond: 0 }),
+        Item::Instr(Instruction::Sub { dest: 11, op1: 11, op2: 13 }),
+        Item::Instr(Instruction::Exit),
+    ]);
+    assert_eq!(
+        vec![
+            Diagnostic::UninitializedRead { addr: 0, reg: 1 },
+            Diagnostic::UninitializedRead { addr: 0, reg: 11 },
+            Diagnostic::UninitializedRead { addr: 4, reg: 12 },
+            Diagnostic::UninitializedRead { addr: 8, reg: 11 },
+        ],
+        Analysis::analyze(&image).diagnostics
+    );
+    // Values saved on the stack before being used
+    let analysis = Analysis::analyze(include_bytes!("../examples/hello_world.bin"));
+    assert_eq!(
+        vec![
+            Diagnostic::UninitializedRead { addr: 12, reg: 10 },
+            Diagnostic::UninitializedRead { addr: 23, reg: 11 },
+        ],
+        analysis.diagnostics
+    );
+}
+
+#[test]
+fn dot_export() {
+    let dot = Cfg::new(include_bytes!("fact.bin")).to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("    b23 [label=\"0023   exit\\l\"];\n"));
+    assert!(dot.contains("    b0 -> b87 [label=\"call\"];\n"));
+    assert!(dot.contains("    b0 -> b23 [label=\"return\", style=dashed];\n"));
+    assert!(dot.contains("    b32 -> b52 [label=\"taken\"];\n"));
+    assert!(dot.contains("    b24 -> b32;\n"));
+}
@@ -0,0 +1,65 @@
+use std::process::Command;
+
+fn runner() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+}
+
+#[test]
+fn run_prints_program_output() {
+    let output = runner().args(["run", "examples/hello_world.bin"]).output().unwrap();
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(&b"Hello, world!\n"[..], &output.stdout[..]);
+}
+
+#[test]
+fn dump_regs_uses_initial_registers() {
+    let output = runner()
+        .args(["dump-regs", "tests/fact.bin", "--reg", "r10=5"])
+        .output()
+        .unwrap();
+    assert_eq!(Some(0), output.status.code());
+    let regs = String::from_utf8(output.stdout).unwrap();
+    assert!(regs.contains("r11 = 0x00000078"));
+}
+
+#[test]
+fn exit_codes() {
+    // Usage error
+    let status = runner().arg("run").output().unwrap().status;
+    assert_eq!(Some(2), status.code());
+
+    // Usage error: more network nodes than ports
+    let files = vec!["tests/fact.bin"; 1025];
+    let status = runner().arg("network").args(&files).output().unwrap().status;
+    assert_eq!(Some(2), status.code());
+
+    // I/O error
+    let status = runner().args(["run", "tests/does_not_exist.bin"]).output().unwrap().status;
+    assert_eq!(Some(3), status.code());
+
+    // Program fault: execution starts outside of the memory
+    let status = runner()
+        .args(["run", "tests/multiply.bin", "--reg", "r0=0xffff"])
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(Some(1), status.code());
+}
+
+#[test]
+fn status_register_and_overflow_trap() {
+    let image = std::env::temp_dir().join("tp-rust-2-flags.bin");
+    // 0: sub r1 <- r1 - r2
+    // 4: out_number r15
+    // 6: exit
+    std::fs::write(&image, [5, 1, 1, 2, 8, 15, 7]).unwrap();
+    let image = image.to_str().unwrap();
+    let output = runner().args(["run", image, "-r", "r2=1", "--status-reg", "r15"]).output().unwrap();
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(&b"6"[..], &output.stdout[..]);
+    let output = runner().args(["run", image, "-r", "r1=0x80000000", "-r", "r2=1", "--trap-overflow"]).output().unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("signed arithmetic overflow"));
+    let output = runner().args(["run", image, "--status-reg", "r0"]).output().unwrap();
+    assert_eq!(Some(2), output.status.code());
+}
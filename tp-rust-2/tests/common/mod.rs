@@ -0,0 +1,8 @@
+//! Helpers shared by the integration tests.
+
+use interpreter::{Assembly, Item};
+
+/// Assemble a program made of `items`, which may also be plain instructions.
+pub fn image<T: Clone + Into<Item>>(items: &[T]) -> Vec<u8> {
+    Assembly { items: items.iter().cloned().map(Into::into).collect() }.assemble().unwrap()
+}
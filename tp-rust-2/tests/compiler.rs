@@ -0,0 +1,80 @@
+use interpreter::compiler::{compile, compile_to_image};
+use interpreter::Machine;
+
+fn run(source: &str) -> Vec<u8> {
+    let mut machine = Machine::new(&compile_to_image(source).unwrap());
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn compile_factorial() {
+    let out = run(include_str!("../examples/factorial.src"));
+    assert_eq!(&include_bytes!("../examples/factorial.expected")[..], &out[..]);
+}
+
+#[test]
+fn compile_fibonacci() {
+    let out = run(include_str!("../examples/fibonacci.src"));
+    assert_eq!(&include_bytes!("../examples/fibonacci.expected")[..], &out[..]);
+}
+
+#[test]
+fn arithmetic_and_comparisons() {
+    let source = r#"
+        fn main() {
+            var a = 3;
+            var b = -5;
+            print a - b, " ", a + b, " ", -(a + 1), " ";
+            print a == 3, a != 3, b == a, b != a;
+        }
+    "#;
+    assert_eq!(&b"8 -2 -4 1001"[..], &run(source)[..]);
+}
+
+#[test]
+fn nested_calls_and_branches() {
+    let source = r#"
+        fn sign(x) {
+            if x == 0 {
+                return 0;
+            }
+            var n = x;
+            var p = x;
+            while 1 {
+                if n == 0 { return 1; }
+                if p == 0 { return -1; }
+                n = n - 1;
+                p = p + 1;
+            }
+        }
+        fn add3(a, b, c) { return a + b + c; }
+        fn main() {
+            print sign(-7), sign(0), sign(12), add3(sign(5), add3(1, 2, 3), 10);
+        }
+    "#;
+    assert_eq!(&b"-10117"[..], &run(source)[..]);
+}
+
+#[test]
+fn listing_uses_listing_conventions() {
+    let listing = compile("fn main() { print \"Hi\"; }").unwrap().listing();
+    assert!(listing.starts_with("  0000   loadimm r2 <- #4096\n"));
+    assert!(listing.contains("return_from_main_1:\n"));
+    assert!(listing.contains("  loadimm r10 <- #str_1\n"));
+    assert!(listing.ends_with("str_1:\n  ???? b'Hi'\n"));
+}
+
+#[test]
+fn compile_errors() {
+    let error = |source| compile(source).unwrap_err().to_string();
+    assert_eq!("function `main` is not defined", error("fn f() {}"));
+    assert_eq!("line 2: variable `x` is not declared", error("fn main() {\n x = 1;\n}"));
+    assert_eq!(
+        "line 1: function `f` expects 1 arguments but 2 were given",
+        error("fn f(a) {} fn main() { f(1, 2); }")
+    );
+    assert_eq!("line 3: expected `;`, found `}`", error("fn main() {\n var x = 1\n}"));
+    assert_eq!("line 1: number literal is larger than 32767", error("fn main() { print 40000; }"));
+}
@@ -0,0 +1,69 @@
+use interpreter::coverage::{Coverage, Summary};
+use interpreter::Machine;
+
+const LISTING: &str = include_str!("multiply.dis");
+
+/// Run the multiplication with the same cases as `test_mult`, and return
+/// the coverage of all the runs.
+fn test_mult_coverage(rights: &[i32]) -> Coverage {
+    let mut total = Coverage::new();
+    for left in &[10i32, -5, 15, -23, 0] {
+        for right in rights {
+            let mut machine = Machine::new(include_bytes!("multiply.bin"));
+            machine.set_coverage(true);
+            machine.set_reg(11, *left as u32).unwrap();
+            machine.set_reg(12, *right as u32).unwrap();
+            machine.run_on(&mut Vec::new()).unwrap();
+            assert_eq!(*left * *right, machine.regs()[11] as i32);
+            total.merge(machine.coverage().unwrap());
+        }
+    }
+    total
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut machine = Machine::new(include_bytes!("multiply.bin"));
+    assert!(machine.coverage().is_none());
+    machine.set_coverage(true);
+    machine.set_reg(12, 1).unwrap();
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(1, machine.coverage().unwrap().hits(0));
+    machine.set_coverage(false);
+    assert!(machine.coverage().is_none());
+}
+
+#[test]
+fn aggregated_runs() {
+    let coverage = test_mult_coverage(&[1, 2, 3, 50]);
+    // Every case runs the prologue once
+    assert_eq!(20, coverage.hits(0));
+    // The loop body runs `right - 1` times for each case
+    assert_eq!(5 * (1 + 2 + 49), coverage.hits(52));
+    assert_eq!(0, coverage.hits(1));
+    assert_eq!(Summary { instructions: 23, executed: 23 }, coverage.summary(LISTING));
+    assert_eq!("100.0% of 23 instructions executed", coverage.summary(LISTING).to_string());
+}
+
+#[test]
+fn annotated_listing() {
+    // Multiplying by one never runs the loop body
+    let coverage = test_mult_coverage(&[1]);
+    let annotated = coverage.annotate(LISTING);
+    let lines: Vec<&str> = annotated.lines().collect();
+    assert_eq!(LISTING.lines().count(), lines.len());
+    assert_eq!("        5:  0000   loadimm r2 <- #4096", lines[0]);
+    assert_eq!("        -:mult_loop:", lines[11]);
+    assert_eq!("    #####:  0052   sub r11 <- r11 - r13", lines[18]);
+    assert_eq!(Summary { instructions: 23, executed: 19 }, coverage.summary(LISTING));
+}
+
+#[test]
+fn lcov_tracefile() {
+    let lcov = test_mult_coverage(&[1]).lcov("tests/multiply.dis", LISTING);
+    let lines: Vec<&str> = lcov.lines().collect();
+    assert_eq!(["TN:", "SF:tests/multiply.dis", "DA:1,5"], lines[..3]);
+    assert!(lines.contains(&"DA:19,0"));
+    assert!(!lines.iter().any(|line| line.starts_with("DA:12,")));
+    assert_eq!(["LF:23", "LH:19", "end_of_record"], lines[lines.len() - 3..]);
+}
@@ -0,0 +1,87 @@
+use interpreter::{Machine, MachineError, FLAG_CARRY, FLAG_NEGATIVE, FLAG_OVERFLOW, FLAG_ZERO};
+
+/// Execute `sub r1 <- r1 - r2` with flags written into r15.
+fn flags(a: u32, b: u32) -> u32 {
+    let mut machine = Machine::new(&[5, 1, 1, 2]);
+    machine.set_status_register(Some(15)).unwrap();
+    machine.set_reg(1, a).unwrap();
+    machine.set_reg(2, b).unwrap();
+    machine.set_reg(15, 0xdead).unwrap();
+    assert!(!machine.step_on(&mut Vec::new()).unwrap());
+    assert_eq!(a.wrapping_sub(b), machine.regs()[1]);
+    machine.regs()[15]
+}
+
+#[test]
+fn flags_are_disabled_by_default() {
+    let mut machine = Machine::new(&[5, 1, 1, 2]);
+    assert_eq!(None, machine.status_register());
+    assert!(!machine.trap_on_overflow());
+    machine.set_reg(2, 1).unwrap();
+    machine.step_on(&mut Vec::new()).unwrap();
+    assert_eq!(&[4, 0xffff_ffff, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], machine.regs());
+}
+
+#[test]
+fn subtraction_flags() {
+    assert_eq!(0, flags(5, 3));
+    assert_eq!(FLAG_ZERO, flags(7, 7));
+    assert_eq!(FLAG_NEGATIVE | FLAG_CARRY, flags(1, 2));
+    // Signed: -1 - 1, unsigned: 0xffffffff - 1
+    assert_eq!(FLAG_NEGATIVE, flags(0xffff_ffff, 1));
+    assert_eq!(FLAG_OVERFLOW, flags(0x8000_0000, 1));
+    assert_eq!(FLAG_NEGATIVE | FLAG_CARRY | FLAG_OVERFLOW, flags(0x7fff_ffff, 0xffff_ffff));
+    // Same operands as `sub_with_wraparound_neg`
+    assert_eq!(FLAG_OVERFLOW, flags(2147878597, 34080773));
+}
+
+#[test]
+fn programs_read_the_flags() {
+    // 0: sub r1 <- r1 - r2
+    // 4: out_number r15
+    // 6: exit
+    let mut machine = Machine::new(&[5, 1, 1, 2, 8, 15, 7]);
+    machine.set_status_register(Some(15)).unwrap();
+    machine.set_reg(2, 1).unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(b"6", &out[..]);
+}
+
+#[test]
+fn invalid_status_register() {
+    let mut machine = Machine::new(&[]);
+    assert_eq!(Err(MachineError::BadRegisterName), machine.set_status_register(Some(0)));
+    assert_eq!(Err(MachineError::BadRegisterName), machine.set_status_register(Some(16)));
+    assert_eq!(None, machine.status_register());
+    machine.set_status_register(Some(3)).unwrap();
+    machine.set_status_register(None).unwrap();
+    assert_eq!(None, machine.status_register());
+}
+
+#[test]
+fn trap_on_signed_overflow() {
+    let mut machine = Machine::new(&[5, 1, 1, 2, 5, 1, 1, 2]);
+    machine.set_trap_on_overflow(true);
+    machine.set_reg(1, 0x8000_0001).unwrap();
+    machine.set_reg(2, 1).unwrap();
+    // Reaching the smallest signed number does not overflow
+    assert_eq!(Ok(false), machine.step_on(&mut Vec::new()));
+    assert_eq!(0x8000_0000, machine.regs()[1]);
+    assert_eq!(Err(MachineError::SignedOverflow), machine.step_on(&mut Vec::new()));
+    assert_eq!(0x8000_0000, machine.regs()[1]);
+    assert_eq!(8, machine.regs()[0]);
+    assert_eq!("signed arithmetic overflow", MachineError::SignedOverflow.to_string());
+}
+
+#[test]
+fn step_back_restores_the_flags() {
+    let mut machine = Machine::new(&[5, 1, 1, 2]);
+    machine.set_history_size(10);
+    machine.set_status_register(Some(15)).unwrap();
+    machine.set_reg(15, 42).unwrap();
+    machine.step_on(&mut Vec::new()).unwrap();
+    assert_eq!(FLAG_ZERO, machine.regs()[15]);
+    assert!(machine.step_back());
+    assert_eq!(42, machine.regs()[15]);
+}
@@ -0,0 +1,203 @@
+use interpreter::gdb::GdbServer;
+use interpreter::Machine;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::{self, JoinHandle};
+
+/// Minimal debugger side of the remote serial protocol.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send_raw(&mut self, packet: &str) {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Read a reply packet and check its checksum.
+    fn reply(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                b => data.push(b),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        self.send_raw(packet);
+        assert_eq!(b'+', self.read_byte());
+        self.reply()
+    }
+
+    fn reg(&mut self, reg: usize) -> u32 {
+        let reply = self.request(&format!("p{:x}", reg));
+        u32::from_str_radix(&reply, 16).unwrap().swap_bytes()
+    }
+}
+
+/// Start a server debugging `image`, and connect to it. The server thread
+/// returns the machine and its output at the end of the session.
+fn connect(image: &[u8], regs: &[(usize, u32)]) -> (Client, JoinHandle<(Machine, Vec<u8>)>) {
+    let mut machine = Machine::new(image);
+    for &(reg, value) in regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    machine.set_history_size(1000);
+    let server = GdbServer::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut out = Vec::new();
+        server.serve(&mut machine, &mut out).unwrap();
+        (machine, out)
+    });
+    (Client { stream: TcpStream::connect(addr).unwrap() }, handle)
+}
+
+#[test]
+fn registers_and_memory() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[(10, 5)]);
+    assert!(client.request("qSupported:multiprocess+").contains("PacketSize="));
+    assert_eq!("S05", client.request("?"));
+    let regs = client.request("g");
+    assert_eq!(16 * 8, regs.len());
+    assert_eq!("05000000", &regs[10 * 8..11 * 8]);
+    assert_eq!("OK", client.request("P3=78563412"));
+    assert_eq!(0x1234_5678, client.reg(3));
+    assert_eq!("E01", client.request("p10"));
+    // fact.bin starts with `loadimm r2 <- #4096`
+    assert_eq!("0402", client.request("m0,2"));
+    assert_eq!("OK", client.request("M100,3:616263"));
+    assert_eq!("61626300", client.request("m100,4"));
+    assert_eq!("E01", client.request("mffe,4"));
+    assert_eq!("", client.request("vMustReplyEmpty"));
+    assert_eq!("OK", client.request("D"));
+    let (machine, _) = handle.join().unwrap();
+    assert_eq!(&b"abc"[..], &machine.memory()[0x100..0x103]);
+    assert_eq!(0x1234_5678, machine.regs()[3]);
+}
+
+#[test]
+fn step_breakpoints_and_continue() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[(10, 4)]);
+    assert_eq!("S05", client.request("s"));
+    assert_eq!(4, client.reg(0));
+    assert_eq!(4096, client.reg(2));
+    // Stop at each execution of the multiplication loop
+    assert_eq!("OK", client.request("Z0,34,1"));
+    assert_eq!("S05", client.request("c"));
+    assert_eq!(0x34, client.reg(0));
+    let first = client.reg(14);
+    // Continuing from a breakpoint executes the instruction under it
+    assert_eq!("S05", client.request("c"));
+    assert_eq!(0x34, client.reg(0));
+    assert_ne!(first, client.reg(14));
+    assert_eq!("OK", client.request("z0,34,1"));
+    assert_eq!("W00", client.request("c"));
+    assert_eq!(24, client.reg(11));
+    client.send_raw("k");
+    handle.join().unwrap();
+}
+
+#[test]
+fn reverse_execution() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[(10, 4)]);
+    assert_eq!("W00", client.request("c"));
+    assert_eq!("OK", client.request("Z0,34,1"));
+    assert_eq!("S05", client.request("bc"));
+    assert_eq!(0x34, client.reg(0));
+    assert_eq!("S05", client.request("bs"));
+    assert_ne!(0x34, client.reg(0));
+    assert_eq!("OK", client.request("z0,34,1"));
+    assert_eq!("T05replaylog:begin;", client.request("bc"));
+    assert_eq!(0, client.reg(0));
+    // The program can run again from there
+    assert_eq!("W00", client.request("c"));
+    assert_eq!(24, client.reg(11));
+    client.send_raw("k");
+    handle.join().unwrap();
+}
+
+#[test]
+fn memory_writes_clear_the_history() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[(10, 4)]);
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("OK", client.request("M800,2:abcd"));
+    // The steps before the write cannot be undone anymore
+    assert_eq!("T05replaylog:begin;", client.request("bs"));
+    assert_eq!("abcd", client.request("m800,2"));
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("S05", client.request("bs"));
+    assert_eq!("abcd", client.request("m800,2"));
+    client.send_raw("k");
+    handle.join().unwrap();
+}
+
+#[test]
+fn register_writes_clear_the_history() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[(10, 4)]);
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("OK", client.request("P5=2a000000"));
+    assert_eq!("T05replaylog:begin;", client.request("bs"));
+    assert_eq!(42, client.reg(5));
+    // Resuming at another address is a register write as well
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("S05", client.request("s0"));
+    assert_eq!(4, client.reg(0));
+    assert_eq!("S05", client.request("bs"));
+    assert_eq!(0, client.reg(0));
+    assert_eq!("T05replaylog:begin;", client.request("bs"));
+    client.send_raw("k");
+    handle.join().unwrap();
+}
+
+#[test]
+fn output_and_faults() {
+    // 0: loadimm r1 <- #65
+    // 4: out r1
+    // 6: loadimm r1 <- #-1
+    // 10: load r1 <- [r1]
+    let image = [4, 1, 65, 0, 6, 1, 4, 1, 0xff, 0xff, 3, 1, 1];
+    let (mut client, handle) = connect(&image, &[]);
+    assert_eq!("S0b", client.request("c"));
+    assert_eq!(10, client.reg(0));
+    client.send_raw("k");
+    let (_, out) = handle.join().unwrap();
+    assert_eq!(b"A", &out[..]);
+}
+
+#[test]
+fn no_ack_mode_and_bad_checksums() {
+    let (mut client, handle) = connect(include_bytes!("fact.bin"), &[]);
+    client.stream.write_all(b"$g#00").unwrap();
+    assert_eq!(b'-', client.read_byte());
+    assert_eq!("OK", client.request("QStartNoAckMode"));
+    client.send_raw("qAttached");
+    assert_eq!("1", client.reply());
+    let target = {
+        client.send_raw("qXfer:features:read:target.xml:0,20");
+        client.reply()
+    };
+    assert!(target.starts_with('m'));
+    client.send_raw("qXfer:features:read:target.xml:0,1000");
+    let target = client.reply();
+    assert!(target.starts_with('l') && target.contains("<reg name=\"r15\""));
+    client.send_raw("k");
+    handle.join().unwrap();
+}
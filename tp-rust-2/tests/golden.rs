@@ -0,0 +1,117 @@
+//! Golden-output tests: every `.bin` program found in `examples/` is run and
+//! its output is compared with the `.expected` file next to it. The programs of
+//! `tests/` are left out, as they print nothing and expect their inputs in
+//! registers (see complex_execution.rs).
+//!
+//! Run `cargo test --test golden -- --bless` to regenerate the expectations.
+
+use interpreter::{Halt, Machine};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Programs which need more steps than this are considered as never ending.
+const MAX_STEPS: usize = 10_000_000;
+
+/// Run the program and return what it printed. If it does not terminate with
+/// an exit instruction, the reason is appended on a line starting with `***`.
+fn run_program(image: &[u8]) -> Vec<u8> {
+    let mut machine = Machine::new(image);
+    let mut out = Vec::new();
+    let stop = match machine.run_until(MAX_STEPS, &mut out).0 {
+        Halt::Exit => None,
+        Halt::StepLimit => Some(String::from("step limit reached")),
+        Halt::Fault(e, ip) => Some(format!("{} (ip = {})", e, ip)),
+    };
+    if let Some(reason) = stop {
+        if !out.is_empty() && !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        out.extend(format!("*** {}\n", reason).bytes());
+    }
+    out
+}
+
+/// List the `.bin` programs of the given directories, sorted by name.
+fn discover(dirs: &[&str]) -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut programs = Vec::new();
+    for dir in dirs {
+        for entry in fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                programs.push(path);
+            }
+        }
+    }
+    programs.sort();
+    programs
+}
+
+/// Describe the first line on which `actual` and `expected` differ.
+fn first_difference(actual: &[u8], expected: &[u8]) -> String {
+    let actual = String::from_utf8_lossy(actual);
+    let expected = String::from_utf8_lossy(expected);
+    let mut actual_lines = actual.lines();
+    let mut expected_lines = expected.lines();
+    for line in 1.. {
+        match (actual_lines.next(), expected_lines.next()) {
+            (Some(a), Some(e)) if a == e => continue,
+            (a, e) => {
+                return format!(
+                    "line {}:\n    expected: {:?}\n    actual:   {:?}",
+                    line,
+                    e.unwrap_or("<end of output>"),
+                    a.unwrap_or("<end of output>")
+                )
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|a| a == "--bless");
+    let filters: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut failures = 0;
+    let mut count = 0;
+    for program in discover(&["examples"]) {
+        let name = program.strip_prefix(root).unwrap().display().to_string();
+        if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+            continue;
+        }
+        count += 1;
+        let actual = run_program(&fs::read(&program).unwrap());
+        let expected_path = program.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            println!("test {} ... blessed", name);
+            continue;
+        }
+        match fs::read(&expected_path) {
+            Ok(expected) if expected == actual => println!("test {} ... ok", name),
+            Ok(expected) => {
+                println!("test {} ... FAILED", name);
+                println!("  {}", first_difference(&actual, &expected));
+                failures += 1;
+            }
+            Err(_) => {
+                println!("test {} ... FAILED", name);
+                println!("  missing {}, run with --bless to create it", expected_path.display());
+                failures += 1;
+            }
+        }
+    }
+
+    println!();
+    if failures == 0 {
+        println!("test result: ok. {} passed; 0 failed", count);
+        ExitCode::SUCCESS
+    } else {
+        println!("test result: FAILED. {} passed; {} failed", count - failures, failures);
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,83 @@
+use interpreter::{Machine, IP};
+
+fn snapshot(machine: &Machine) -> (Vec<u32>, Vec<u8>) {
+    (machine.regs().to_vec(), machine.memory().to_vec())
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut machine = Machine::new(include_bytes!("rfact.bin"));
+    machine.set_reg(10, 5).unwrap();
+    machine.step_on(&mut Vec::new()).unwrap();
+    assert_eq!(0, machine.history_size());
+    assert_eq!(0, machine.history_len());
+    assert!(!machine.step_back());
+}
+
+#[test]
+fn step_back_restores_registers_and_memory() {
+    let mut machine = Machine::new(include_bytes!("rfact.bin"));
+    machine.set_reg(10, 5).unwrap();
+    machine.set_history_size(1000);
+    let mut states = vec![snapshot(&machine)];
+    let mut out = Vec::new();
+    for _ in 0..200 {
+        assert!(!machine.step_on(&mut out).unwrap());
+        states.push(snapshot(&machine));
+    }
+    assert_eq!(200, machine.history_len());
+    while let Some(state) = states.pop() {
+        assert_eq!(state, snapshot(&machine));
+        assert_eq!(!states.is_empty(), machine.step_back());
+    }
+    // Executing again gives the same result
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(120, machine.regs()[11]);
+}
+
+#[test]
+fn run_back_to_address() {
+    // Go back to the last execution of the multiplication loop
+    let mut machine = Machine::new(include_bytes!("fact.bin"));
+    machine.set_reg(10, 4).unwrap();
+    machine.set_history_size(10_000);
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(24, machine.regs()[11]);
+    assert!(machine.run_back_to(52));
+    assert_eq!(52, machine.regs()[IP]);
+    assert_eq!(2, machine.regs()[14]);
+    assert!(!machine.run_back_to(10_000));
+    assert_eq!(0, machine.history_len());
+    assert_eq!(0, machine.regs()[IP]);
+    assert_eq!(4, machine.regs()[10]);
+}
+
+#[test]
+fn bounded_history() {
+    let mut machine = Machine::new(include_bytes!("fact.bin"));
+    machine.set_reg(10, 5).unwrap();
+    machine.set_history_size(10);
+    let mut out = Vec::new();
+    let mut states = Vec::new();
+    for _ in 0..50 {
+        states.push(snapshot(&machine));
+        machine.step_on(&mut out).unwrap();
+    }
+    assert_eq!(10, machine.history_len());
+    for _ in 0..10 {
+        assert!(machine.step_back());
+    }
+    assert!(!machine.step_back());
+    assert_eq!(states[40], snapshot(&machine));
+    // Shrinking the history forgets the oldest steps
+    for _ in 0..10 {
+        machine.step_on(&mut out).unwrap();
+    }
+    machine.set_history_size(3);
+    assert_eq!(3, machine.history_len());
+    for _ in 0..3 {
+        assert!(machine.step_back());
+    }
+    assert!(!machine.step_back());
+    assert_eq!(states[47], snapshot(&machine));
+}
@@ -0,0 +1,61 @@
+use interpreter::inspect::{hexdump, strings, Diff};
+use interpreter::Machine;
+
+#[test]
+fn hexdump_ranges() {
+    let machine = Machine::new(include_bytes!("../examples/hello_world.bin"));
+    assert_eq!(
+        concat!(
+            "0090  03 03 00 03 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64  |....Hello, world|\n",
+            "00a0  21 0a 00 00                                      |!...|\n",
+        ),
+        hexdump(machine.memory(), 0x90..0xa4)
+    );
+    assert_eq!("0ffe  00 00                                            |..|\n", hexdump(machine.memory(), 4094..4096));
+    assert_eq!("", hexdump(machine.memory(), 10..10));
+}
+
+#[test]
+fn hello_world_strings() {
+    let image = include_bytes!("../examples/hello_world.bin");
+    assert_eq!(vec![(0x94, String::from("Hello, world!"))], strings(image, 6));
+    // Short runs of printable bytes are found in the code as well
+    assert!(strings(image, 1).len() > 1);
+    assert_eq!(vec![(0, String::from("ab")), (3, String::from("cd"))], strings(b"ab\0cd", 2));
+}
+
+#[test]
+fn diff_after_a_step() {
+    // 0: store [r1] <- r2
+    let mut machine = Machine::new(&[2, 1, 2]);
+    machine.set_reg(1, 0x21).unwrap();
+    machine.set_reg(2, 0x6948).unwrap();
+    let before = machine.clone();
+    assert!(Diff::between(&before, &machine).is_empty());
+    machine.step_on(&mut Vec::new()).unwrap();
+    let diff = Diff::between(&before, &machine);
+    assert_eq!(vec![(0, 0, 3)], diff.regs);
+    assert_eq!(vec![(0x21, 0, 0x48), (0x22, 0, 0x69)], diff.memory);
+    assert_eq!(
+        concat!(
+            "r0   0x00000000 -> 0x00000003\n",
+            "- 0020  .. 00 00 .. .. .. .. .. .. .. .. .. .. .. .. ..  | ..             |\n",
+            "+ 0020  .. 48 69 .. .. .. .. .. .. .. .. .. .. .. .. ..  | Hi             |\n",
+        ),
+        diff.to_string()
+    );
+}
+
+#[test]
+fn diff_of_programs() {
+    // Both versions of the factorial compute the same result
+    let run = |image: &[u8]| {
+        let mut machine = Machine::new(image);
+        machine.set_reg(10, 5).unwrap();
+        machine.run_on(&mut Vec::new()).unwrap();
+        machine
+    };
+    let diff = Diff::between(&run(include_bytes!("fact.bin")), &run(include_bytes!("rfact.bin")));
+    assert!(!diff.regs.iter().any(|&(reg, _, _)| reg == 11), "{}", diff);
+    assert!(!diff.memory.is_empty());
+}
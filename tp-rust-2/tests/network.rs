@@ -0,0 +1,137 @@
+use interpreter::network::{port, Network, NetworkError, Policy, Status, Wait};
+use interpreter::{Instruction, Machine, MachineError};
+
+mod common;
+use common::image;
+
+fn loadimm(dest: usize, value: i16) -> Instruction {
+    Instruction::LoadImm { dest, value }
+}
+
+/// Load the port of `node` into r1.
+fn port_of(node: usize) -> Instruction {
+    loadimm(1, port(node) as i32 as i16)
+}
+
+/// Send 10, 20 and 30 to node 1.
+fn sender() -> Machine {
+    let mut instrs = vec![port_of(1)];
+    for value in [10, 20, 30] {
+        instrs.push(loadimm(2, value));
+        instrs.push(Instruction::Store { addr: 1, src: 2 });
+    }
+    instrs.push(Instruction::Exit);
+    Machine::new(&image(&instrs))
+}
+
+/// Receive three numbers from node 0 and print them.
+fn receiver() -> Machine {
+    let mut instrs = vec![port_of(0)];
+    for _ in 0..3 {
+        instrs.push(Instruction::Load { dest: 2, addr: 1 });
+        instrs.push(Instruction::OutNumber { reg: 2 });
+    }
+    instrs.push(Instruction::Exit);
+    Machine::new(&image(&instrs))
+}
+
+/// Receive a word from `node`.
+fn recv_from(node: usize) -> Machine {
+    Machine::new(&image(&[port_of(node), Instruction::Load { dest: 2, addr: 1 }, Instruction::Exit]))
+}
+
+#[test]
+fn ports_are_loadable_immediates() {
+    assert_eq!(0xFFFF_F000, port(0));
+    assert_eq!(port(1), (-4092i32) as u32);
+}
+
+#[test]
+fn messages_arrive_in_order_whatever_the_interleaving() {
+    let policies = [
+        Policy::RoundRobin { quantum: 1 },
+        Policy::RoundRobin { quantum: 100 },
+        Policy::Random { seed: 0 },
+        Policy::Random { seed: 42 },
+    ];
+    for policy in policies {
+        for capacity in [1, 3] {
+            let mut network = Network::new(vec![sender(), receiver()], capacity, policy);
+            let steps = network.run(1000).unwrap();
+            let nodes = network.nodes();
+            assert_eq!(b"102030", &nodes[1].output[..], "{:?}", policy);
+            assert!(nodes.iter().all(|node| node.status == Status::Exited));
+            assert_eq!(steps, nodes[0].steps + nodes[1].steps);
+            assert_eq!(8, nodes[0].steps);
+        }
+    }
+}
+
+#[test]
+fn bounded_channels() {
+    // The sender blocks on its second message until the receiver gets the first one
+    let mut network = Network::new(vec![sender(), receiver()], 1, Policy::RoundRobin { quantum: 100 });
+    network.step().unwrap();
+    network.step().unwrap();
+    network.step().unwrap();
+    assert_eq!(&[10], network.pending(0, 1).iter().copied().collect::<Vec<_>>().as_slice());
+    network.step().unwrap();
+    network.step().unwrap();
+    assert_eq!(4, network.nodes()[0].steps);
+    assert_eq!(1, network.nodes()[1].steps);
+    network.step().unwrap();
+    assert!(network.pending(0, 1).is_empty());
+    assert_eq!(10, network.nodes()[1].machine.regs()[2]);
+}
+
+#[test]
+fn deadlock() {
+    let mut network = Network::new(vec![recv_from(1), recv_from(0)], 1, Policy::RoundRobin { quantum: 1 });
+    let error = network.run(1000).unwrap_err();
+    assert_eq!(NetworkError::Deadlock(vec![(0, Wait::Recv { from: 1 }), (1, Wait::Recv { from: 0 })]), error);
+    assert_eq!("deadlock: node 0 is receiving from node 1, node 1 is receiving from node 0", error.to_string());
+    // A node waiting for a terminated node is blocked forever
+    let exit = Machine::new(&[7]);
+    let mut network = Network::new(vec![exit, recv_from(0)], 1, Policy::Random { seed: 7 });
+    assert_eq!(Err(NetworkError::Deadlock(vec![(1, Wait::Recv { from: 0 })])), network.run(1000));
+    assert_eq!(Status::Exited, network.nodes()[0].status);
+}
+
+#[test]
+fn faults_stop_a_single_node() {
+    let bad_port = Machine::new(&image(&[
+        loadimm(1, port(5) as i32 as i16),
+        Instruction::Store { addr: 1, src: 1 },
+    ]));
+    let mut network = Network::new(vec![bad_port, sender(), receiver()], 1, Policy::RoundRobin { quantum: 2 });
+    // Node 1 sends to itself, the receiver waits for node 0
+    let error = network.run(1000).unwrap_err();
+    assert_eq!(Status::Faulted(MachineError::OutOfMemory(port(5))), network.nodes()[0].status);
+    assert_eq!(NetworkError::Deadlock(vec![(1, Wait::Send { to: 1 }), (2, Wait::Recv { from: 0 })]), error);
+}
+
+#[test]
+fn random_interleaving_is_reproducible() {
+    let trace = |seed| {
+        let mut network = Network::new(vec![sender(), receiver()], 1, Policy::Random { seed });
+        let mut trace = Vec::new();
+        while !network.step().unwrap() {
+            trace.push(network.nodes()[0].steps);
+        }
+        trace
+    };
+    assert_eq!(trace(1234), trace(1234));
+    assert!((0..10).any(|seed| trace(seed) != trace(1234)));
+    // Seeds which only differ by their lowest bit give different schedules
+    assert!((0..10).all(|k| trace(2 * k) != trace(2 * k + 1)));
+}
+
+#[test]
+fn step_limit() {
+    // 0: loadimm r0 <- #0
+    let spin = Machine::new(&image(&[loadimm(0, 0)]));
+    let mut network = Network::new(vec![spin, Machine::new(&[7])], 1, Policy::RoundRobin { quantum: 3 });
+    assert_eq!(Err(NetworkError::StepLimit), network.run(100));
+    assert_eq!(99, network.nodes()[0].steps);
+    assert_eq!(Status::Exited, network.nodes()[1].status);
+}
@@ -0,0 +1,259 @@
+use interpreter::optimizer::{optimize, verify, Mismatch, Outcome};
+use interpreter::{Instruction, Item};
+
+mod common;
+use common::image;
+
+const MAX_STEPS: usize = 10_000_000;
+
+fn instructions(image: &[u8]) -> Vec<Instruction> {
+    interpreter::disassemble(image)
+        .into_iter()
+        .map_while(|(_, instr)| instr)
+        .take_while(|instr| *instr != Instruction::Exit)
+        .collect()
+}
+
+fn loadimm(dest: usize, value: i16) -> Item {
+    Item::Instr(Instruction::LoadImm { dest, value })
+}
+
+fn sub(dest: usize, op1: usize, op2: usize) -> Item {
+    Item::Instr(Instruction::Sub { dest, op1, op2 })
+}
+
+#[test]
+fn examples_are_preserved_and_faster() {
+    for name in ["99bottles", "count", "factorial", "fibonacci", "hello_world"] {
+        let original = std::fs::read(format!("examples/{}.bin", name)).unwrap();
+        let optimized = optimize(&original);
+        assert!(optimized.removed > 0, "{}", name);
+        let (before, after) = verify(&original, &optimized, &[], MAX_STEPS).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(Outcome::Exit, after.outcome);
+        assert!(after.steps < before.steps, "{}", name);
+    }
+}
+
+#[test]
+fn test_programs_are_preserved() {
+    let fact = [1u32, 5, 12].map(|n| vec![(10, n)]);
+    let mult = [(10i32, 3i32), (-23, 50), (0, 2)].map(|(a, b)| vec![(11, a as u32), (12, b as u32)]);
+    let cases = [
+        ("fact", &fact[..]),
+        ("afact", &fact[..]),
+        ("rfact", &fact[..]),
+        ("rfact_tr", &fact[..]),
+        ("multiply", &mult[..]),
+        ("fibo", &[vec![]]),
+        ("function", &[vec![]]),
+        ("push_pop", &[vec![]]),
+    ];
+    for (name, regs) in cases {
+        let original = std::fs::read(format!("tests/{}.bin", name)).unwrap();
+        let optimized = optimize(&original);
+        for regs in regs {
+            verify(&original, &optimized, regs, MAX_STEPS).unwrap_or_else(|e| panic!("{} {:?}: {}", name, regs, e));
+        }
+    }
+}
+
+#[test]
+fn redundant_loadimm_is_removed() {
+    let original = image(&[
+        loadimm(3, 4),
+        sub(2, 2, 3),
+        loadimm(3, 4),
+        sub(1, 1, 3),
+        Item::Instr(Instruction::Exit),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(1, optimized.removed);
+    assert_eq!(
+        vec![
+            Instruction::LoadImm { dest: 3, value: 4 },
+            Instruction::Sub { dest: 2, op1: 2, op2: 3 },
+            Instruction::Sub { dest: 1, op1: 1, op2: 3 },
+        ],
+        instructions(&optimized.image)
+    );
+    verify(&original, &optimized, &[(1, 10), (2, 20)], MAX_STEPS).unwrap();
+}
+
+#[test]
+fn constant_sub_is_folded() {
+    let original = image(&[
+        loadimm(1, 100),
+        loadimm(3, 4),
+        sub(1, 1, 3),
+        sub(1, 1, 3),
+        Item::Instr(Instruction::OutNumber { reg: 1 }),
+        Item::Instr(Instruction::Exit),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(
+        vec![
+            Instruction::LoadImm { dest: 3, value: 4 },
+            Instruction::LoadImm { dest: 1, value: 92 },
+            Instruction::OutNumber { reg: 1 },
+        ],
+        instructions(&optimized.image)
+    );
+    let (_, after) = verify(&original, &optimized, &[], MAX_STEPS).unwrap();
+    assert_eq!(b"92", &after.output[..]);
+}
+
+#[test]
+fn sub_chain_is_folded() {
+    // Pop immediately followed by a push
+    let original = image(&[
+        loadimm(3, -4),
+        sub(2, 2, 3),
+        loadimm(3, 4),
+        sub(2, 2, 3),
+        Item::Instr(Instruction::Store { addr: 2, src: 1 }),
+        Item::Instr(Instruction::Exit),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(
+        vec![Instruction::LoadImm { dest: 3, value: 4 }, Instruction::Store { addr: 2, src: 1 }],
+        instructions(&optimized.image)
+    );
+    verify(&original, &optimized, &[(1, 7), (2, 4000)], MAX_STEPS).unwrap();
+}
+
+#[test]
+fn dead_store_is_removed() {
+    let original = image(&[
+        loadimm(5, 1),
+        loadimm(6, 2),
+        Item::Instr(Instruction::MoveIf { dest: 5, src: 6, cond: 0 }),
+        Item::Instr(Instruction::Exit),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(
+        vec![Instruction::LoadImm { dest: 6, value: 2 }, Instruction::LoadImm { dest: 5, value: 2 }],
+        instructions(&optimized.image)
+    );
+    verify(&original, &optimized, &[], MAX_STEPS).unwrap();
+}
+
+#[test]
+fn jump_targets_and_data_are_patched() {
+    let original = image(&[
+        loadimm(3, 4),
+        loadimm(3, 4),
+        loadimm(10, 0), // overwritten below
+        Item::LoadLabel { dest: 10, label: String::from("str") },
+        Item::LoadLabel { dest: 0, label: String::from("print") },
+        Item::Label(String::from("print")),
+        Item::Instr(Instruction::Load { dest: 1, addr: 10 }),
+        Item::Instr(Instruction::Out { reg: 1 }),
+        Item::Instr(Instruction::Exit),
+        Item::Label(String::from("str")),
+        Item::Data(b"!".to_vec()),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(2, optimized.removed);
+    // Data stays at its address, the jump goes to the new location of `print`
+    assert_eq!(original.len(), optimized.image.len());
+    assert_eq!(original.last(), optimized.image.last());
+    assert_eq!(
+        Instruction::LoadImm { dest: 0, value: 12 },
+        instructions(&optimized.image)[2]
+    );
+    let (_, after) = verify(&original, &optimized, &[], MAX_STEPS).unwrap();
+    assert_eq!(b"!", &after.output[..]);
+}
+
+#[test]
+fn computed_jumps_are_left_alone() {
+    let original = image(&[
+        loadimm(3, 4),
+        loadimm(3, 4),
+        loadimm(4, 16),
+        sub(0, 4, 3),
+        Item::Instr(Instruction::Exit),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(0, optimized.removed);
+    assert_eq!(original, optimized.image);
+}
+
+#[test]
+fn verifier_detects_differences() {
+    let original = std::fs::read("examples/hello_world.bin").unwrap();
+    let mut optimized = optimize(&original);
+    let len = optimized.image.len();
+    optimized.image[len - 2] = b'?';
+    assert!(matches!(verify(&original, &optimized, &[], MAX_STEPS), Err(Mismatch::Output { .. })));
+
+    // Fault instead of exiting after printing
+    let mut optimized = optimize(&original);
+    let (exit, _) = interpreter::disassemble(&optimized.image)
+        .into_iter()
+        .find(|&(_, instr)| instr == Some(Instruction::Exit))
+        .unwrap();
+    optimized.image[exit] = 0;
+    assert!(matches!(
+        verify(&original, &optimized, &[], MAX_STEPS),
+        Err(Mismatch::Outcome { original: Outcome::Exit, optimized: Outcome::Fault(_) })
+    ));
+}
+
+#[test]
+fn stored_code_addresses_are_left_alone() {
+    // The address of `func` is stored in memory and used as a function pointer,
+    // which the optimizer cannot relocate.
+    let original = image(&[
+        loadimm(3, 4),
+        loadimm(3, 4),
+        loadimm(1, 33),
+        Item::LoadLabel { dest: 2, label: String::from("pointer") },
+        Item::LoadLabel { dest: 5, label: String::from("func") },
+        Item::Instr(Instruction::Store { addr: 2, src: 5 }),
+        Item::Label(String::from("func")),
+        Item::Instr(Instruction::Out { reg: 1 }),
+        Item::LoadLabel { dest: 8, label: String::from("done") },
+        Item::Instr(Instruction::MoveIf { dest: 0, src: 8, cond: 6 }),
+        loadimm(6, 1),
+        Item::Instr(Instruction::Load { dest: 0, addr: 2 }),
+        Item::Label(String::from("done")),
+        Item::Instr(Instruction::Exit),
+        Item::Label(String::from("pointer")),
+        Item::Data(vec![0; 4]),
+    ]);
+    let optimized = optimize(&original);
+    assert_eq!(0, optimized.removed);
+    assert_eq!(original, optimized.image);
+    let (_, after) = verify(&original, &optimized, &[], MAX_STEPS).unwrap();
+    assert_eq!(b"!!", &after.output[..]);
+
+    // The same goes for code addresses found in the data, such as jump tables
+    let original = image(&[
+        loadimm(3, 4),
+        loadimm(3, 4),
+        loadimm(1, 33),
+        Item::LoadLabel { dest: 2, label: String::from("table") },
+        Item::Label(String::from("func")),
+        Item::Instr(Instruction::Out { reg: 1 }),
+        Item::LoadLabel { dest: 8, label: String::from("done") },
+        Item::Instr(Instruction::MoveIf { dest: 0, src: 8, cond: 6 }),
+        loadimm(6, 1),
+        Item::Instr(Instruction::Load { dest: 0, addr: 2 }),
+        Item::Label(String::from("done")),
+        Item::Instr(Instruction::Exit),
+        Item::Label(String::from("table")),
+        Item::Data(vec![0; 4]),
+    ]);
+    let mut original = original;
+    let (func, _) = interpreter::disassemble(&original)
+        .into_iter()
+        .find(|&(_, instr)| instr == Some(Instruction::Out { reg: 1 }))
+        .unwrap();
+    let len = original.len();
+    original[len - 4..].copy_from_slice(&(func as u32).to_le_bytes());
+    let optimized = optimize(&original);
+    assert_eq!(original, optimized.image);
+    let (_, after) = verify(&original, &optimized, &[], MAX_STEPS).unwrap();
+    assert_eq!(b"!!", &after.output[..]);
+}
@@ -0,0 +1,26 @@
+use interpreter::{Machine, MachineError, Output};
+
+#[test]
+fn custom_output() {
+    // Output sink which only accepts a limited number of bytes
+    struct Limited(Vec<u8>, usize);
+    impl Output for Limited {
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), MachineError> {
+            if self.0.len() + bytes.len() > self.1 {
+                return Err(MachineError::OutputFailure);
+            }
+            self.0.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    // 0: out_number r1
+    // 2: out_number r1
+    // 4: exit
+    let mut machine = Machine::new(&[8, 1, 8, 1, 7]);
+    machine.set_reg(1, -42i32 as u32).unwrap();
+    let mut out = Limited(Vec::new(), 3);
+    assert!(!machine.step_on(&mut out).unwrap());
+    assert_eq!(Err(MachineError::OutputFailure), machine.run_on(&mut out));
+    assert_eq!(&b"-42"[..], &out.0[..]);
+}
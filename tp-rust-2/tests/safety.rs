@@ -0,0 +1,97 @@
+use interpreter::safety::{Interval, Report, Verdict};
+use interpreter::{MEMORY_SIZE, NREGS};
+
+fn zero() -> [Interval; NREGS] {
+    [Interval::constant(0); NREGS]
+}
+
+fn with(reg: usize, values: Interval) -> [Interval; NREGS] {
+    let mut regs = zero();
+    regs[reg] = values;
+    regs
+}
+
+fn verdict(image: &[u8], regs: &[Interval; NREGS], addr: usize) -> Verdict {
+    Report::analyze(image, regs).findings[&addr].verdict
+}
+
+#[test]
+fn memory_access_near_end_of_memory() {
+    // 0: store [r1] <- r1
+    // 3: load r1 <- [r1]
+    // 6: exit
+    let image = [2, 1, 1, 3, 1, 1, 7];
+    let last = MEMORY_SIZE as u32 - 4;
+    assert_eq!(Verdict::Safe, verdict(&image, &with(1, Interval::constant(last)), 0));
+    assert_eq!(Verdict::Fails, verdict(&image, &with(1, Interval::constant(last + 2)), 0));
+    assert_eq!(Verdict::Fails, verdict(&image, &with(1, Interval::constant(0xFFFF_FFFF)), 0));
+    assert_eq!(Verdict::MayFail, verdict(&image, &with(1, Interval::TOP), 0));
+    // Once the store succeeded, the address is known to be valid
+    assert_eq!(Verdict::Safe, verdict(&image, &with(1, Interval::TOP), 3));
+}
+
+#[test]
+fn failing_access_stops_the_execution() {
+    // 0: loadimm r1 <- #-1
+    // 4: store [r1] <- r1
+    // 7: out r1
+    // 9: exit
+    let image = [4, 1, 0xff, 0xff, 2, 1, 1, 6, 1, 7];
+    let report = Report::analyze(&image, &zero());
+    assert_eq!(Verdict::Fails, report.findings[&4].verdict);
+    assert!(!report.findings.contains_key(&7));
+}
+
+#[test]
+fn out_of_non_ascii_bytes() {
+    // 0: out r1
+    // 2: exit
+    let image = [6, 1, 7];
+    assert_eq!(Verdict::Safe, verdict(&image, &with(1, Interval::new(b'a' as u32, b'z' as u32)), 0));
+    assert_eq!(Verdict::Fails, verdict(&image, &with(1, Interval::constant(0xe9)), 0));
+    assert_eq!(Verdict::MayFail, verdict(&image, &with(1, Interval::new(100, 200)), 0));
+    // Only the low byte is printed
+    assert_eq!(Verdict::Safe, verdict(&image, &with(1, Interval::constant(0x141)), 0));
+}
+
+#[test]
+fn branch_conditions() {
+    // 0: loadimm r3 <- #11
+    // 4: move r0 <- r3 if r1 != 0
+    // 8: store [r1] <- r1
+    // 11: exit
+    let image = [4, 3, 11, 0, 1, 0, 3, 1, 2, 1, 1, 7];
+    // r1 is 0 when the branch is not taken
+    assert_eq!(Verdict::Safe, verdict(&image, &with(1, Interval::new(0, 5000)), 8));
+}
+
+#[test]
+fn test_programs() {
+    // The stack never overflows, whatever the argument
+    let report = Report::analyze(include_bytes!("fact.bin"), &with(10, Interval::TOP));
+    assert!(report.complete);
+    assert_eq!(Vec::<usize>::new(), report.unsafe_addresses());
+    assert_eq!(4, report.findings.len());
+    // Recursive calls may exhaust the stack
+    let report = Report::analyze(include_bytes!("rfact.bin"), &with(10, Interval::TOP));
+    assert!(report.unsafe_addresses().contains(&119));
+}
+
+#[test]
+fn examples() {
+    // Only the loop of `print` reads memory through a pointer, and prints what it reads
+    let report = Report::analyze(include_bytes!("../examples/99bottles.bin"), &zero());
+    assert_eq!(vec![1177, 1180], report.unsafe_addresses());
+    assert_eq!(
+        "  1180   out r3: may print non-ASCII value [0, 4294967295]\n",
+        report.to_string().lines().find(|l| l.starts_with("  1180")).map(|l| format!("{}\n", l)).unwrap()
+    );
+}
+
+#[test]
+fn incomplete_graph() {
+    // 0: sub r0 <- r1 - r2
+    let report = Report::analyze(&[5, 0, 1, 2], &zero());
+    assert!(!report.complete);
+    assert!(report.to_string().contains("incomplete"));
+}
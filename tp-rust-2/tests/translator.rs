@@ -0,0 +1,114 @@
+use interpreter::translator::translate;
+use interpreter::{Instruction, Machine};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+use common::image;
+
+/// Translate `image` and compile the result into an executable named after `name`.
+fn compile(name: &str, image: &[u8]) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("translator");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(format!("{}.rs", name));
+    let binary = dir.join(name);
+    fs::write(&source, translate(image)).unwrap();
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let status = Command::new(rustc)
+        .args(["--edition", "2021", "-O", "-D", "warnings", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{} does not compile", source.display());
+    binary
+}
+
+fn run(binary: &Path, args: &[&str]) -> Output {
+    Command::new(binary).args(args).output().unwrap()
+}
+
+#[test]
+fn examples() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut programs: Vec<PathBuf> = fs::read_dir(root.join("examples"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty());
+    for program in programs {
+        let image = fs::read(&program).unwrap();
+        let mut expected = Vec::new();
+        Machine::new(&image).run_on(&mut expected).unwrap();
+        let name = program.file_stem().unwrap().to_str().unwrap();
+        let output = run(&compile(name, &image), &[]);
+        assert!(output.status.success(), "{} failed", name);
+        assert_eq!(String::from_utf8_lossy(&expected), String::from_utf8_lossy(&output.stdout), "{}", name);
+    }
+}
+
+#[test]
+fn initial_registers() {
+    let binary = compile("rfact", include_bytes!("rfact.bin"));
+    for n in [0, 1, 5, 7] {
+        let mut machine = Machine::new(include_bytes!("rfact.bin"));
+        machine.set_reg(10, n).unwrap();
+        let mut expected = Vec::new();
+        let result = machine.run_on(&mut expected);
+        let output = run(&binary, &[&format!("r10={}", n)]);
+        // With 0, the recursion never ends and overflows the stack
+        assert_eq!(if result.is_ok() { Some(0) } else { Some(1) }, output.status.code(), "r10={}", n);
+        assert_eq!(expected, output.stdout);
+    }
+    assert_eq!(Some(2), run(&binary, &["r16=1"]).status.code());
+    assert_eq!(Some(2), run(&binary, &["r1=99999999999"]).status.code());
+    assert_eq!(Some(2), run(&binary, &["r1=-2147483649"]).status.code());
+}
+
+#[test]
+fn self_modifying_code() {
+    // 0: loadimm r5 <- #65
+    // 4: loadimm r1 <- #17
+    // 8: loadimm r2 <- #7
+    // 12: store [r1] <- r2      replaces the second `out` by `exit`
+    // 15: out r5
+    // 17: out r5
+    // 19: exit
+    let image = image(&[
+        Instruction::LoadImm { dest: 5, value: 65 },
+        Instruction::LoadImm { dest: 1, value: 17 },
+        Instruction::LoadImm { dest: 2, value: 7 },
+        Instruction::Store { addr: 1, src: 2 },
+        Instruction::Out { reg: 5 },
+        Instruction::Out { reg: 5 },
+        Instruction::Exit,
+    ]);
+    let mut expected = Vec::new();
+    Machine::new(&image).run_on(&mut expected).unwrap();
+    assert_eq!(b"A", &expected[..]);
+    let output = run(&compile("self_modifying", &image), &[]);
+    assert!(output.status.success());
+    assert_eq!(expected, output.stdout);
+}
+
+#[test]
+fn faults() {
+    // 0: loadimm r1 <- #-1
+    // 4: out_number r1
+    // 6: load r1 <- [r1]
+    let image = image(&[
+        Instruction::LoadImm { dest: 1, value: -1 },
+        Instruction::OutNumber { reg: 1 },
+        Instruction::Load { dest: 1, addr: 1 },
+    ]);
+    let output = run(&compile("fault", &image), &[]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(b"-1", &output.stdout[..]);
+    assert_eq!(
+        "error: program fault at address 6: access outside of memory at address 0xffffffff\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "tui")]
+
+use interpreter::tui::{App, State};
+use interpreter::Machine;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::style::Color;
+use ratatui::Terminal;
+
+fn render(app: &App) -> Terminal<TestBackend> {
+    let mut terminal = Terminal::new(TestBackend::new(100, 40)).unwrap();
+    terminal.draw(|frame| app.draw(frame)).unwrap();
+    terminal
+}
+
+fn screen(terminal: &Terminal<TestBackend>) -> Vec<String> {
+    let buffer = terminal.backend().buffer();
+    (0..buffer.area.height)
+        .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect())
+        .collect()
+}
+
+#[test]
+fn step_and_highlight() {
+    let mut app = App::new(Machine::new(include_bytes!("../examples/hello_world.bin")));
+    let lines = screen(&render(&app));
+    assert!(lines.iter().any(|line| line.contains("> 0000   loadimm r2 <- #4096")));
+    assert!(lines[1].contains("r0  0x00000000"));
+    assert!(lines.last().unwrap().contains("paused"));
+
+    app.handle_key(KeyCode::Char('s'));
+    assert_eq!(vec![0, 2], app.changed());
+    let terminal = render(&app);
+    let lines = screen(&terminal);
+    assert!(lines.iter().any(|line| line.contains("> 0004   loadimm r3 <- #4")));
+    assert!(lines.iter().any(|line| line.contains("  0000   loadimm r2 <- #4096")));
+    // r2 is highlighted, r1 is not
+    let (x, y) = (lines[3].rfind("r2 ").unwrap() as u16, 3);
+    assert!(lines[3].contains("r2  0x00001000"));
+    assert_eq!(Color::Yellow, terminal.backend().buffer()[(x, y)].fg);
+    assert_ne!(Color::Yellow, terminal.backend().buffer()[(x, 2)].fg);
+}
+
+#[test]
+fn run_until_exit() {
+    let mut app = App::new(Machine::new(include_bytes!("../examples/hello_world.bin")));
+    app.handle_key(KeyCode::Char('+'));
+    app.handle_key(KeyCode::Char('+'));
+    assert_eq!(4, app.speed());
+    app.handle_key(KeyCode::Char(' '));
+    assert!(app.running());
+    app.tick();
+    assert_eq!(15, app.machine().regs()[0]);
+    for _ in 0..1000 {
+        app.tick();
+    }
+    assert_eq!(State::Exited, app.state());
+    assert!(!app.running());
+    assert_eq!(b"Hello, world!\n", app.output());
+    let lines = screen(&render(&app));
+    assert!(lines.iter().any(|line| line.contains("Hello, world!") && !line.contains('|')));
+    assert!(lines.last().unwrap().contains("exited"));
+    // Nothing happens once the program has exited
+    app.handle_key(KeyCode::Char(' '));
+    assert!(!app.running());
+    assert!(!app.handle_key(KeyCode::Char('q')));
+}
+
+#[test]
+fn faults_and_memory_scrolling() {
+    // 0: load r1 <- [r1]
+    let mut machine = Machine::new(&[3, 1, 1]);
+    machine.set_reg(1, 5000).unwrap();
+    let mut app = App::new(machine);
+    app.step();
+    assert!(matches!(app.state(), State::Fault(_, 0)));
+    let lines = screen(&render(&app));
+    assert!(lines.last().unwrap().contains("fault at address 0"));
+    assert!(lines.iter().any(|line| line.contains("0000  03 01 01 00")));
+    app.handle_key(KeyCode::PageDown);
+    app.handle_key(KeyCode::Down);
+    let lines = screen(&render(&app));
+    assert!(lines.iter().any(|line| line.contains("0110  00 00")));
+    assert!(!lines.iter().any(|line| line.contains("0000  03 01 01 00")));
+    app.handle_key(KeyCode::Home);
+    let lines = screen(&render(&app));
+    assert!(lines.iter().any(|line| line.contains("0000  03 01 01 00")));
+}